@@ -0,0 +1,367 @@
+use std::future::Future;
+use std::io::{self, Read};
+use std::marker::PhantomData;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use serde::de::DeserializeOwned;
+use serde::ser::Serialize;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::task::{spawn_blocking, JoinError, JoinHandle};
+
+use compression::open_file;
+use de::{from_slice, from_slice_any};
+use error::Error;
+use format::{guess_format, Format};
+use ser::{format_from_path, to_vec, write_file, WriteOptions};
+
+/// Deserialize from an asynchronous reader using a specified format
+///
+/// None of the backends can deserialize incrementally from data that is not available yet,
+/// so the whole input is read into memory first, and then deserialized with [`from_slice`].
+///
+/// # Errors
+///
+/// If reading fails, [`Error::Io`] is returned.
+/// Otherwise, errors are the same as for [`from_slice`].
+///
+/// # Example
+///
+/// ```
+/// # extern crate serde_any;
+/// # extern crate tokio;
+/// use std::collections::HashMap;
+/// use serde_any::Format;
+///
+/// # fn main() -> Result<(), serde_any::Error> {
+/// let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+/// let reader: &[u8] = b"{\"a\": \"alpha\"}";
+/// let m: HashMap<String, String> = runtime.block_on(serde_any::from_async_reader(reader, Format::Json))?;
+/// assert_eq!(m["a"], "alpha");
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`from_slice`]: ../de/fn.from_slice.html
+/// [`Error::Io`]: ../error/enum.Error.html#variant.Io
+pub fn from_async_reader<T, R>(reader: R, format: Format) -> FromAsyncReader<T, R>
+where
+    T: DeserializeOwned,
+    R: AsyncRead + Unpin,
+{
+    FromAsyncReader {
+        reader,
+        format,
+        data: Vec::new(),
+        marker: PhantomData,
+    }
+}
+
+/// Serialize to an asynchronous writer using a specified format
+///
+/// The value is serialized into memory first, and then written and flushed.
+///
+/// # Errors
+///
+/// If serialization fails, the same error as from [`to_vec`] is returned, and nothing is written.
+/// If writing fails, [`Error::Io`] is returned.
+///
+/// [`to_vec`]: ../ser/fn.to_vec.html
+/// [`Error::Io`]: ../error/enum.Error.html#variant.Io
+pub fn to_async_writer<W, T>(writer: W, value: &T, format: Format) -> ToAsyncWriter<W>
+where
+    W: AsyncWrite + Unpin,
+    T: Serialize,
+{
+    let (data, error) = match to_vec(value, format) {
+        Ok(data) => (data, None),
+        Err(error) => (Vec::new(), Some(error)),
+    };
+    ToAsyncWriter {
+        writer,
+        data,
+        written: 0,
+        error,
+    }
+}
+
+/// Deserialize from a file without blocking the asynchronous runtime
+///
+/// This works like [`from_file`]: the format is guessed from the file name, and compressed files
+/// are decompressed. The file is read on the blocking thread pool of the Tokio runtime,
+/// so the returned future has to be polled within a Tokio runtime.
+///
+/// # Errors
+///
+/// Errors are the same as for [`from_file`].
+///
+/// # Example
+///
+/// ```
+/// # extern crate serde_any;
+/// # extern crate tokio;
+/// use std::collections::HashMap;
+///
+/// # fn main() -> Result<(), serde_any::Error> {
+/// let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+/// let mut m = HashMap::new();
+/// m.insert("a".to_string(), "alpha".to_string());
+///
+/// runtime.block_on(serde_any::to_file_async("greek_async.yaml", &m))?;
+/// let m2: HashMap<String, String> = runtime.block_on(serde_any::from_file_async("greek_async.yaml"))?;
+/// assert_eq!(m, m2);
+/// # std::fs::remove_file("greek_async.yaml").unwrap();
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`from_file`]: ../de/fn.from_file.html
+pub fn from_file_async<T, P>(path: P) -> FromFileAsync<T>
+where
+    T: DeserializeOwned,
+    P: AsRef<Path>,
+{
+    FromFileAsync {
+        path: path.as_ref().to_path_buf(),
+        read: None,
+        marker: PhantomData,
+    }
+}
+
+/// Serialize to a file without blocking the asynchronous runtime
+///
+/// This works like [`to_file`]: the format is inferred from the file name, and the file is replaced atomically.
+/// The value is serialized immediately, and the file is written on the blocking thread pool
+/// of the Tokio runtime, so the returned future has to be polled within a Tokio runtime.
+///
+/// # Errors
+///
+/// Errors are the same as for [`to_file`].
+///
+/// [`to_file`]: ../ser/fn.to_file.html
+pub fn to_file_async<T, P>(path: P, value: &T) -> ToFileAsync
+where
+    T: Serialize,
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    ToFileAsync {
+        path: path.to_path_buf(),
+        data: Some(format_from_path(path).and_then(|format| to_vec(value, format))),
+        write: None,
+    }
+}
+
+/// Future returned by [`from_async_reader`]
+///
+/// [`from_async_reader`]: fn.from_async_reader.html
+#[derive(Debug)]
+pub struct FromAsyncReader<T, R> {
+    reader: R,
+    format: Format,
+    data: Vec<u8>,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T, R> Future for FromAsyncReader<T, R>
+where
+    T: DeserializeOwned,
+    R: AsyncRead + Unpin,
+{
+    type Output = Result<T, Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let mut buffer = [0; 8192];
+        loop {
+            let mut buf = ReadBuf::new(&mut buffer);
+            match Pin::new(&mut this.reader).poll_read(cx, &mut buf) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Err(error)) => return Poll::Ready(Err(error.into())),
+                Poll::Ready(Ok(())) if buf.filled().is_empty() => break,
+                Poll::Ready(Ok(())) => this.data.extend_from_slice(buf.filled()),
+            }
+        }
+        Poll::Ready(from_slice(&this.data, this.format))
+    }
+}
+
+/// Future returned by [`to_async_writer`]
+///
+/// [`to_async_writer`]: fn.to_async_writer.html
+#[derive(Debug)]
+pub struct ToAsyncWriter<W> {
+    writer: W,
+    data: Vec<u8>,
+    written: usize,
+    error: Option<Error>,
+}
+
+impl<W> Future for ToAsyncWriter<W>
+where
+    W: AsyncWrite + Unpin,
+{
+    type Output = Result<(), Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = &mut *self;
+        if let Some(error) = this.error.take() {
+            return Poll::Ready(Err(error));
+        }
+
+        while this.written < this.data.len() {
+            match Pin::new(&mut this.writer).poll_write(cx, &this.data[this.written..]) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Err(error)) => return Poll::Ready(Err(error.into())),
+                Poll::Ready(Ok(0)) => {
+                    let error = io::Error::new(io::ErrorKind::WriteZero, "failed to write whole buffer");
+                    return Poll::Ready(Err(error.into()));
+                }
+                Poll::Ready(Ok(n)) => this.written += n,
+            }
+        }
+        Pin::new(&mut this.writer).poll_flush(cx).map_err(Error::from)
+    }
+}
+
+/// Future returned by [`from_file_async`]
+///
+/// [`from_file_async`]: fn.from_file_async.html
+#[derive(Debug)]
+pub struct FromFileAsync<T> {
+    path: PathBuf,
+    read: Option<JoinHandle<Result<Vec<u8>, Error>>>,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Future for FromFileAsync<T>
+where
+    T: DeserializeOwned,
+{
+    type Output = Result<T, Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = &mut *self;
+        if this.read.is_none() {
+            let path = this.path.clone();
+            this.read = Some(spawn_blocking(move || read_file(&path)));
+        }
+
+        let data = match this.read.as_mut().map(|read| Pin::new(read).poll(cx)) {
+            Some(Poll::Ready(result)) => joined(result),
+            _ => return Poll::Pending,
+        };
+
+        let path = &this.path;
+        let result = data.and_then(|data| match guess_format(path) {
+            Some(format) => from_slice(&data, format),
+            None => from_slice_any(&data),
+        });
+        Poll::Ready(result.map_err(|error| error.in_file(path)))
+    }
+}
+
+/// Future returned by [`to_file_async`]
+///
+/// [`to_file_async`]: fn.to_file_async.html
+#[derive(Debug)]
+pub struct ToFileAsync {
+    path: PathBuf,
+    data: Option<Result<Vec<u8>, Error>>,
+    write: Option<JoinHandle<Result<(), Error>>>,
+}
+
+impl Future for ToFileAsync {
+    type Output = Result<(), Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = &mut *self;
+        match this.data.take() {
+            Some(Ok(data)) => {
+                let path = this.path.clone();
+                this.write = Some(spawn_blocking(move || {
+                    write_file(&path, &data, &WriteOptions::default())
+                }));
+            }
+            Some(Err(error)) => return Poll::Ready(Err(error.in_file(&this.path))),
+            None => {}
+        }
+
+        match this.write.as_mut().map(|write| Pin::new(write).poll(cx)) {
+            Some(Poll::Ready(result)) => Poll::Ready(joined(result).map_err(|error| error.in_file(&this.path))),
+            _ => Poll::Pending,
+        }
+    }
+}
+
+/// Read a whole file on a blocking thread, decompressing it if its name ends with a compression suffix
+fn read_file(path: &Path) -> Result<Vec<u8>, Error> {
+    let mut data = Vec::new();
+    open_file(path)?.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Return the result of a blocking task, resuming its panic if it panicked
+fn joined<T>(result: Result<Result<T, Error>, JoinError>) -> Result<T, Error> {
+    match result {
+        Ok(result) => result,
+        Err(error) => match error.try_into_panic() {
+            Ok(payload) => panic::resume_unwind(payload),
+            Err(error) => Err(io::Error::other(error).into()),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use tokio::runtime::{Builder, Runtime};
+
+    fn runtime() -> Runtime {
+        Builder::new_current_thread().build().unwrap()
+    }
+
+    #[test]
+    fn readers_and_writers() {
+        let runtime = runtime();
+        let mut m = BTreeMap::new();
+        m.insert("a".to_string(), 1);
+        m.insert("b".to_string(), 2);
+
+        for &format in &[Format::Json, Format::Yaml, Format::Toml, Format::Ron] {
+            let mut written = Vec::new();
+            runtime.block_on(to_async_writer(&mut written, &m, format)).unwrap();
+            assert_eq!(written, to_vec(&m, format).unwrap());
+
+            let read: BTreeMap<String, i32> = runtime.block_on(from_async_reader(&written[..], format)).unwrap();
+            assert_eq!(read, m);
+        }
+
+        let error = runtime
+            .block_on(from_async_reader::<BTreeMap<String, i32>, _>(&b"{"[..], Format::Json))
+            .unwrap_err();
+        let expected = from_slice::<BTreeMap<String, i32>>(b"{", Format::Json).unwrap_err();
+        assert_eq!(error.to_string(), expected.to_string());
+    }
+
+    #[test]
+    fn files() {
+        let runtime = runtime();
+        let mut m = BTreeMap::new();
+        m.insert("a".to_string(), 1);
+
+        let path = "async_io_files.json";
+        runtime.block_on(to_file_async(path, &m)).unwrap();
+        let read: BTreeMap<String, i32> = runtime.block_on(from_file_async(path)).unwrap();
+        ::std::fs::remove_file(path).unwrap();
+        assert_eq!(read, m);
+
+        let missing = runtime.block_on(from_file_async::<BTreeMap<String, i32>, _>("async_io_missing.json"));
+        assert_matches!(missing, Err(Error::File { ref error, .. }) if matches!(**error, Error::Io(_)));
+        let unknown = runtime.block_on(to_file_async("async_io_files.unknown", &m));
+        assert_matches!(unknown, Err(Error::File { ref error, .. }) if matches!(**error, Error::UnsupportedFileExtension(_)));
+    }
+}
@@ -7,6 +7,9 @@ pub(crate) use serde_yaml;
 #[cfg(feature = "toml")]
 pub(crate) use toml;
 
+#[cfg(all(feature = "toml", feature = "toml_edit"))]
+pub(crate) use toml_edit;
+
 #[cfg(feature = "ron")]
 pub(crate) use ron;
 
@@ -15,3 +18,6 @@ pub(crate) use serde_xml_any as xml;
 
 #[cfg(feature = "url")]
 pub(crate) use serde_urlencoded as url;
+
+#[cfg(feature = "url")]
+pub(crate) use form_urlencoded;
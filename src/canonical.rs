@@ -0,0 +1,200 @@
+// The JSON Canonicalization Scheme (RFC 8785), used for canonical JSON output.
+//
+// Values are first converted into a `Value`, and then written without whitespace,
+// with map keys sorted by their UTF-16 code units, strings escaped minimally,
+// and floats formatted like `Number.prototype.toString` in ECMAScript.
+// As required by RFC 8785, integers are formatted as doubles, so integers beyond 2^53 are rounded.
+
+use value::Value;
+
+/// Write a value in canonical JSON, returning the reason if it contains a float which JSON cannot represent
+pub(crate) fn write_jcs(value: &Value, output: &mut String) -> Result<(), String> {
+    match value {
+        Value::Null => output.push_str("null"),
+        Value::Bool(b) => output.push_str(if *b { "true" } else { "false" }),
+        Value::Int(i) => output.push_str(&format_number(*i as f64)?),
+        Value::UInt(u) => output.push_str(&format_number(*u as f64)?),
+        Value::Float(f) => output.push_str(&format_number(*f)?),
+        Value::String(s) => write_string(s, output),
+        Value::Seq(elements) => {
+            output.push('[');
+            for (i, element) in elements.iter().enumerate() {
+                if i > 0 {
+                    output.push(',');
+                }
+                write_jcs(element, output)?;
+            }
+            output.push(']');
+        }
+        Value::Map(map) => {
+            let mut entries: Vec<(Vec<u16>, &String, &Value)> = map
+                .iter()
+                .map(|(key, value)| (key.encode_utf16().collect(), key, value))
+                .collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+            output.push('{');
+            for (i, (_, key, value)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    output.push(',');
+                }
+                write_string(key, output);
+                output.push(':');
+                write_jcs(value, output)?;
+            }
+            output.push('}');
+        }
+    }
+    Ok(())
+}
+
+fn write_string(s: &str, output: &mut String) {
+    output.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\u{8}' => output.push_str("\\b"),
+            '\u{c}' => output.push_str("\\f"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            c if c < ' ' => output.push_str(&format!("\\u{:04x}", c as u32)),
+            c => output.push(c),
+        }
+    }
+    output.push('"');
+}
+
+/// Format a float in the shortest form that parses back to the same value, as in ECMAScript
+fn format_number(f: f64) -> Result<String, String> {
+    if !f.is_finite() {
+        return Err(format!("{} cannot be represented in JSON", f));
+    }
+    if f == 0.0 {
+        return Ok("0".to_string());
+    }
+
+    // The number of digits which are needed to round-trip, and then the closest decimal with that many digits,
+    // which is what ECMAScript chooses if several decimals with the fewest digits round-trip
+    let shortest = format!("{:e}", f.abs());
+    let precision = shortest.find('e').expect("scientific notation") - shortest.find('.').map_or(1, |_| 2);
+    let scientific = format!("{:.*e}", precision, f.abs());
+    let (mantissa, exponent) = scientific.split_at(scientific.find('e').expect("scientific notation"));
+    let digits = mantissa.replace('.', "");
+    let exponent: i32 = exponent[1..].parse().expect("valid exponent");
+
+    let k = digits.len() as i32;
+    let n = exponent + 1;
+    let mut output = if f < 0.0 { "-".to_string() } else { String::new() };
+
+    if k <= n && n <= 21 {
+        output.push_str(&digits);
+        output.extend((0..n - k).map(|_| '0'));
+    } else if 0 < n && n <= 21 {
+        output.push_str(&digits[..n as usize]);
+        output.push('.');
+        output.push_str(&digits[n as usize..]);
+    } else if -6 < n && n <= 0 {
+        output.push_str("0.");
+        output.extend((0..-n).map(|_| '0'));
+        output.push_str(&digits);
+    } else {
+        output.push_str(&digits[..1]);
+        if k > 1 {
+            output.push('.');
+            output.push_str(&digits[1..]);
+        }
+        output.push_str(&format!("e{}{}", if n > 0 { "+" } else { "-" }, (n - 1).abs()));
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers() {
+        // Examples from RFC 8785, appendix B
+        let cases: &[(u64, &str)] = &[
+            (0x0000000000000000, "0"),
+            (0x8000000000000000, "0"),
+            (0x0000000000000001, "5e-324"),
+            (0x8000000000000001, "-5e-324"),
+            (0x7fefffffffffffff, "1.7976931348623157e+308"),
+            (0xffefffffffffffff, "-1.7976931348623157e+308"),
+            (0x4340000000000000, "9007199254740992"),
+            (0xc340000000000000, "-9007199254740992"),
+            (0x4430000000000000, "295147905179352830000"),
+            (0x44b52d02c7e14af5, "9.999999999999997e+22"),
+            (0x44b52d02c7e14af6, "1e+23"),
+            (0x44b52d02c7e14af7, "1.0000000000000001e+23"),
+            (0x444b1ae4d6e2ef4e, "999999999999999700000"),
+            (0x444b1ae4d6e2ef4f, "999999999999999900000"),
+            (0x444b1ae4d6e2ef50, "1e+21"),
+            (0x3eb0c6f7a0b5ed8c, "9.999999999999997e-7"),
+            (0x3eb0c6f7a0b5ed8d, "0.000001"),
+            (0x41b3de4355555553, "333333333.3333332"),
+            (0x41b3de4355555554, "333333333.33333325"),
+            (0x41b3de4355555555, "333333333.3333333"),
+            (0x41b3de4355555556, "333333333.3333334"),
+            (0x41b3de4355555557, "333333333.33333343"),
+            (0xbecbf647612f3696, "-0.0000033333333333333333"),
+            (0x43143ff3c1cb0959, "1424953923781206.2"),
+        ];
+
+        for &(bits, expected) in cases {
+            assert_eq!(format_number(f64::from_bits(bits)).unwrap(), expected);
+        }
+        assert!(format_number(f64::NAN).is_err());
+        assert!(format_number(f64::INFINITY).is_err());
+    }
+
+    #[test]
+    fn integers() {
+        let cases = &[
+            (Value::UInt(u64::MAX), "18446744073709552000"),
+            (Value::Int(i64::MIN), "-9223372036854776000"),
+            (Value::UInt(9007199254740993), "9007199254740992"),
+            (Value::Int(-42), "-42"),
+            (Value::UInt(0), "0"),
+        ];
+
+        for (value, expected) in cases {
+            let mut output = String::new();
+            write_jcs(value, &mut output).unwrap();
+            assert_eq!(output, *expected);
+        }
+    }
+
+    #[test]
+    fn sorting_and_escaping() {
+        let mut map = ::value::Map::new();
+        map.insert("\u{20ac}".to_string(), Value::String("Euro Sign".to_string()));
+        map.insert("\r".to_string(), Value::String("Carriage Return".to_string()));
+        map.insert(
+            "\u{fb33}".to_string(),
+            Value::String("Hebrew Letter Dalet With Dagesh".to_string()),
+        );
+        map.insert("1".to_string(), Value::String("One".to_string()));
+        map.insert(
+            "\u{1f600}".to_string(),
+            Value::String("Emoji: Grinning Face".to_string()),
+        );
+        map.insert("\u{80}".to_string(), Value::String("Control\u{7f}\u{1f}".to_string()));
+        map.insert(
+            "\u{f6}".to_string(),
+            Value::String("Latin Small Letter O With Diaeresis".to_string()),
+        );
+
+        let mut output = String::new();
+        write_jcs(&Value::Map(map), &mut output).unwrap();
+        assert_eq!(
+            output,
+            "{\"\\r\":\"Carriage Return\",\"1\":\"One\",\"\u{80}\":\"Control\u{7f}\\u001f\",\
+             \"\u{f6}\":\"Latin Small Letter O With Diaeresis\",\"\u{20ac}\":\"Euro Sign\",\
+             \"\u{1f600}\":\"Emoji: Grinning Face\",\"\u{fb33}\":\"Hebrew Letter Dalet With Dagesh\"}"
+        );
+    }
+}
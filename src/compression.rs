@@ -0,0 +1,289 @@
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs::File;
+#[cfg(any(feature = "gzip", feature = "xz", feature = "bzip2"))]
+use std::io::Write;
+use std::io::{self, Read};
+use std::path::Path;
+
+use encoding::decode;
+use error::Error;
+
+/// Compression formats for files and data
+///
+/// Compressed files are recognized by a suffix after the extension of the serialization format,
+/// such as `data.json.gz`, and compressed data by its magic bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    /// Gzip, with the suffix `.gz`, enabled by the `gzip` feature, implemented using [`flate2`](https://docs.rs/flate2).
+    Gzip,
+    /// Zstandard, with the suffix `.zst`, enabled by the `zstd` feature, implemented using [`zstd`](https://docs.rs/zstd).
+    Zstd,
+    /// XZ, with the suffix `.xz`, enabled by the `xz` feature, implemented using [`xz2`](https://docs.rs/xz2).
+    Xz,
+    /// Bzip2, with the suffix `.bz2`, enabled by the `bzip2` feature, implemented using [`bzip2`](https://docs.rs/bzip2).
+    Bzip2,
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl Compression {
+    /// Checks whether this compression is supported
+    ///
+    /// Support for different compressions is controlled by the features used
+    /// when building `serde_any`. None of them are enabled by default.
+    pub fn is_supported(&self) -> bool {
+        match self {
+            Compression::Gzip => cfg!(feature = "gzip"),
+            Compression::Zstd => cfg!(feature = "zstd"),
+            Compression::Xz => cfg!(feature = "xz"),
+            Compression::Bzip2 => cfg!(feature = "bzip2"),
+        }
+    }
+
+    /// Return the file name suffix used for this compression, without the leading dot
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+            Compression::Zstd => "zst",
+            Compression::Xz => "xz",
+            Compression::Bzip2 => "bz2",
+        }
+    }
+}
+
+/// Return a list of supported compressions
+///
+/// Support for different compressions is controlled by the features used
+/// when building `serde_any`.
+pub fn supported_compressions() -> Vec<Compression> {
+    vec![
+        #[cfg(feature = "gzip")]
+        Compression::Gzip,
+        #[cfg(feature = "zstd")]
+        Compression::Zstd,
+        #[cfg(feature = "xz")]
+        Compression::Xz,
+        #[cfg(feature = "bzip2")]
+        Compression::Bzip2,
+    ]
+}
+
+/// Attempt to guess the compression from a file name
+///
+/// This function may recognize and return a compression even if it's not supported due to feature flags.
+pub fn guess_compression<P>(path: P) -> Option<Compression>
+where
+    P: AsRef<Path>,
+{
+    path.as_ref()
+        .extension()
+        .and_then(OsStr::to_str)
+        .and_then(guess_compression_from_extension)
+}
+
+/// Attempt to guess the compression from a file extension
+///
+/// This function may recognize and return a compression even if it's not supported due to feature flags.
+pub fn guess_compression_from_extension(ext: &str) -> Option<Compression> {
+    match ext {
+        "gz" => Some(Compression::Gzip),
+        "zst" => Some(Compression::Zstd),
+        "xz" => Some(Compression::Xz),
+        "bz2" => Some(Compression::Bzip2),
+        _ => None,
+    }
+}
+
+/// Attempt to detect the compression of data from its magic bytes
+///
+/// This function may recognize and return a compression even if it's not supported due to feature flags.
+///
+/// # Example
+///
+/// ```
+/// use serde_any::{detect_compression, Compression};
+///
+/// assert_eq!(detect_compression(b"\x1f\x8b\x08\x00"), Some(Compression::Gzip));
+/// assert_eq!(detect_compression(b"{\"a\": 1}"), None);
+/// ```
+pub fn detect_compression(data: &[u8]) -> Option<Compression> {
+    if data.starts_with(&[0x1f, 0x8b]) {
+        Some(Compression::Gzip)
+    } else if data.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        Some(Compression::Zstd)
+    } else if data.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+        Some(Compression::Xz)
+    } else if is_bzip2(data) {
+        Some(Compression::Bzip2)
+    } else {
+        None
+    }
+}
+
+/// Check for the bzip2 header followed by the magic of a block or of the end of the stream,
+/// since the header alone is printable text
+fn is_bzip2(data: &[u8]) -> bool {
+    const BLOCK: &[u8] = &[0x31, 0x41, 0x59, 0x26, 0x53, 0x59];
+    const END: &[u8] = &[0x17, 0x72, 0x45, 0x38, 0x50, 0x90];
+
+    data.len() >= 10
+        && data.starts_with(b"BZh")
+        && (b'1'..=b'9').contains(&data[3])
+        && (&data[4..10] == BLOCK || &data[4..10] == END)
+}
+
+/// Wrap a reader so that it returns the decompressed data
+#[cfg_attr(
+    not(any(feature = "gzip", feature = "zstd", feature = "xz", feature = "bzip2")),
+    allow(unused_variables)
+)]
+pub(crate) fn decoder<'a, R>(compression: Compression, reader: R) -> Result<Box<dyn Read + 'a>, Error>
+where
+    R: Read + 'a,
+{
+    match compression {
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => Ok(Box::new(::flate2::read::MultiGzDecoder::new(reader))),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => Ok(Box::new(::zstd::stream::read::Decoder::new(reader)?)),
+        #[cfg(feature = "xz")]
+        Compression::Xz => Ok(Box::new(::xz2::read::XzDecoder::new_multi_decoder(reader))),
+        #[cfg(feature = "bzip2")]
+        Compression::Bzip2 => Ok(Box::new(::bzip2::read::MultiBzDecoder::new(reader))),
+        #[allow(unreachable_patterns)]
+        compression => Err(Error::UnsupportedCompression(compression)),
+    }
+}
+
+/// Compress data with the default level of the compression
+#[cfg_attr(
+    not(any(feature = "gzip", feature = "zstd", feature = "xz", feature = "bzip2")),
+    allow(unused_variables)
+)]
+pub(crate) fn compress(compression: Compression, data: &[u8]) -> Result<Vec<u8>, Error> {
+    match compression {
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => {
+            let mut encoder = ::flate2::write::GzEncoder::new(Vec::new(), ::flate2::Compression::default());
+            encoder.write_all(data)?;
+            Ok(encoder.finish()?)
+        }
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => Ok(::zstd::stream::encode_all(data, 0)?),
+        #[cfg(feature = "xz")]
+        Compression::Xz => {
+            let mut encoder = ::xz2::write::XzEncoder::new(Vec::new(), 6);
+            encoder.write_all(data)?;
+            Ok(encoder.finish()?)
+        }
+        #[cfg(feature = "bzip2")]
+        Compression::Bzip2 => {
+            let mut encoder = ::bzip2::write::BzEncoder::new(Vec::new(), ::bzip2::Compression::default());
+            encoder.write_all(data)?;
+            Ok(encoder.finish()?)
+        }
+        #[allow(unreachable_patterns)]
+        compression => Err(Error::UnsupportedCompression(compression)),
+    }
+}
+
+/// Open a file for reading, decompressing it if its name ends with a compression suffix
+pub(crate) fn open_file(path: &Path) -> Result<Box<dyn Read>, Error> {
+    let file = File::open(path)?;
+    match guess_compression(path) {
+        Some(compression) => decoder(compression, file),
+        None => Ok(Box::new(file)),
+    }
+}
+
+/// Read a whole file into a string, decompressing it if its name ends with a compression suffix
+///
+/// The encoding of the file is detected, and a byte order mark is skipped.
+pub(crate) fn read_to_string(path: &Path) -> Result<String, Error> {
+    let mut data = Vec::new();
+    open_file(path)?.read_to_end(&mut data)?;
+    String::from_utf8(decode(&data, None)?.into_owned())
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error).into())
+}
+
+/// Compress data for a file if its name ends with a compression suffix
+pub(crate) fn compress_for_path(path: &Path, data: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+    guess_compression(path)
+        .map(|compression| compress(compression, data))
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extensions() {
+        for compression in &[
+            Compression::Gzip,
+            Compression::Zstd,
+            Compression::Xz,
+            Compression::Bzip2,
+        ] {
+            let path = Path::new("data.json").with_extension(format!("json.{}", compression.extension()));
+            assert_eq!(guess_compression(&path), Some(*compression));
+        }
+        assert_eq!(guess_compression("data.json"), None);
+    }
+
+    #[test]
+    fn round_trip() {
+        let data = b"{\"name\": \"serde_any\"}";
+        for compression in supported_compressions() {
+            let compressed = compress(compression, data).unwrap();
+            assert_eq!(detect_compression(&compressed), Some(compression));
+
+            let mut decompressed = Vec::new();
+            decoder(compression, &compressed[..])
+                .unwrap()
+                .read_to_end(&mut decompressed)
+                .unwrap();
+            assert_eq!(&decompressed[..], &data[..]);
+        }
+    }
+
+    #[test]
+    fn files() {
+        let mut value = ::value::Map::new();
+        value.insert("name".to_string(), ::value::Value::String("serde_any".to_string()));
+
+        for compression in supported_compressions() {
+            let stem = format!("compressed_{}", compression.extension());
+            let path = format!("{}.yaml.{}", stem, compression.extension());
+            ::ser::to_file(&path, &value).unwrap();
+
+            let raw = ::std::fs::read(&path).unwrap();
+            assert_eq!(detect_compression(&raw), Some(compression));
+            assert_eq!(::de::from_slice_any::<::value::Map>(&raw).unwrap(), value);
+            assert_eq!(::de::from_file::<::value::Map, _>(&path).unwrap(), value);
+            assert_eq!(::de::from_file_stem::<::value::Map, _>(&stem).unwrap(), value);
+
+            ::std::fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn unsupported() {
+        for compression in &[
+            Compression::Gzip,
+            Compression::Zstd,
+            Compression::Xz,
+            Compression::Bzip2,
+        ] {
+            if !compression.is_supported() {
+                assert_matches!(compress(*compression, b""), Err(Error::UnsupportedCompression(_)));
+            }
+        }
+        assert_eq!(detect_compression(b"BZh9 is not bzip2"), None);
+    }
+}
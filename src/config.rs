@@ -0,0 +1,691 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+
+use de::{from_file, from_str};
+use error::Error;
+use format::Format;
+use search::FileSearch;
+use value::{from_value_coerced_at, Map, Value};
+
+/// How sequences are combined when several sources set the same sequence
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ArrayMerge {
+    /// The sequence from the later source replaces the earlier one
+    #[default]
+    Replace,
+    /// Elements from the later source are appended to the earlier sequence
+    Append,
+    /// Elements at the same index are merged, and additional elements are appended
+    ByIndex,
+}
+
+#[derive(Clone, Debug)]
+enum Source {
+    File { path: PathBuf, required: bool },
+    Stem { stem: PathBuf, required: bool },
+    Str { name: String, data: String, format: Format },
+    Search { search: FileSearch, required: bool },
+    /// Environment variables, read from the process environment unless `vars` is given
+    Env {
+        prefix: String,
+        separator: String,
+        vars: Option<Vec<(String, String)>>,
+    },
+}
+
+/// Builder for configuration loaded from several layered sources
+///
+/// Sources are added in order of increasing priority. Each source is deserialized
+/// independently, in its own format, and deep-merged over the sources before it:
+/// maps are merged key by key, while other values from later sources replace earlier ones.
+/// How sequences are combined is set with [`array_merge`].
+///
+/// The merged data is then deserialized into the target type with [`build`],
+/// or with [`build_layered`] to also find out which source each value came from.
+///
+/// # Example
+///
+/// ```
+/// #[macro_use]
+/// extern crate serde;
+/// extern crate serde_any;
+///
+/// use serde_any::{ConfigBuilder, Format};
+///
+/// #[derive(Deserialize, Debug)]
+/// struct Server {
+///     host: String,
+///     port: u16,
+/// }
+///
+/// #[derive(Deserialize, Debug)]
+/// struct Config {
+///     server: Server,
+/// }
+///
+/// fn main() -> Result<(), serde_any::Error> {
+///     let config = ConfigBuilder::new()
+///         .string("defaults", "[server]\nhost = \"localhost\"\nport = 80\n", Format::Toml)
+///         .optional_file("/etc/app/config.yaml")
+///         .string("overrides", r#"{"server": {"port": 8080}}"#, Format::Json)
+///         .build_layered::<Config>()?;
+///
+///     assert_eq!(config.value.server.host, "localhost");
+///     assert_eq!(config.value.server.port, 8080);
+///     assert_eq!(config.source_of("server.host"), Some("defaults"));
+///     assert_eq!(config.source_of("server.port"), Some("overrides"));
+///     Ok(())
+/// }
+/// ```
+///
+/// [`array_merge`]: struct.ConfigBuilder.html#method.array_merge
+/// [`build`]: struct.ConfigBuilder.html#method.build
+/// [`build_layered`]: struct.ConfigBuilder.html#method.build_layered
+#[derive(Clone, Debug, Default)]
+pub struct ConfigBuilder {
+    sources: Vec<Source>,
+    array_merge: ArrayMerge,
+}
+
+impl ConfigBuilder {
+    /// Create a builder without any sources
+    pub fn new() -> ConfigBuilder {
+        ConfigBuilder::default()
+    }
+
+    /// Add a file, which must exist
+    ///
+    /// The format is detected in the same way as with [`from_file`].
+    ///
+    /// [`from_file`]: ../de/fn.from_file.html
+    pub fn file<P: AsRef<Path>>(mut self, path: P) -> ConfigBuilder {
+        self.sources.push(Source::File {
+            path: path.as_ref().to_path_buf(),
+            required: true,
+        });
+        self
+    }
+
+    /// Add a file, which is skipped if it does not exist
+    pub fn optional_file<P: AsRef<Path>>(mut self, path: P) -> ConfigBuilder {
+        self.sources.push(Source::File {
+            path: path.as_ref().to_path_buf(),
+            required: false,
+        });
+        self
+    }
+
+    /// Add the first existing file with the given stem and any supported extension
+    ///
+    /// The files are tried in the same order as with [`from_file_stem`].
+    ///
+    /// [`from_file_stem`]: ../de/fn.from_file_stem.html
+    pub fn file_stem<P: AsRef<Path>>(mut self, stem: P) -> ConfigBuilder {
+        self.sources.push(Source::Stem {
+            stem: stem.as_ref().to_path_buf(),
+            required: true,
+        });
+        self
+    }
+
+    /// Add the first existing file with the given stem, which is skipped if no such file exists
+    pub fn optional_file_stem<P: AsRef<Path>>(mut self, stem: P) -> ConfigBuilder {
+        self.sources.push(Source::Stem {
+            stem: stem.as_ref().to_path_buf(),
+            required: false,
+        });
+        self
+    }
+
+    /// Add every file found by a [`FileSearch`]
+    ///
+    /// Files in directories with a higher priority override those with a lower priority,
+    /// so that for example a file in the current directory overrides one in `/etc`.
+    ///
+    /// [`FileSearch`]: ../search/struct.FileSearch.html
+    pub fn search(mut self, search: FileSearch) -> ConfigBuilder {
+        self.sources.push(Source::Search { search, required: true });
+        self
+    }
+
+    /// Add every file found by a [`FileSearch`], and skip it if none is found
+    ///
+    /// [`FileSearch`]: ../search/struct.FileSearch.html
+    pub fn optional_search(mut self, search: FileSearch) -> ConfigBuilder {
+        self.sources.push(Source::Search {
+            search,
+            required: false,
+        });
+        self
+    }
+
+    /// Add data from a string in the given format
+    ///
+    /// The `name` identifies this source in [`Layered::source_of`].
+    ///
+    /// [`Layered::source_of`]: struct.Layered.html#method.source_of
+    pub fn string<N: Into<String>, S: Into<String>>(mut self, name: N, data: S, format: Format) -> ConfigBuilder {
+        self.sources.push(Source::Str {
+            name: name.into(),
+            data: data.into(),
+            format,
+        });
+        self
+    }
+
+    /// Add environment variables starting with `prefix` followed by an underscore
+    ///
+    /// The rest of the variable name is split at double underscores into a path of lowercase keys,
+    /// so that with the prefix `APP`, the variable `APP_DATABASE__PORT` sets the field `database.port`.
+    /// Every variable is a separate source, named after the variable in [`Layered::source_of`].
+    ///
+    /// Since environment variables only contain strings, their values are converted to the type of the target field:
+    /// numbers and booleans are parsed, and comma separated lists are split into sequences.
+    /// Strings from other sources are not converted.
+    ///
+    /// [`Layered::source_of`]: struct.Layered.html#method.source_of
+    pub fn env<P: Into<String>>(self, prefix: P) -> ConfigBuilder {
+        self.env_with_separator(prefix, "__")
+    }
+
+    /// Add environment variables starting with `prefix` followed by an underscore, with a custom separator
+    ///
+    /// This is the same as [`env`], but nested keys are separated by `separator` instead of a double underscore.
+    ///
+    /// [`env`]: struct.ConfigBuilder.html#method.env
+    pub fn env_with_separator<P: Into<String>, S: Into<String>>(mut self, prefix: P, separator: S) -> ConfigBuilder {
+        self.sources.push(Source::Env {
+            prefix: prefix.into(),
+            separator: separator.into(),
+            vars: None,
+        });
+        self
+    }
+
+    /// Add the given variables as if they were the environment, without reading or changing the process environment
+    #[cfg(test)]
+    fn env_vars(mut self, prefix: &str, vars: &[(&str, &str)]) -> ConfigBuilder {
+        self.sources.push(Source::Env {
+            prefix: prefix.to_string(),
+            separator: "__".to_string(),
+            vars: Some(vars.iter().map(|&(name, data)| (name.to_string(), data.to_string())).collect()),
+        });
+        self
+    }
+
+    /// Set how sequences from different sources are combined
+    ///
+    /// By default, a sequence from a later source replaces the earlier one.
+    pub fn array_merge(mut self, strategy: ArrayMerge) -> ConfigBuilder {
+        self.array_merge = strategy;
+        self
+    }
+
+    /// Load and merge all sources, and deserialize the result
+    ///
+    /// # Errors
+    ///
+    /// If a source fails to load, the error from [`from_file`], [`from_file_stem`] or [`from_str`] is returned.
+    /// If a search does not find any file, [`Error::NoFileFound`] is returned.
+    /// Missing optional sources are skipped.
+    ///
+    /// Strings from environment variables are converted to numbers, booleans and sequences where `T` expects them,
+    /// which allows them to be used for any field. Values from files and strings are not converted,
+    /// so they have to match `T` in the same way as with [`from_file`].
+    /// If the merged data still does not match `T`, [`Error::Value`] is returned,
+    /// wrapped in [`Error::Field`] with the path to the offending value.
+    ///
+    /// [`from_file`]: ../de/fn.from_file.html
+    /// [`from_file_stem`]: ../de/fn.from_file_stem.html
+    /// [`from_str`]: ../de/fn.from_str.html
+    /// [`Error::NoFileFound`]: ../error/enum.Error.html#variant.NoFileFound
+    /// [`Error::Value`]: ../error/enum.Error.html#variant.Value
+    /// [`Error::Field`]: ../error/enum.Error.html#variant.Field
+    pub fn build<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        self.build_layered().map(|layered| layered.value)
+    }
+
+    /// Load and merge all sources, deserialize the result, and record where each value came from
+    ///
+    /// # Errors
+    ///
+    /// Errors are the same as for [`build`].
+    ///
+    /// [`build`]: struct.ConfigBuilder.html#method.build
+    pub fn build_layered<T: DeserializeOwned>(&self) -> Result<Layered<T>, Error> {
+        let mut merged = Value::Null;
+        let mut names = Vec::new();
+        let mut from_env = Vec::new();
+        let mut origins = BTreeMap::new();
+
+        for source in &self.sources {
+            for (name, value) in load(source)? {
+                merge(&mut merged, value, self.array_merge, "", names.len(), &mut origins);
+                names.push(name);
+                from_env.push(matches!(source, Source::Env { .. }));
+            }
+        }
+
+        // Only values from environment variables are strings regardless of their type
+        let coerced: BTreeSet<String> = origins
+            .iter()
+            .filter(|(_, &index)| from_env[index])
+            .map(|(path, _)| path.clone())
+            .collect();
+
+        Ok(Layered {
+            value: from_value_coerced_at(merged, coerced)?,
+            sources: names,
+            origins,
+        })
+    }
+}
+
+/// Configuration deserialized from layered sources, together with the origin of each value
+#[derive(Clone, Debug)]
+pub struct Layered<T> {
+    /// The deserialized configuration
+    pub value: T,
+    sources: Vec<String>,
+    origins: BTreeMap<String, usize>,
+}
+
+impl<T> Layered<T> {
+    /// Return the name of the source which set the value at `path`
+    ///
+    /// Paths are written in the same way as in [`Error::Field`], for example `servers[0].port`.
+    /// Files are named by their path, and strings by the name they were added with.
+    /// Only paths to scalars, empty maps and empty sequences are recorded,
+    /// since the contents of other maps and sequences can come from several sources.
+    ///
+    /// [`Error::Field`]: ../error/enum.Error.html#variant.Field
+    pub fn source_of(&self, path: &str) -> Option<&str> {
+        self.origins.get(path).map(|&index| self.sources[index].as_str())
+    }
+
+    /// Return the names of all sources that were loaded, in order of increasing priority
+    ///
+    /// Missing optional sources are not included.
+    pub fn sources(&self) -> &[String] {
+        &self.sources
+    }
+
+    /// Return the path of every value together with the name of the source that set it, sorted by path
+    pub fn origins(&self) -> Vec<(&str, &str)> {
+        self.origins
+            .iter()
+            .map(|(path, &index)| (path.as_str(), self.sources[index].as_str()))
+            .collect()
+    }
+}
+
+/// Load a single source, returning no layers if it is optional and missing
+fn load(source: &Source) -> Result<Vec<(String, Value)>, Error> {
+    match source {
+        Source::File { path, required } => match from_file(path) {
+            Ok(value) => Ok(vec![(path.display().to_string(), value)]),
+            Err(ref error) if !required && is_not_found(error) => Ok(Vec::new()),
+            Err(error) => Err(error),
+        },
+        Source::Stem { stem, required } => match FileSearch::new(stem).find() {
+            Ok(path) => Ok(vec![(path.display().to_string(), from_file(&path)?)]),
+            Err(Error::NoFileFound { .. }) if !required => Ok(Vec::new()),
+            Err(error) => Err(error),
+        },
+        Source::Search { search, required } => {
+            let paths = search.find_all_checked()?;
+            if paths.is_empty() && *required {
+                search.find()?;
+            }
+            paths
+                .into_iter()
+                .rev()
+                .map(|path| Ok((path.display().to_string(), from_file(&path)?)))
+                .collect()
+        }
+        Source::Str { name, data, format } => Ok(vec![(name.clone(), from_str(data, *format)?)]),
+        Source::Env {
+            prefix,
+            separator,
+            vars: Some(vars),
+        } => Ok(env_layers(vars.clone(), prefix, separator)),
+        Source::Env { prefix, separator, .. } => Ok(env_layers(env::vars(), prefix, separator)),
+    }
+}
+
+/// Check whether a file failed to load because it does not exist
+///
+/// Other I/O errors, such as missing permissions, are not treated as a missing file.
+fn is_not_found(error: &Error) -> bool {
+    match error.root() {
+        Error::Io(error) => error.kind() == io::ErrorKind::NotFound,
+        _ => false,
+    }
+}
+
+/// Turn every variable starting with `prefix` into a layer with a single nested value
+///
+/// Variables are sorted by name, so that the result does not depend on the order of the environment.
+fn env_layers<I>(vars: I, prefix: &str, separator: &str) -> Vec<(String, Value)>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    let prefix = format!("{}_", prefix);
+    let mut vars: Vec<(String, String)> = vars.into_iter().filter(|(name, _)| name.starts_with(&prefix)).collect();
+    vars.sort();
+
+    vars.into_iter()
+        .filter_map(|(name, data)| {
+            let keys: Vec<String> = name[prefix.len()..]
+                .split(separator)
+                .map(|key| key.to_lowercase())
+                .collect();
+            if keys.iter().any(String::is_empty) {
+                return None;
+            }
+            let value = keys.into_iter().rev().fold(Value::String(data), |value, key| {
+                let mut map = Map::new();
+                map.insert(key, value);
+                Value::Map(map)
+            });
+            Some((name, value))
+        })
+        .collect()
+}
+
+fn key_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+fn index_path(path: &str, index: usize) -> String {
+    format!("{}[{}]", path, index)
+}
+
+/// Merge `overlay` into `base`, recording the source of every value taken from `overlay`
+fn merge(
+    base: &mut Value,
+    overlay: Value,
+    strategy: ArrayMerge,
+    path: &str,
+    source: usize,
+    origins: &mut BTreeMap<String, usize>,
+) {
+    match (base, overlay) {
+        (Value::Map(base), Value::Map(overlay)) => {
+            for (key, value) in overlay {
+                let path = key_path(path, &key);
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value, strategy, &path, source, origins),
+                    None => {
+                        record(&value, &path, source, origins);
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (Value::Seq(base), Value::Seq(overlay)) if strategy != ArrayMerge::Replace => {
+            for (index, value) in overlay.into_iter().enumerate() {
+                let index = match strategy {
+                    ArrayMerge::Append => base.len(),
+                    _ => index,
+                };
+                let path = index_path(path, index);
+                if index < base.len() {
+                    merge(&mut base[index], value, strategy, &path, source, origins);
+                } else {
+                    record(&value, &path, source, origins);
+                    base.push(value);
+                }
+            }
+        }
+        (base, overlay) => {
+            forget(path, origins);
+            record(&overlay, path, source, origins);
+            *base = overlay;
+        }
+    }
+}
+
+/// Record `source` as the origin of `value` and everything inside it
+fn record(value: &Value, path: &str, source: usize, origins: &mut BTreeMap<String, usize>) {
+    match value {
+        Value::Map(map) if !map.is_empty() => {
+            for (key, value) in map {
+                record(value, &key_path(path, key), source, origins);
+            }
+        }
+        Value::Seq(elements) if !elements.is_empty() => {
+            for (index, value) in elements.iter().enumerate() {
+                record(value, &index_path(path, index), source, origins);
+            }
+        }
+        _ => {
+            origins.insert(path.to_string(), source);
+        }
+    }
+}
+
+/// Forget the origins of the value at `path` and everything inside it
+fn forget(path: &str, origins: &mut BTreeMap<String, usize>) {
+    let nested = |other: &str| {
+        path.is_empty()
+            || other == path
+            || other.starts_with(path) && (other[path.len()..].starts_with('.') || other[path.len()..].starts_with('['))
+    };
+    let paths: Vec<String> = origins.keys().filter(|other| nested(other)).cloned().collect();
+    for other in paths {
+        origins.remove(&other);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Server {
+        host: String,
+        port: u16,
+        #[serde(default)]
+        tags: Vec<String>,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Config {
+        name: String,
+        servers: Vec<Server>,
+    }
+
+    const DEFAULTS: &str = "name = \"app\"\n\n[[servers]]\nhost = \"localhost\"\nport = 80\ntags = [\"a\"]\n";
+    const OVERRIDES: &str = r#"{"servers": [{"port": 8080, "tags": ["b"]}, {"host": "backup", "port": 81}]}"#;
+
+    fn builder(strategy: ArrayMerge) -> ConfigBuilder {
+        ConfigBuilder::new()
+            .string("defaults", DEFAULTS, Format::Toml)
+            .string("overrides", OVERRIDES, Format::Json)
+            .array_merge(strategy)
+    }
+
+    #[test]
+    fn replace_arrays() {
+        let error = builder(ArrayMerge::Replace).build::<Config>().unwrap_err();
+        assert_eq!(error.path(), Some("servers[0]"));
+
+        let config = builder(ArrayMerge::Replace).build_layered::<Value>().unwrap();
+        assert_eq!(config.source_of("name"), Some("defaults"));
+        assert_eq!(config.source_of("servers[0].port"), Some("overrides"));
+        assert_eq!(config.source_of("servers[0].host"), None);
+    }
+
+    #[test]
+    fn merge_arrays_by_index() {
+        let config = builder(ArrayMerge::ByIndex).build_layered::<Config>().unwrap();
+        assert_eq!(config.value.servers[0].host, "localhost");
+        assert_eq!(config.value.servers[0].port, 8080);
+        assert_eq!(config.value.servers[0].tags, vec!["b"]);
+        assert_eq!(config.value.servers[1].host, "backup");
+
+        assert_eq!(config.source_of("servers[0].host"), Some("defaults"));
+        assert_eq!(config.source_of("servers[0].port"), Some("overrides"));
+        assert_eq!(config.source_of("servers[0].tags[0]"), Some("overrides"));
+        assert_eq!(config.source_of("servers[1].host"), Some("overrides"));
+        assert_eq!(config.sources(), &["defaults".to_string(), "overrides".to_string()]);
+    }
+
+    #[test]
+    fn append_arrays() {
+        let overrides = r#"{"servers": [{"host": "backup", "port": 81}]}"#;
+        let config = ConfigBuilder::new()
+            .string("defaults", DEFAULTS, Format::Toml)
+            .string("overrides", overrides, Format::Json)
+            .array_merge(ArrayMerge::Append)
+            .build_layered::<Config>()
+            .unwrap();
+
+        let hosts: Vec<&str> = config.value.servers.iter().map(|s| s.host.as_str()).collect();
+        assert_eq!(hosts, vec!["localhost", "backup"]);
+        assert_eq!(config.source_of("servers[0].tags[0]"), Some("defaults"));
+        assert_eq!(config.source_of("servers[1].port"), Some("overrides"));
+    }
+
+    #[test]
+    fn replaced_maps_forget_origins() {
+        let config = ConfigBuilder::new()
+            .string("first", "a:\n  b: 1\n  c: 2\nab: 3\n", Format::Yaml)
+            .string("second", "a = 4\n", Format::Toml)
+            .build_layered::<Value>()
+            .unwrap();
+
+        assert_eq!(config.origins(), vec![("a", "second"), ("ab", "first")]);
+    }
+
+    #[test]
+    fn files_and_stems() {
+        let dir = ::std::env::temp_dir().join(format!("serde_any_config_{}", ::std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("defaults.toml"), DEFAULTS).unwrap();
+        fs::write(dir.join("local.yaml"), "name: local\n").unwrap();
+
+        let result = ConfigBuilder::new()
+            .file(dir.join("defaults.toml"))
+            .optional_file(dir.join("missing.json"))
+            .optional_file_stem(dir.join("missing"))
+            .file_stem(dir.join("local"))
+            .build_layered::<Config>();
+        let missing = ConfigBuilder::new().file(dir.join("missing.json")).build::<Value>();
+        let not_a_directory = ConfigBuilder::new()
+            .optional_file(dir.join("defaults.toml").join("nested.json"))
+            .build::<Value>();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let config = result.unwrap();
+        assert_eq!(config.value.name, "local");
+        assert_eq!(config.value.servers[0].port, 80);
+        assert_eq!(config.source_of("name"), Some(dir.join("local.yaml").display().to_string().as_str()));
+        assert_eq!(config.sources().len(), 2);
+        assert_matches!(missing, Err(Error::File { ref error, .. }) if matches!(**error, Error::Io(_)));
+        assert_matches!(not_a_directory, Err(Error::File { ref error, .. }) if matches!(**error, Error::Io(_)));
+    }
+
+    #[test]
+    fn env_paths() {
+        let vars = vec![
+            ("APP_NAME".to_string(), "env".to_string()),
+            ("APP_DATABASE__PORT".to_string(), "5432".to_string()),
+            ("APP_DATABASE__".to_string(), "ignored".to_string()),
+            ("APPLICATION".to_string(), "ignored".to_string()),
+        ];
+        let layers = env_layers(vars, "APP", "__");
+
+        let names: Vec<&str> = layers.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["APP_DATABASE__PORT", "APP_NAME"]);
+        let port = layers[0].1.get("database").and_then(|database| database.get("port"));
+        assert_eq!(port, Some(&Value::String("5432".to_string())));
+    }
+
+    #[test]
+    fn env_overrides_files() {
+        #[derive(Deserialize, Debug)]
+        struct Database {
+            port: u16,
+            replicas: Vec<String>,
+            tls: bool,
+        }
+
+        #[derive(Deserialize, Debug)]
+        struct Settings {
+            name: String,
+            database: Database,
+        }
+
+        let vars = [
+            ("SERDE_ANY_TEST_DATABASE__PORT", "5432"),
+            ("SERDE_ANY_TEST_DATABASE__REPLICAS", "a, b"),
+            ("SERDE_ANY_TEST_DATABASE__TLS", "true"),
+        ];
+        let defaults = "name = \"app\"\n[database]\nport = 1\nreplicas = []\ntls = false\n";
+        let config = ConfigBuilder::new()
+            .string("defaults", defaults, Format::Toml)
+            .env_vars("SERDE_ANY_TEST", &vars)
+            .build_layered::<Settings>()
+            .unwrap();
+        let error = ConfigBuilder::new()
+            .env_vars("SERDE_ANY_TEST", &[("SERDE_ANY_TEST_DATABASE__PORT", "many")])
+            .build::<Settings>()
+            .unwrap_err();
+
+        // Strings from files are not converted, even when they are merged with environment variables
+        let quoted = ConfigBuilder::new()
+            .string("defaults", "name = \"app\"\n[database]\nport = \"1\"\nreplicas = []\n", Format::Toml)
+            .env_vars("SERDE_ANY_TEST", &[("SERDE_ANY_TEST_DATABASE__TLS", "true")])
+            .build::<Settings>()
+            .unwrap_err();
+        let listed = ConfigBuilder::new()
+            .string("defaults", "name: app\ndatabase:\n  port: 1\n  replicas: a,b\n  tls: true\n", Format::Yaml)
+            .build::<Settings>()
+            .unwrap_err();
+
+        assert_eq!(config.value.name, "app");
+        assert_eq!(config.value.database.port, 5432);
+        assert_eq!(config.value.database.replicas, vec!["a", "b"]);
+        assert!(config.value.database.tls);
+        assert_eq!(config.source_of("database.port"), Some("SERDE_ANY_TEST_DATABASE__PORT"));
+        assert_eq!(error.path(), Some("database.port"));
+        assert_eq!(quoted.path(), Some("database.port"));
+        assert_eq!(listed.path(), Some("database.replicas"));
+    }
+
+    #[test]
+    fn searched_files() {
+        let dir = env::temp_dir().join(format!("serde_any_config_search_{}", ::std::process::id()));
+        fs::create_dir_all(dir.join("etc")).unwrap();
+        fs::create_dir_all(dir.join("home")).unwrap();
+        fs::write(dir.join("etc/app.toml"), DEFAULTS).unwrap();
+        fs::write(dir.join("home/app.yaml"), "name: home\n").unwrap();
+
+        let search = FileSearch::new("app").dirs(vec![dir.join("home"), dir.join("etc")]);
+        let result = ConfigBuilder::new().search(search).build_layered::<Config>();
+        let missing = ConfigBuilder::new().search(FileSearch::new("missing").dir(&dir)).build::<Value>();
+        let optional = ConfigBuilder::new()
+            .optional_search(FileSearch::new("missing").dir(&dir))
+            .build::<Value>();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let config = result.unwrap();
+        assert_eq!(config.value.name, "home");
+        assert_eq!(config.value.servers[0].host, "localhost");
+        let etc = dir.join("etc/app.toml").display().to_string();
+        assert_eq!(config.source_of("servers[0].host"), Some(etc.as_str()));
+        assert_matches!(missing, Err(Error::NoFileFound { .. }));
+        assert_eq!(optional.unwrap(), Value::Null);
+    }
+}
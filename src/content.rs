@@ -0,0 +1,683 @@
+// Values are buffered into a `Content` tree, which mirrors the serde data model closely enough
+// to be replayed into any serializer, keeping struct and variant names intact.
+
+use std::cmp::Ordering;
+use std::error;
+use std::fmt;
+use std::iter;
+
+use serde::ser::{
+    self, Serialize, SerializeMap, SerializeSeq, SerializeStruct, SerializeStructVariant, SerializeTuple,
+    SerializeTupleStruct, SerializeTupleVariant, Serializer,
+};
+
+/// Serializes the wrapped value after buffering it, optionally rearranging it on the way
+pub(crate) struct Buffered<'a, T: ?Sized + 'a> {
+    value: &'a T,
+    sort_keys: bool,
+    normalize_floats: bool,
+    root_as_map: bool,
+}
+
+impl<'a, T: ?Sized> Buffered<'a, T> {
+    pub fn new(value: &'a T) -> Buffered<'a, T> {
+        Buffered {
+            value,
+            sort_keys: false,
+            normalize_floats: false,
+            root_as_map: false,
+        }
+    }
+
+    /// Sort all map keys and struct fields
+    pub fn sort_keys(mut self) -> Buffered<'a, T> {
+        self.sort_keys = true;
+        self
+    }
+
+    /// Replace negative zero with zero in all floats
+    pub fn normalize_floats(mut self) -> Buffered<'a, T> {
+        self.normalize_floats = true;
+        self
+    }
+
+    /// Serialize a top-level struct as a map of its fields, dropping the struct name
+    #[cfg(feature = "xml")]
+    pub fn root_as_map(mut self) -> Buffered<'a, T> {
+        self.root_as_map = true;
+        self
+    }
+}
+
+impl<'a, T> Serialize for Buffered<'a, T>
+where
+    T: ?Sized + Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut content = self.value.serialize(ContentSerializer).map_err(ser::Error::custom)?;
+        if self.normalize_floats {
+            content.normalize_floats();
+        }
+        if self.sort_keys {
+            content.sort();
+        }
+        if self.root_as_map {
+            if let Content::Struct(_, fields) = content {
+                let entries = fields
+                    .into_iter()
+                    .map(|(key, value)| (Content::String(key.to_string()), value))
+                    .collect();
+                content = Content::Map(entries);
+            }
+        }
+        content.serialize(serializer)
+    }
+}
+
+#[derive(Debug)]
+struct Error(String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Error {
+        Error(msg.to_string())
+    }
+}
+
+type Fields = Vec<(&'static str, Content)>;
+
+#[derive(Debug)]
+enum Content {
+    Bool(bool),
+    I64(i64),
+    U64(u64),
+    F32(f32),
+    F64(f64),
+    Char(char),
+    String(String),
+    Bytes(Vec<u8>),
+    None,
+    Some(Box<Content>),
+    Unit,
+    UnitStruct(&'static str),
+    UnitVariant(&'static str, u32, &'static str),
+    NewtypeStruct(&'static str, Box<Content>),
+    NewtypeVariant(&'static str, u32, &'static str, Box<Content>),
+    Seq(Vec<Content>),
+    Tuple(Vec<Content>),
+    TupleStruct(&'static str, Vec<Content>),
+    TupleVariant(&'static str, u32, &'static str, Vec<Content>),
+    Map(Vec<(Content, Content)>),
+    Struct(&'static str, Fields),
+    StructVariant(&'static str, u32, &'static str, Fields),
+}
+
+impl Content {
+    /// Return the value of a number as a float, or `None` for any other content
+    fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Content::I64(v) => Some(v as f64),
+            Content::U64(v) => Some(v as f64),
+            Content::F32(v) => Some(f64::from(v)),
+            Content::F64(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Return the position of this kind of content in the order of keys of different kinds
+    fn rank(&self) -> u8 {
+        match self {
+            Content::None => 0,
+            Content::Some(_) => 1,
+            Content::Unit => 2,
+            Content::UnitStruct(_) => 3,
+            Content::Bool(_) => 4,
+            Content::I64(_) | Content::U64(_) | Content::F32(_) | Content::F64(_) => 5,
+            Content::Char(_) => 6,
+            Content::String(_) => 7,
+            Content::Bytes(_) => 8,
+            Content::NewtypeStruct(..) => 9,
+            Content::Seq(_) => 10,
+            Content::Tuple(_) => 11,
+            Content::TupleStruct(..) => 12,
+            Content::Map(_) => 13,
+            Content::Struct(..) => 14,
+            Content::UnitVariant(..) => 15,
+            Content::NewtypeVariant(..) => 16,
+            Content::TupleVariant(..) => 17,
+            Content::StructVariant(..) => 18,
+        }
+    }
+
+    fn sort(&mut self) {
+        match self {
+            Content::Some(content) | Content::NewtypeStruct(_, content) | Content::NewtypeVariant(_, _, _, content) => {
+                content.sort()
+            }
+            Content::Seq(elements)
+            | Content::Tuple(elements)
+            | Content::TupleStruct(_, elements)
+            | Content::TupleVariant(_, _, _, elements) => elements.iter_mut().for_each(Content::sort),
+            Content::Map(entries) => {
+                for (_, value) in entries.iter_mut() {
+                    value.sort();
+                }
+                entries.sort_by(|a, b| compare_keys(&a.0, &b.0));
+            }
+            Content::Struct(_, fields) | Content::StructVariant(_, _, _, fields) => {
+                for (_, value) in fields.iter_mut() {
+                    value.sort();
+                }
+                fields.sort_by_key(|&(key, _)| key);
+            }
+            _ => {}
+        }
+    }
+
+    fn normalize_floats(&mut self) {
+        match self {
+            Content::F32(v) if *v == 0.0 => *v = 0.0,
+            Content::F64(v) if *v == 0.0 => *v = 0.0,
+            Content::Some(content) | Content::NewtypeStruct(_, content) | Content::NewtypeVariant(_, _, _, content) => {
+                content.normalize_floats()
+            }
+            Content::Seq(elements)
+            | Content::Tuple(elements)
+            | Content::TupleStruct(_, elements)
+            | Content::TupleVariant(_, _, _, elements) => elements.iter_mut().for_each(Content::normalize_floats),
+            Content::Map(entries) => {
+                for (key, value) in entries.iter_mut() {
+                    key.normalize_floats();
+                    value.normalize_floats();
+                }
+            }
+            Content::Struct(_, fields) | Content::StructVariant(_, _, _, fields) => {
+                fields.iter_mut().for_each(|(_, value)| value.normalize_floats())
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Orders keys by value, comparing numbers numerically and enum variants by their index
+///
+/// Keys of different kinds are ordered by kind, so that every map has a single sorted order.
+fn compare_keys(a: &Content, b: &Content) -> Ordering {
+    match (a, b) {
+        (Content::String(a), Content::String(b)) => a.cmp(b),
+        (&Content::I64(a), &Content::I64(b)) => a.cmp(&b),
+        (&Content::U64(a), &Content::U64(b)) => a.cmp(&b),
+        (&Content::I64(a), &Content::U64(b)) => (i128::from(a)).cmp(&i128::from(b)),
+        (&Content::U64(a), &Content::I64(b)) => (i128::from(a)).cmp(&i128::from(b)),
+        (&Content::Bool(a), &Content::Bool(b)) => a.cmp(&b),
+        (&Content::Char(a), &Content::Char(b)) => a.cmp(&b),
+        (Content::Bytes(a), Content::Bytes(b)) => a.cmp(b),
+        (Content::Some(a), Content::Some(b)) | (Content::NewtypeStruct(_, a), Content::NewtypeStruct(_, b)) => {
+            compare_keys(a, b)
+        }
+        (&Content::UnitVariant(_, a, _), &Content::UnitVariant(_, b, _)) => a.cmp(&b),
+        (Content::NewtypeVariant(_, a_index, _, a), Content::NewtypeVariant(_, b_index, _, b)) => {
+            a_index.cmp(b_index).then_with(|| compare_keys(a, b))
+        }
+        (Content::Seq(a), Content::Seq(b))
+        | (Content::Tuple(a), Content::Tuple(b))
+        | (Content::TupleStruct(_, a), Content::TupleStruct(_, b)) => compare_all(a, b),
+        (Content::TupleVariant(_, a_index, _, a), Content::TupleVariant(_, b_index, _, b)) => {
+            a_index.cmp(b_index).then_with(|| compare_all(a, b))
+        }
+        (Content::Map(a), Content::Map(b)) => {
+            let a = a.iter().flat_map(|(key, value)| iter::once(key).chain(iter::once(value)));
+            let b = b.iter().flat_map(|(key, value)| iter::once(key).chain(iter::once(value)));
+            compare_iters(a, b)
+        }
+        (Content::Struct(_, a), Content::Struct(_, b)) => compare_fields(a, b),
+        (Content::StructVariant(_, a_index, _, a), Content::StructVariant(_, b_index, _, b)) => {
+            a_index.cmp(b_index).then_with(|| compare_fields(a, b))
+        }
+        _ => match (a.as_f64(), b.as_f64()) {
+            (Some(a), Some(b)) => compare_floats(a, b),
+            _ => a.rank().cmp(&b.rank()),
+        },
+    }
+}
+
+/// Orders floats numerically, with NaN after every other value
+fn compare_floats(a: f64, b: f64) -> Ordering {
+    a.partial_cmp(&b).unwrap_or_else(|| a.is_nan().cmp(&b.is_nan()))
+}
+
+fn compare_all(a: &[Content], b: &[Content]) -> Ordering {
+    compare_iters(a.iter(), b.iter())
+}
+
+fn compare_fields(a: &Fields, b: &Fields) -> Ordering {
+    compare_iters(a.iter().map(|(_, value)| value), b.iter().map(|(_, value)| value))
+}
+
+/// Orders sequences of keys lexicographically
+fn compare_iters<'a, A, B>(a: A, b: B) -> Ordering
+where
+    A: IntoIterator<Item = &'a Content>,
+    B: IntoIterator<Item = &'a Content>,
+{
+    let mut b = b.into_iter();
+    for a in a {
+        match b.next() {
+            Some(b) => match compare_keys(a, b) {
+                Ordering::Equal => {}
+                ordering => return ordering,
+            },
+            None => return Ordering::Greater,
+        }
+    }
+    if b.next().is_some() {
+        Ordering::Less
+    } else {
+        Ordering::Equal
+    }
+}
+
+impl Serialize for Content {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match *self {
+            Content::Bool(v) => serializer.serialize_bool(v),
+            Content::I64(v) => serializer.serialize_i64(v),
+            Content::U64(v) => serializer.serialize_u64(v),
+            Content::F32(v) => serializer.serialize_f32(v),
+            Content::F64(v) => serializer.serialize_f64(v),
+            Content::Char(v) => serializer.serialize_char(v),
+            Content::String(ref v) => serializer.serialize_str(v),
+            Content::Bytes(ref v) => serializer.serialize_bytes(v),
+            Content::None => serializer.serialize_none(),
+            Content::Some(ref v) => serializer.serialize_some(v),
+            Content::Unit => serializer.serialize_unit(),
+            Content::UnitStruct(name) => serializer.serialize_unit_struct(name),
+            Content::UnitVariant(name, index, variant) => serializer.serialize_unit_variant(name, index, variant),
+            Content::NewtypeStruct(name, ref v) => serializer.serialize_newtype_struct(name, v),
+            Content::NewtypeVariant(name, index, variant, ref v) => {
+                serializer.serialize_newtype_variant(name, index, variant, v)
+            }
+            Content::Seq(ref elements) => {
+                let mut seq = serializer.serialize_seq(Some(elements.len()))?;
+                for element in elements {
+                    seq.serialize_element(element)?;
+                }
+                seq.end()
+            }
+            Content::Tuple(ref elements) => {
+                let mut tuple = serializer.serialize_tuple(elements.len())?;
+                for element in elements {
+                    tuple.serialize_element(element)?;
+                }
+                tuple.end()
+            }
+            Content::TupleStruct(name, ref fields) => {
+                let mut tuple = serializer.serialize_tuple_struct(name, fields.len())?;
+                for field in fields {
+                    tuple.serialize_field(field)?;
+                }
+                tuple.end()
+            }
+            Content::TupleVariant(name, index, variant, ref fields) => {
+                let mut tuple = serializer.serialize_tuple_variant(name, index, variant, fields.len())?;
+                for field in fields {
+                    tuple.serialize_field(field)?;
+                }
+                tuple.end()
+            }
+            Content::Map(ref entries) => {
+                let mut map = serializer.serialize_map(Some(entries.len()))?;
+                for (key, value) in entries {
+                    map.serialize_entry(key, value)?;
+                }
+                map.end()
+            }
+            Content::Struct(name, ref fields) => {
+                let mut structure = serializer.serialize_struct(name, fields.len())?;
+                for &(key, ref value) in fields {
+                    structure.serialize_field(key, value)?;
+                }
+                structure.end()
+            }
+            Content::StructVariant(name, index, variant, ref fields) => {
+                let mut structure = serializer.serialize_struct_variant(name, index, variant, fields.len())?;
+                for &(key, ref value) in fields {
+                    structure.serialize_field(key, value)?;
+                }
+                structure.end()
+            }
+        }
+    }
+}
+
+struct ContentSerializer;
+
+impl Serializer for ContentSerializer {
+    type Ok = Content;
+    type Error = Error;
+
+    type SerializeSeq = SerializeElements;
+    type SerializeTuple = SerializeElements;
+    type SerializeTupleStruct = SerializeElements;
+    type SerializeTupleVariant = SerializeElements;
+    type SerializeMap = SerializeEntries;
+    type SerializeStruct = SerializeFields;
+    type SerializeStructVariant = SerializeFields;
+
+    fn serialize_bool(self, v: bool) -> Result<Content, Error> {
+        Ok(Content::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Content, Error> {
+        Ok(Content::I64(v.into()))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Content, Error> {
+        Ok(Content::I64(v.into()))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Content, Error> {
+        Ok(Content::I64(v.into()))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Content, Error> {
+        Ok(Content::I64(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Content, Error> {
+        Ok(Content::U64(v.into()))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Content, Error> {
+        Ok(Content::U64(v.into()))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Content, Error> {
+        Ok(Content::U64(v.into()))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Content, Error> {
+        Ok(Content::U64(v))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Content, Error> {
+        Ok(Content::F32(v))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Content, Error> {
+        Ok(Content::F64(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Content, Error> {
+        Ok(Content::Char(v))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Content, Error> {
+        Ok(Content::String(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Content, Error> {
+        Ok(Content::Bytes(v.to_vec()))
+    }
+
+    fn serialize_none(self) -> Result<Content, Error> {
+        Ok(Content::None)
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<Content, Error> {
+        Ok(Content::Some(Box::new(value.serialize(self)?)))
+    }
+
+    fn serialize_unit(self) -> Result<Content, Error> {
+        Ok(Content::Unit)
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<Content, Error> {
+        Ok(Content::UnitStruct(name))
+    }
+
+    fn serialize_unit_variant(self, name: &'static str, index: u32, variant: &'static str) -> Result<Content, Error> {
+        Ok(Content::UnitVariant(name, index, variant))
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(self, name: &'static str, value: &T) -> Result<Content, Error> {
+        Ok(Content::NewtypeStruct(name, Box::new(value.serialize(self)?)))
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Content, Error> {
+        Ok(Content::NewtypeVariant(name, index, variant, Box::new(value.serialize(self)?)))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeElements, Error> {
+        Ok(SerializeElements::new(ElementsKind::Seq, len.unwrap_or(0)))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SerializeElements, Error> {
+        Ok(SerializeElements::new(ElementsKind::Tuple, len))
+    }
+
+    fn serialize_tuple_struct(self, name: &'static str, len: usize) -> Result<SerializeElements, Error> {
+        Ok(SerializeElements::new(ElementsKind::TupleStruct(name), len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SerializeElements, Error> {
+        Ok(SerializeElements::new(ElementsKind::TupleVariant(name, index, variant), len))
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<SerializeEntries, Error> {
+        Ok(SerializeEntries {
+            entries: Vec::with_capacity(len.unwrap_or(0)),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(self, name: &'static str, len: usize) -> Result<SerializeFields, Error> {
+        Ok(SerializeFields {
+            variant: None,
+            name,
+            fields: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SerializeFields, Error> {
+        Ok(SerializeFields {
+            variant: Some((index, variant)),
+            name,
+            fields: Vec::with_capacity(len),
+        })
+    }
+}
+
+enum ElementsKind {
+    Seq,
+    Tuple,
+    TupleStruct(&'static str),
+    TupleVariant(&'static str, u32, &'static str),
+}
+
+struct SerializeElements {
+    kind: ElementsKind,
+    elements: Vec<Content>,
+}
+
+impl SerializeElements {
+    fn new(kind: ElementsKind, len: usize) -> SerializeElements {
+        SerializeElements {
+            kind,
+            elements: Vec::with_capacity(len),
+        }
+    }
+
+    fn push<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.elements.push(value.serialize(ContentSerializer)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Content {
+        match self.kind {
+            ElementsKind::Seq => Content::Seq(self.elements),
+            ElementsKind::Tuple => Content::Tuple(self.elements),
+            ElementsKind::TupleStruct(name) => Content::TupleStruct(name, self.elements),
+            ElementsKind::TupleVariant(name, index, variant) => {
+                Content::TupleVariant(name, index, variant, self.elements)
+            }
+        }
+    }
+}
+
+impl SerializeSeq for SerializeElements {
+    type Ok = Content;
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Content, Error> {
+        Ok(self.finish())
+    }
+}
+
+impl SerializeTuple for SerializeElements {
+    type Ok = Content;
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Content, Error> {
+        Ok(self.finish())
+    }
+}
+
+impl SerializeTupleStruct for SerializeElements {
+    type Ok = Content;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Content, Error> {
+        Ok(self.finish())
+    }
+}
+
+impl SerializeTupleVariant for SerializeElements {
+    type Ok = Content;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Content, Error> {
+        Ok(self.finish())
+    }
+}
+
+struct SerializeEntries {
+    entries: Vec<(Content, Content)>,
+    key: Option<Content>,
+}
+
+impl SerializeMap for SerializeEntries {
+    type Ok = Content;
+    type Error = Error;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), Error> {
+        self.key = Some(key.serialize(ContentSerializer)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| Error("serialize_value called before serialize_key".to_string()))?;
+        self.entries.push((key, value.serialize(ContentSerializer)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Content, Error> {
+        Ok(Content::Map(self.entries))
+    }
+}
+
+struct SerializeFields {
+    variant: Option<(u32, &'static str)>,
+    name: &'static str,
+    fields: Fields,
+}
+
+impl SerializeFields {
+    fn push<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        self.fields.push((key, value.serialize(ContentSerializer)?));
+        Ok(())
+    }
+
+    fn finish(self) -> Content {
+        match self.variant {
+            Some((index, variant)) => Content::StructVariant(self.name, index, variant, self.fields),
+            None => Content::Struct(self.name, self.fields),
+        }
+    }
+}
+
+impl SerializeStruct for SerializeFields {
+    type Ok = Content;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        self.push(key, value)
+    }
+
+    fn end(self) -> Result<Content, Error> {
+        Ok(self.finish())
+    }
+}
+
+impl SerializeStructVariant for SerializeFields {
+    type Ok = Content;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        self.push(key, value)
+    }
+
+    fn end(self) -> Result<Content, Error> {
+        Ok(self.finish())
+    }
+}
@@ -1,12 +1,24 @@
-use std::fs::File;
 use std::io::Read;
 use std::path::Path;
+use std::str;
 
-use serde::de::{Deserialize, DeserializeOwned};
+use serde::de::{Deserialize, DeserializeOwned, Deserializer};
+#[cfg(all(feature = "toml", feature = "toml_edit"))]
+use serde::de::{self, DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor};
+#[cfg(all(feature = "toml", feature = "toml_edit"))]
+use std::fmt;
+use serde_path_to_error;
 
 use backend::*;
-use format::{guess_format, supported_extensions, supported_formats, Format};
-use error::Error;
+use compression::{decoder, detect_compression, open_file};
+use encoding::{decode, detect_encoding, read_prefix, Encoding};
+use format::{guess_format, supported_formats, Format};
+use error::{Error, Limit, Location, NoSuccessfulParse};
+use guard::{Budget, Guard};
+#[cfg(all(feature = "toml", feature = "toml_edit"))]
+use path::parse_path;
+use path::{render_partial_path, Segment};
+use search::FileSearch;
 
 /// Deserialize from an IO stream using a specified format
 ///
@@ -17,6 +29,8 @@ use error::Error;
 ///
 /// If the conversion itself fails, the format-specific variant of [`Error`]
 /// will be returned, with the underlying error as its cause.
+/// If the failure happened inside a nested value, that error is wrapped in
+/// [`Error::Field`] together with the path to the value.
 ///
 /// # Example
 ///
@@ -59,33 +73,14 @@ use error::Error;
 ///
 /// [`Error`]: ../error/enum.Error.html
 /// [`Error::UnsupportedFormat`]: ../error/enum.Error.html#variant.UnsupportedFormat
+/// [`Error::Field`]: ../error/enum.Error.html#variant.Field
 ///
-#[allow(unreachable_patterns, unused_mut)]
-pub fn from_reader<T, R>(mut reader: R, format: Format) -> Result<T, Error>
+pub fn from_reader<T, R>(reader: R, format: Format) -> Result<T, Error>
 where
     T: DeserializeOwned,
     R: Read,
 {
-    match format {
-        #[cfg(feature = "yaml")]
-        Format::Yaml => Ok(serde_yaml::from_reader::<_, T>(reader)?),
-        #[cfg(feature = "json")]
-        Format::Json => Ok(serde_json::from_reader::<_, T>(reader)?),
-        #[cfg(feature = "toml")]
-        Format::Toml => {
-            let mut s = Vec::new();
-            reader.read_to_end(&mut s)?;
-            Ok(toml::from_slice::<T>(&s)?)
-        }
-        #[cfg(feature = "ron")]
-        Format::Ron => Ok(ron::de::from_reader::<_, T>(reader)?),
-        #[cfg(feature = "xml")]
-        Format::Xml => Ok(xml::from_reader::<_, T>(reader)?),
-        #[cfg(feature = "url")]
-        Format::Url => Ok(url::from_reader::<T, _>(reader)?),
-
-        _ => Err(Error::UnsupportedFormat(format)),
-    }
+    from_reader_with(reader, format, &DeserializeOptions::default())
 }
 
 /// Deserialize from a string using a specified format
@@ -97,6 +92,8 @@ where
 ///
 /// If the conversion itself fails, the format-specific variant of [`Error`]
 /// will be returned, with the underlying error as its cause.
+/// If the failure happened inside a nested value, that error is wrapped in
+/// [`Error::Field`] together with the path to the value.
 ///
 /// # Example
 ///
@@ -129,28 +126,13 @@ where
 ///
 /// [`Error`]: ../error/enum.Error.html
 /// [`Error::UnsupportedFormat`]: ../error/enum.Error.html#variant.UnsupportedFormat
+/// [`Error::Field`]: ../error/enum.Error.html#variant.Field
 ///
 pub fn from_str<'a, T>(s: &'a str, format: Format) -> Result<T, Error>
 where
     T: for<'de> Deserialize<'de>,
 {
-    #[allow(unreachable_patterns)]
-    match format {
-        #[cfg(feature = "yaml")]
-        Format::Yaml => Ok(serde_yaml::from_str::<T>(s)?),
-        #[cfg(feature = "json")]
-        Format::Json => Ok(serde_json::from_str::<T>(s)?),
-        #[cfg(feature = "toml")]
-        Format::Toml => Ok(toml::from_str::<T>(s)?),
-        #[cfg(feature = "ron")]
-        Format::Ron => Ok(ron::de::from_str::<T>(s)?),
-        #[cfg(feature = "xml")]
-        Format::Xml => Ok(xml::from_str(s)?),
-        #[cfg(feature = "url")]
-        Format::Url => Ok(url::from_str::<T>(s)?),
-
-        _ => Err(Error::UnsupportedFormat(format)),
-    }
+    from_str_with(s, format, &DeserializeOptions::default())
 }
 
 /// Deserialize from a string using any supported format
@@ -198,16 +180,7 @@ pub fn from_str_any<'a, T>(s: &'a str) -> Result<T, Error>
 where
     T: for<'de> Deserialize<'de>,
 {
-    let mut errors = Vec::new();
-
-    for format in supported_formats() {
-        match from_str(&s, format) {
-            Ok(t) => return Ok(t),
-            Err(err) => errors.push((format, err)),
-        }
-    }
-
-    Err(Error::NoSuccessfulParse(errors))
+    from_str_any_with(s, &DeserializeOptions::default())
 }
 
 /// Deserialize from a byte slice using a specified format
@@ -222,6 +195,8 @@ where
 ///
 /// If the conversion itself fails, the format-specific variant of [`Error`]
 /// will be returned, with the underlying error as its cause.
+/// If the failure happened inside a nested value, that error is wrapped in
+/// [`Error::Field`] together with the path to the value.
 ///
 /// # Example
 ///
@@ -254,28 +229,13 @@ where
 ///
 /// [`Error`]: ../error/enum.Error.html
 /// [`Error::UnsupportedFormat`]: ../error/enum.Error.html#variant.UnsupportedFormat
+/// [`Error::Field`]: ../error/enum.Error.html#variant.Field
 ///
 pub fn from_slice<'a, T>(s: &'a [u8], format: Format) -> Result<T, Error>
 where
     T: for<'de> Deserialize<'de>,
 {
-    #[allow(unreachable_patterns)]
-    match format {
-        #[cfg(feature = "yaml")]
-        Format::Yaml => Ok(serde_yaml::from_slice(s)?),
-        #[cfg(feature = "json")]
-        Format::Json => Ok(serde_json::from_slice(s)?),
-        #[cfg(feature = "toml")]
-        Format::Toml => Ok(toml::from_slice(s)?),
-        #[cfg(feature = "ron")]
-        Format::Ron => Ok(ron::de::from_bytes(s)?),
-        #[cfg(feature = "xml")]
-        Format::Xml => Ok(xml::from_reader(s)?),
-        #[cfg(feature = "url")]
-        Format::Url => Ok(url::from_bytes(s)?),
-
-        _ => Err(Error::UnsupportedFormat(format)),
-    }
+    from_slice_with(s, format, &DeserializeOptions::default())
 }
 
 /// Deserialize from a byte slice using any supported format
@@ -283,11 +243,17 @@ where
 /// This function will attempt to deserialize the slice using each supported format, and will return the result of the
 /// first successful deserialization.
 ///
+/// If the slice starts with the magic bytes of a compression recognized by [`detect_compression`],
+/// it is decompressed first.
+///
 /// # Errors
 ///
 /// If none of the supported formats can deserialize the string successfully,
 /// [`Error::NoSuccessfulParse`] is returned.
 ///
+/// If the data is compressed and its compression is not supported, [`Error::UnsupportedCompression`] is returned.
+/// If decompression fails, [`Error::Io`] is returned.
+///
 /// # Example
 ///
 /// ```
@@ -317,22 +283,117 @@ where
 /// }
 /// ```
 ///
+/// [`detect_compression`]: ../compression/fn.detect_compression.html
 /// [`Error::NoSuccessfulParse`]: ../error/enum.Error.html#variant.NoSuccessfulParse
+/// [`Error::UnsupportedCompression`]: ../error/enum.Error.html#variant.UnsupportedCompression
+/// [`Error::Io`]: ../error/enum.Error.html#variant.Io
 ///
 pub fn from_slice_any<'a, T>(s: &'a [u8]) -> Result<T, Error>
 where
     T: for<'de> Deserialize<'de>,
 {
-    let mut errors = Vec::new();
+    from_slice_any_with(s, &DeserializeOptions::default())
+}
 
-    for format in supported_formats() {
-        match from_slice(&s, format) {
-            Ok(t) => return Ok(t),
-            Err(err) => errors.push((format, err)),
+/// Deserialize from a string using a specified format, borrowing strings and bytes from the input
+///
+/// Unlike [`from_str`], this allows deserializing into types that hold references into the input,
+/// such as `&'a str`, which avoids copying them.
+/// Only formats for which [`Format::supports_borrowing`] is true can borrow from their input:
+/// JSON, TOML and URL-encoded data.
+///
+/// A string can only be borrowed if the input contains it verbatim, so strings with escape sequences
+/// fail to deserialize into `&'a str`. Use `Cow<'a, str>` with `#[serde(borrow)]` for strings that may contain them.
+///
+/// # Errors
+///
+/// If the format cannot borrow from its input, [`Error::UnsupportedBorrow`] is returned,
+/// or [`Error::UnsupportedFormat`] if it is not supported at all.
+/// Otherwise, errors are the same as for [`from_str`].
+///
+/// # Example
+///
+/// ```
+/// #[macro_use]
+/// extern crate serde;
+/// extern crate serde_any;
+/// extern crate failure;
+///
+/// use failure::Error;
+///
+/// use serde_any::Format;
+///
+/// #[derive(Deserialize, Debug)]
+/// struct Person<'a> {
+///     name: &'a str,
+///     knowledge: u32,
+/// }
+///
+/// fn main() -> Result<(), Error> {
+///     let data = "{
+/// \"name\": \"Jon Snow\",
+/// \"knowledge\": 0
+/// }";
+///     let person: Person = serde_any::from_str_borrowed(data, Format::Json)?;
+///     assert_eq!(person.name, "Jon Snow");
+///     assert!(serde_any::from_str_borrowed::<Person>("name: Jon Snow", Format::Yaml).is_err());
+///     Ok(())
+/// }
+/// ```
+///
+/// [`from_str`]: fn.from_str.html
+/// [`Format::supports_borrowing`]: ../format/enum.Format.html#method.supports_borrowing
+/// [`Error::UnsupportedBorrow`]: ../error/enum.Error.html#variant.UnsupportedBorrow
+/// [`Error::UnsupportedFormat`]: ../error/enum.Error.html#variant.UnsupportedFormat
+///
+pub fn from_str_borrowed<'a, T>(s: &'a str, format: Format) -> Result<T, Error>
+where
+    T: Deserialize<'a>,
+{
+    from_slice_borrowed(s.as_bytes(), format)
+}
+
+/// Deserialize from a byte slice using a specified format, borrowing strings and bytes from the input
+///
+/// This works like [`from_str_borrowed`]. The input has to be UTF-8, since transcoded input could not be
+/// borrowed from, but a byte order mark is skipped.
+///
+/// # Errors
+///
+/// Errors are the same as for [`from_str_borrowed`].
+///
+/// [`from_str_borrowed`]: fn.from_str_borrowed.html
+///
+#[cfg_attr(
+    not(any(feature = "json", feature = "toml", feature = "url")),
+    allow(unused_variables)
+)]
+pub fn from_slice_borrowed<'a, T>(s: &'a [u8], format: Format) -> Result<T, Error>
+where
+    T: Deserialize<'a>,
+{
+    let s = &s[Encoding::Utf8.bom_len(s)..];
+
+    #[allow(unreachable_patterns)]
+    match format {
+        #[cfg(feature = "json")]
+        Format::Json => {
+            let mut deserializer = serde_json::Deserializer::from_slice(s);
+            let t = deserialize_tracked(&mut deserializer)?;
+            deserializer.end()?;
+            Ok(t)
         }
-    }
+        #[cfg(feature = "toml")]
+        Format::Toml => {
+            let s = str::from_utf8(s).map_err(<toml::de::Error as ::serde::de::Error>::custom)?;
+            deserialize_tracked(&mut toml::Deserializer::new(s))
+        }
+        #[cfg(feature = "url")]
+        Format::Url => deserialize_tracked(url::Deserializer::new(form_urlencoded::parse(s))),
 
-    Err(Error::NoSuccessfulParse(errors))
+        _ if format.is_supported() => Err(Error::UnsupportedBorrow(format)),
+        _ => Err(Error::UnsupportedFormat(format)),
+    }
 }
 
 /// Deserialize from a file
@@ -342,6 +403,9 @@ where
 /// the whole file is read into a buffer,
 /// and deserialization is attempted using [`from_slice_any`].
 ///
+/// If the file name ends with a compression suffix, such as `data.json.gz`,
+/// the file is decompressed while reading, and the format is guessed from the inner extension.
+///
 /// # Errors
 ///
 /// If the file extension is recognized, but parsing fails, this function returns
@@ -350,9 +414,14 @@ where
 /// If the file extension is not recognized and the file cannot be opened,
 /// it returns [`Error::Io`] with the underlying error as the cause.
 ///
+/// If the compression suffix is recognized but its feature is not enabled,
+/// [`Error::UnsupportedCompression`] is returned.
+///
 /// If the file extension is not recognized, the file can opened but deserialization fails,
 /// this function returns the error from [`from_slice_any`].
 ///
+/// All of these errors are wrapped in [`Error::File`] with the path of the file.
+///
 /// # Example
 ///
 /// ```
@@ -378,24 +447,15 @@ where
 /// [`from_reader`]: fn.from_reader.html
 /// [`from_slice_any`]: fn.from_slice_any.html
 /// [`Error::Io`]: ../error/enum.Error.html#variant.Io
+/// [`Error::UnsupportedCompression`]: ../error/enum.Error.html#variant.UnsupportedCompression
+/// [`Error::File`]: ../error/enum.Error.html#variant.File
 ///
 pub fn from_file<T, P>(path: P) -> Result<T, Error>
 where
     T: DeserializeOwned,
     P: AsRef<Path>,
 {
-    let format = guess_format(&path);
-
-    match format {
-        Some(format) => from_reader(File::open(path)?, format),
-        None => {
-            let mut s = Vec::new();
-            let mut reader = File::open(&path)?;
-            reader.read_to_end(&mut s)?;
-
-            Ok(from_slice_any(&s)?)
-        }
-    }
+    from_file_with(path, &DeserializeOptions::default())
 }
 
 /// Deserialize from any file with a given stem
@@ -403,10 +463,17 @@ where
 /// This function tries to deserialize from any file with the given `stem` and any of the supported extensions.
 /// The list of supported extensions can be queried with [`supported_extensions`].
 ///
+/// If files with several of the extensions exist, the one whose extension comes first is used
+/// and the others are ignored. To report such conflicts instead, use a [`FileSearch`]
+/// with a [`ConflictPolicy`], such as `FileSearch::new(stem).conflicts(ConflictPolicy::Error).load()`.
+///
 /// # Errors
 ///
-/// If none of the supported formats can deserialize the string successfully,
-/// [`Error::NoSuccessfulParse`] is returned.
+/// If no file with the stem and a supported extension exists,
+/// [`Error::NoFileFound`] is returned with all paths that were tried.
+///
+/// If a file exists but fails to deserialize, its error from [`from_file`] is returned,
+/// wrapped in [`Error::File`] with the path of the file.
 ///
 /// # Example
 ///
@@ -432,117 +499,1062 @@ where
 /// ```
 ///
 /// [`supported_extensions`]: ../format/fn.supported_extensions.html
-/// [`Error::NoSuccessfulParse`]: ../error/enum.Error.html#variant.NoSuccessfulParse
+/// [`FileSearch`]: ../search/struct.FileSearch.html
+/// [`ConflictPolicy`]: ../search/enum.ConflictPolicy.html
+/// [`from_file`]: fn.from_file.html
+/// [`Error::NoFileFound`]: ../error/enum.Error.html#variant.NoFileFound
+/// [`Error::File`]: ../error/enum.Error.html#variant.File
 ///
 pub fn from_file_stem<T, P>(stem: P) -> Result<T, Error>
 where
     T: DeserializeOwned,
     P: AsRef<Path>,
 {
-    let mut errors = Vec::new();
-
-    for extension in supported_extensions() {
-        let path = stem.as_ref().with_extension(extension);
-        match from_file(&path) {
-            Ok(t) => return Ok(t),
-            Err(err) => {
-                if let Some(format) = guess_format(path) {
-                    errors.push((format, err));
-                }
-            }
-        }
-    }
+    FileSearch::new(stem).load()
+}
 
-    Err(Error::NoSuccessfulParse(errors))
+/// Deserialize from the first file with the given stem found in a list of directories
+///
+/// Directories are searched in the given order, and in each directory, every extension from
+/// [`supported_extensions`] is tried. Directories may refer to environment variables,
+/// such as `$XDG_CONFIG_HOME/app`. Use [`FileSearch`] directly to set a preferred order of extensions,
+/// or to find all matching files instead of only the first one.
+///
+/// # Errors
+///
+/// If no file exists, [`Error::NoFileFound`] is returned with all paths that were tried.
+/// If a file exists but fails to deserialize, the error from [`from_file`] is returned,
+/// wrapped in [`Error::File`] with the path of the file.
+///
+/// # Example
+///
+/// ```
+/// #[macro_use]
+/// extern crate serde;
+/// extern crate serde_any;
+///
+/// #[derive(Deserialize, Debug)]
+/// struct User {
+///     fingerprint: String,
+///     location: String,
+/// }
+///
+/// fn main() {
+///     let dirs = ["./", "$XDG_CONFIG_HOME/app", "/etc/app"];
+///     match serde_any::from_file_stem_in::<User, _, _>(&dirs, "user") {
+///         Ok(u) => println!("{:#?}", u),
+///         Err(e) => println!("Error deserializing user: {}", e),
+///     };
+/// }
+/// ```
+///
+/// [`supported_extensions`]: ../format/fn.supported_extensions.html
+/// [`FileSearch`]: ../search/struct.FileSearch.html
+/// [`from_file`]: fn.from_file.html
+/// [`Error::NoFileFound`]: ../error/enum.Error.html#variant.NoFileFound
+/// [`Error::File`]: ../error/enum.Error.html#variant.File
+///
+pub fn from_file_stem_in<T, D, P>(dirs: &[D], stem: P) -> Result<T, Error>
+where
+    T: DeserializeOwned,
+    D: AsRef<Path>,
+    P: AsRef<Path>,
+{
+    FileSearch::new(stem).dirs(dirs).load()
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Options for deserialization
+///
+/// These options protect against malicious or accidentally huge inputs, such as untrusted uploads.
+/// They are enforced in the same way for all formats.
+/// By default, no limits are set and duplicate keys are accepted,
+/// with the last occurrence of a key usually winning.
+///
+/// When a limit is exceeded, [`Error::LimitExceeded`] is returned.
+/// It is wrapped in [`Error::Field`] if the limit was exceeded inside a nested value.
+///
+/// # Example
+///
+/// ```
+/// #[macro_use]
+/// extern crate serde;
+/// extern crate serde_any;
+///
+/// use serde_any::DeserializeOptions;
+/// use serde_any::error::{Error, Limit};
+///
+/// #[derive(Deserialize, Debug)]
+/// struct Laughs {
+///     a: Vec<String>,
+///     b: Vec<Vec<String>>,
+///     c: Vec<Vec<Vec<String>>>,
+///     d: Vec<Vec<Vec<Vec<String>>>>,
+///     e: Vec<Vec<Vec<Vec<Vec<String>>>>>,
+/// }
+///
+/// fn main() {
+///     let options = DeserializeOptions::new()
+///         .max_input_bytes(64 * 1024)
+///         .max_depth(16)
+///         .max_collection_len(1000)
+///         .max_values(10_000);
+///
+///     // A YAML "billion laughs" document expands to far more values than its size suggests
+///     let laughs = "a: &a [lol, lol, lol, lol, lol, lol, lol, lol, lol]
+/// b: &b [*a, *a, *a, *a, *a, *a, *a, *a, *a]
+/// c: &c [*b, *b, *b, *b, *b, *b, *b, *b, *b]
+/// d: &d [*c, *c, *c, *c, *c, *c, *c, *c, *c]
+/// e: &e [*d, *d, *d, *d, *d, *d, *d, *d, *d]
+/// ";
+///     match serde_any::from_slice_any_with::<Laughs>(laughs.as_bytes(), &options) {
+///         Err(Error::Field { path, error }) => match *error {
+///             Error::LimitExceeded { limit: Limit::Values, .. } => assert!(path.starts_with("e[")),
+///             other => panic!("{}", other),
+///         },
+///         other => panic!("{:?}", other),
+///     }
+/// }
+/// ```
+///
+/// [`Error::LimitExceeded`]: ../error/enum.Error.html#variant.LimitExceeded
+/// [`Error::Field`]: ../error/enum.Error.html#variant.Field
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DeserializeOptions {
+    pub(crate) max_input_bytes: Option<usize>,
+    pub(crate) max_depth: Option<usize>,
+    pub(crate) max_collection_len: Option<usize>,
+    pub(crate) max_values: Option<usize>,
+    pub(crate) reject_duplicate_keys: bool,
+    pub(crate) deny_unknown_fields: bool,
+    pub(crate) encoding: Option<Encoding>,
+}
 
-    #[derive(Deserialize, PartialEq, Eq, Debug)]
-    pub struct Wizard {
-        pub name: String,
-        pub is_late: bool,
-        pub color: String,
-        pub age: u32,
-        pub friends: Vec<String>,
+impl DeserializeOptions {
+    /// Create the default options, without any limits
+    pub fn new() -> DeserializeOptions {
+        DeserializeOptions::default()
     }
 
-    pub fn radagast() -> Wizard {
-        Wizard {
-            name: "Radagast".to_string(),
-            color: "Brown".to_string(),
-            is_late: true,
-            age: 8000,
-            friends: vec!["animals".to_string()],
-        }
+    /// Reject inputs larger than `max` bytes
+    ///
+    /// Readers and files are not read beyond this limit.
+    pub fn max_input_bytes(mut self, max: usize) -> DeserializeOptions {
+        self.max_input_bytes = Some(max);
+        self
     }
 
-    fn assert_deserialized_any(expected: &Wizard, s: &str) {
-        let deserialized: Wizard = from_str_any(s).unwrap();
-        assert_eq!(&deserialized, expected);
+    /// Reject sequences and maps nested more than `max` levels deep
+    ///
+    /// Structs, tuples and enum variants with fields count as maps or sequences.
+    pub fn max_depth(mut self, max: usize) -> DeserializeOptions {
+        self.max_depth = Some(max);
+        self
+    }
 
-        let deserialized_from_bytes: Wizard = from_slice_any(s.as_bytes()).unwrap();
-        assert_eq!(&deserialized_from_bytes, expected);
+    /// Reject sequences and maps with more than `max` elements
+    pub fn max_collection_len(mut self, max: usize) -> DeserializeOptions {
+        self.max_collection_len = Some(max);
+        self
     }
 
-    #[test]
-    fn guess_from_json() {
-        let s = r#"{"name": "Radagast", "color": "Brown", "is_late": true, "age": 8000, friends: ["animals"]}"#;
-        assert_deserialized_any(&radagast(), s);
+    /// Reject inputs containing more than `max` values in total
+    ///
+    /// Every scalar, sequence, map and map key counts as a value.
+    /// This is the only limit that catches documents which expand to far more values than
+    /// their size suggests, such as YAML documents with recursively nested aliases.
+    pub fn max_values(mut self, max: usize) -> DeserializeOptions {
+        self.max_values = Some(max);
+        self
     }
 
-    #[test]
-    #[should_panic]
-    fn guess_from_json_fail() {
-        let s = r#"{"name" = "Radagast", "color": "Brown", "is_late": true, "age": 8000, friends: ["animals"],}"#;
-        assert_deserialized_any(&radagast(), s);
+    /// Reject maps which contain the same key more than once
+    ///
+    /// If enabled, [`Error::DuplicateKey`] is returned for the second occurrence of a key,
+    /// in the same way for all formats. The error includes the locations of both occurrences
+    /// for formats which report positions. JSON and RON report the end of the key rather than its start.
+    ///
+    /// Readers and files are read into memory in this mode, since finding the first occurrence
+    /// requires a second pass over the data.
+    ///
+    /// [`Error::DuplicateKey`]: ../error/enum.Error.html#variant.DuplicateKey
+    pub fn reject_duplicate_keys(mut self, reject: bool) -> DeserializeOptions {
+        self.reject_duplicate_keys = reject;
+        self
     }
 
-    #[test]
-    fn guess_from_yaml_inline() {
-        let s = r#"{name: Radagast, color: Brown, is_late: true, age: 8000, friends: [animals]}"#;
-        assert_deserialized_any(&radagast(), s);
+    /// Reject fields which are not used by the target type
+    ///
+    /// If enabled, [`Error::UnknownField`] is returned for the first value that the target type ignores,
+    /// such as a misspelled field of a struct. This works like `#[serde(deny_unknown_fields)]`,
+    /// but applies to all nested types, and can be decided at runtime.
+    ///
+    /// To collect the unknown fields instead of failing, use [`from_str_with_unused`].
+    ///
+    /// [`Error::UnknownField`]: ../error/enum.Error.html#variant.UnknownField
+    /// [`from_str_with_unused`]: fn.from_str_with_unused.html
+    pub fn deny_unknown_fields(mut self, deny: bool) -> DeserializeOptions {
+        self.deny_unknown_fields = deny;
+        self
     }
 
-    #[test]
-    fn guess_from_yaml_long() {
-        let s = "name: Radagast\ncolor: Brown\nis_late: true\nage: 8000\nfriends:\n- animals\n";
-        assert_deserialized_any(&radagast(), s);
+    /// Decode input in the specified encoding instead of detecting it
+    ///
+    /// By default, the encoding is detected with [`detect_encoding`], which recognizes UTF-8, UTF-16 and UTF-32,
+    /// and input which is not UTF-8 is transcoded before it is deserialized. A byte order mark is skipped
+    /// in both cases. Set the encoding for input which cannot be detected, such as Latin-1.
+    ///
+    /// This applies to byte slices, readers and files. Strings are always UTF-8.
+    /// If the input is not valid in the encoding, [`Error::InvalidEncoding`] is returned.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::collections::HashMap;
+    /// use serde_any::{DeserializeOptions, Encoding, Format};
+    ///
+    /// let options = DeserializeOptions::new().encoding(Encoding::Latin1);
+    /// let m: HashMap<String, String> = serde_any::from_slice_with(b"<a><b>caf\xe9</b></a>", Format::Xml, &options).unwrap();
+    /// assert_eq!(m["b"], "caf\u{e9}");
+    /// ```
+    ///
+    /// [`detect_encoding`]: ../encoding/fn.detect_encoding.html
+    /// [`Error::InvalidEncoding`]: ../error/enum.Error.html#variant.InvalidEncoding
+    pub fn encoding(mut self, encoding: Encoding) -> DeserializeOptions {
+        self.encoding = Some(encoding);
+        self
     }
 
-    #[test]
-    #[should_panic]
-    fn guess_from_yaml_long_fail() {
-        let s = "name: Radagast\ncolor: Brown\nis_late: true\nage: 8000\nfriends:\nanimals\n";
-        assert_deserialized_any(&radagast(), s);
+    /// Return the options for input which is already UTF-8
+    fn for_str(&self) -> DeserializeOptions {
+        self.clone().encoding(Encoding::Utf8)
     }
 
-    #[test]
-    fn guess_from_toml() {
-        let s = "name = \"Radagast\"\ncolor = \"Brown\"\nis_late = true\nage = 8000\nfriends = [\n  \"animals\",\n]\n";
-        assert_deserialized_any(&radagast(), s);
+    fn check_input_len(&self, len: usize) -> Result<(), Error> {
+        match self.max_input_bytes {
+            Some(max) if len > max => Err(Error::LimitExceeded {
+                limit: Limit::InputBytes,
+                max,
+            }),
+            _ => Ok(()),
+        }
     }
 
-    #[test]
-    fn guess_from_ron() {
-        let s = "Wizard (name: \"Radagast\", color: \"Brown\", is_late: true, age: 8000, friends: [\"animals\",],)";
-        assert_deserialized_any(&radagast(), s);
+    /// Read all data from `reader`, but not more than the input size limit
+    fn read_all<R: Read>(&self, reader: R) -> Result<Vec<u8>, Error> {
+        let mut data = Vec::new();
+        match self.max_input_bytes {
+            Some(max) => {
+                reader.take(max as u64 + 1).read_to_end(&mut data)?;
+                self.check_input_len(data.len())?;
+            }
+            None => {
+                let mut reader = reader;
+                reader.read_to_end(&mut data)?;
+            }
+        }
+        Ok(data)
     }
+}
 
-    #[test]
-    fn invalid_data() {
-        let s = "invalid {} data [] that cannot <> be parsed by any format !!";
+/// Deserialize from an IO stream using a specified format and options
+///
+/// This works like [`from_reader`], with [`DeserializeOptions`] limiting what is accepted.
+/// If an input size limit is set, the data is read into memory before deserializing it.
+///
+/// # Errors
+///
+/// Errors are the same as for [`from_reader`].
+/// If the data violates the options, [`Error::LimitExceeded`] or [`Error::DuplicateKey`] is returned.
+///
+/// [`from_reader`]: fn.from_reader.html
+/// [`DeserializeOptions`]: struct.DeserializeOptions.html
+/// [`Error::LimitExceeded`]: ../error/enum.Error.html#variant.LimitExceeded
+/// [`Error::DuplicateKey`]: ../error/enum.Error.html#variant.DuplicateKey
+///
+#[allow(unreachable_patterns)]
+pub fn from_reader_with<T, R>(mut reader: R, format: Format, options: &DeserializeOptions) -> Result<T, Error>
+where
+    T: DeserializeOwned,
+    R: Read,
+{
+    // Locating both occurrences of a duplicate key requires reading the data twice
+    if options.max_input_bytes.is_some() || options.reject_duplicate_keys {
+        let data = options.read_all(reader)?;
+        return from_slice_with(&data, format, options);
+    }
 
-        assert_matches!(from_str_any::<Wizard>(&s), Err(Error::NoSuccessfulParse(_)));
-        assert_matches!(
-            from_slice_any::<Wizard>(s.as_bytes()),
-            Err(Error::NoSuccessfulParse(_))
-        );
+    // Only UTF-8 can be streamed to the backends, other encodings are transcoded as a whole
+    let mut prefix = [0; 4];
+    let len = read_prefix(&mut reader, &mut prefix)?;
+    let encoding = options.encoding.unwrap_or_else(|| detect_encoding(&prefix[..len]));
+    if encoding != Encoding::Utf8 {
+        let data = options.read_all((&prefix[..len]).chain(reader))?;
+        return from_slice_with(&data, format, options);
     }
+    let reader = (&prefix[encoding.bom_len(&prefix[..len])..len]).chain(reader);
 
-    #[test]
+    match format {
+        #[cfg(feature = "yaml")]
+        Format::Yaml => deserialize_with(serde_yaml::Deserializer::from_reader(reader), &Budget::new(options)),
+        #[cfg(feature = "json")]
+        Format::Json => {
+            let mut deserializer = serde_json::Deserializer::from_reader(reader);
+            let t = deserialize_with(&mut deserializer, &Budget::new(options))?;
+            deserializer.end()?;
+            Ok(t)
+        }
+        #[cfg(feature = "xml")]
+        Format::Xml => deserialize_with(&mut xml::Deserializer::new_from_reader(reader), &Budget::new(options)),
+
+        _ if format.is_supported() => {
+            let data = options.read_all(reader)?;
+            from_slice_with(&data, format, options)
+        }
+        _ => Err(Error::UnsupportedFormat(format)),
+    }
+}
+
+/// Deserialize from a string using a specified format and options
+///
+/// This works like [`from_str`], with [`DeserializeOptions`] limiting what is accepted.
+///
+/// # Errors
+///
+/// Errors are the same as for [`from_str`].
+/// If the data violates the options, [`Error::LimitExceeded`] or [`Error::DuplicateKey`] is returned.
+///
+/// [`from_str`]: fn.from_str.html
+/// [`DeserializeOptions`]: struct.DeserializeOptions.html
+/// [`Error::LimitExceeded`]: ../error/enum.Error.html#variant.LimitExceeded
+/// [`Error::DuplicateKey`]: ../error/enum.Error.html#variant.DuplicateKey
+///
+pub fn from_str_with<T>(s: &str, format: Format, options: &DeserializeOptions) -> Result<T, Error>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    from_slice_with(s.as_bytes(), format, &options.for_str())
+}
+
+/// Deserialize from a byte slice using a specified format and options
+///
+/// This works like [`from_slice`], with [`DeserializeOptions`] limiting what is accepted.
+///
+/// # Errors
+///
+/// Errors are the same as for [`from_slice`].
+/// If the data violates the options, [`Error::LimitExceeded`] or [`Error::DuplicateKey`] is returned.
+///
+/// [`from_slice`]: fn.from_slice.html
+/// [`DeserializeOptions`]: struct.DeserializeOptions.html
+/// [`Error::LimitExceeded`]: ../error/enum.Error.html#variant.LimitExceeded
+/// [`Error::DuplicateKey`]: ../error/enum.Error.html#variant.DuplicateKey
+///
+pub fn from_slice_with<T>(s: &[u8], format: Format, options: &DeserializeOptions) -> Result<T, Error>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    options.check_input_len(s.len())?;
+    from_slice_budget(s, format, &Budget::new(options))
+}
+
+fn from_slice_budget<T>(s: &[u8], format: Format, budget: &Budget) -> Result<T, Error>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    let decoded = decode(s, budget.encoding())?;
+    let s = &decoded[..];
+    slice_with(s, format, budget).map_err(|error| match Budget::locate_duplicate(budget) {
+        // Deserializing again stops at the first occurrence of the key, which tells its location
+        Some(locate) => with_first_location(error, || {
+            slice_with::<T>(s, format, &locate).err().and_then(|e| e.location())
+        }),
+        None => error,
+    })
+}
+
+fn slice_with<T>(s: &[u8], format: Format, budget: &Budget) -> Result<T, Error>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    #[allow(unreachable_patterns)]
+    match format {
+        #[cfg(feature = "yaml")]
+        Format::Yaml => deserialize_with(serde_yaml::Deserializer::from_slice(s), budget),
+        #[cfg(feature = "json")]
+        Format::Json => {
+            let mut deserializer = serde_json::Deserializer::from_slice(s);
+            let t = deserialize_with(&mut deserializer, budget)?;
+            deserializer.end()?;
+            Ok(t)
+        }
+        #[cfg(feature = "toml")]
+        Format::Toml => {
+            let s = str::from_utf8(s).map_err(<toml::de::Error as ::serde::de::Error>::custom)?;
+            let mut deserializer = toml::Deserializer::new(s);
+            if budget.rejects_duplicate_keys() {
+                deserialize_guarded(&mut deserializer, budget).map_err(|error| toml_duplicate(error, s))
+            } else {
+                deserialize_with(&mut deserializer, budget)
+            }
+        }
+        #[cfg(feature = "ron")]
+        Format::Ron => {
+            let mut deserializer = ron::de::Deserializer::from_bytes(s)?;
+            let t = deserialize_with(&mut deserializer, budget).map_err(|error| {
+                if budget.rejects_duplicate_keys() {
+                    ron_duplicate(error, s, deserializer.remainder().len())
+                } else {
+                    error
+                }
+            })?;
+            deserializer.end()?;
+            Ok(t)
+        }
+        #[cfg(feature = "xml")]
+        Format::Xml => deserialize_with(&mut xml::Deserializer::new_from_reader(s), budget),
+        #[cfg(feature = "url")]
+        Format::Url => deserialize_with(url::Deserializer::new(form_urlencoded::parse(s)), budget),
+
+        _ => Err(Error::UnsupportedFormat(format)),
+    }
+}
+
+/// Deserialize from a string using any supported format and the specified options
+///
+/// This works like [`from_str_any`], with [`DeserializeOptions`] limiting what is accepted.
+///
+/// # Errors
+///
+/// Errors are the same as for [`from_str_any`].
+/// If a limit is exceeded, [`Error::LimitExceeded`] is returned immediately,
+/// without trying the remaining formats.
+///
+/// [`from_str_any`]: fn.from_str_any.html
+/// [`DeserializeOptions`]: struct.DeserializeOptions.html
+/// [`Error::LimitExceeded`]: ../error/enum.Error.html#variant.LimitExceeded
+///
+pub fn from_str_any_with<T>(s: &str, options: &DeserializeOptions) -> Result<T, Error>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    from_slice_any_with(s.as_bytes(), &options.for_str())
+}
+
+/// Deserialize from a byte slice using any supported format and the specified options
+///
+/// This works like [`from_slice_any`], with [`DeserializeOptions`] limiting what is accepted.
+///
+/// # Errors
+///
+/// Errors are the same as for [`from_slice_any`].
+/// If a limit is exceeded, [`Error::LimitExceeded`] is returned immediately,
+/// without trying the remaining formats.
+///
+/// [`from_slice_any`]: fn.from_slice_any.html
+/// [`DeserializeOptions`]: struct.DeserializeOptions.html
+/// [`Error::LimitExceeded`]: ../error/enum.Error.html#variant.LimitExceeded
+///
+pub fn from_slice_any_with<T>(s: &[u8], options: &DeserializeOptions) -> Result<T, Error>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    options.check_input_len(s.len())?;
+
+    if let Some(compression) = detect_compression(s) {
+        let data = options.read_all(decoder(compression, s)?)?;
+        return from_slice_any_with(&data, options);
+    }
+
+    let mut errors = Vec::new();
+
+    for format in supported_formats() {
+        match from_slice_with(s, format, options) {
+            Ok(t) => return Ok(t),
+            Err(err) => {
+                if exceeds_limit(&err) {
+                    return Err(err);
+                }
+                errors.push((format, err))
+            }
+        }
+    }
+
+    Err(Error::NoSuccessfulParse(NoSuccessfulParse::new(errors, Some(s))))
+}
+
+/// Deserialize from a file with the specified options
+///
+/// This works like [`from_file`], with [`DeserializeOptions`] limiting what is accepted.
+///
+/// # Errors
+///
+/// Errors are the same as for [`from_file`].
+/// If the data violates the options, [`Error::LimitExceeded`] or [`Error::DuplicateKey`] is returned.
+///
+/// [`from_file`]: fn.from_file.html
+/// [`DeserializeOptions`]: struct.DeserializeOptions.html
+/// [`Error::LimitExceeded`]: ../error/enum.Error.html#variant.LimitExceeded
+/// [`Error::DuplicateKey`]: ../error/enum.Error.html#variant.DuplicateKey
+///
+pub fn from_file_with<T, P>(path: P, options: &DeserializeOptions) -> Result<T, Error>
+where
+    T: DeserializeOwned,
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    read_file(path, |file| match guess_format(path) {
+        Some(format) => from_reader_with(file, format, options),
+        None => from_slice_any_with(&options.read_all(file)?, options),
+    })
+}
+
+/// Deserialize from a string, and return the paths of all fields that were not used
+///
+/// Fields which the target type ignores, such as misspelled field names that fall back to defaults,
+/// are collected and returned together with the value. The paths are formatted in the same way
+/// as in [`Error::Field`], for example `server.prot`. Only the outermost ignored value is reported,
+/// not the values nested inside it.
+///
+/// To reject such fields instead, use [`DeserializeOptions::deny_unknown_fields`].
+///
+/// # Errors
+///
+/// Errors are the same as for [`from_str`].
+///
+/// # Example
+///
+/// ```
+/// #[macro_use]
+/// extern crate serde;
+/// extern crate serde_any;
+///
+/// use serde_any::Format;
+///
+/// #[derive(Deserialize, Debug)]
+/// struct Server {
+///     #[serde(default)]
+///     port: u16,
+/// }
+///
+/// #[derive(Deserialize, Debug)]
+/// struct Config {
+///     server: Server,
+/// }
+///
+/// fn main() {
+///     let data = "[server]\nprot = 8080\n";
+///     let (config, unused) = serde_any::from_str_with_unused::<Config>(data, Format::Toml).unwrap();
+///     assert_eq!(config.server.port, 0);
+///     assert_eq!(unused, vec!["server.prot"]);
+/// }
+/// ```
+///
+/// [`from_str`]: fn.from_str.html
+/// [`Error::Field`]: ../error/enum.Error.html#variant.Field
+/// [`DeserializeOptions::deny_unknown_fields`]: struct.DeserializeOptions.html#method.deny_unknown_fields
+///
+pub fn from_str_with_unused<T>(s: &str, format: Format) -> Result<(T, Vec<String>), Error>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    from_slice_with_unused(s.as_bytes(), format)
+}
+
+/// Deserialize from a byte slice, and return the paths of all fields that were not used
+///
+/// This works like [`from_str_with_unused`].
+///
+/// # Errors
+///
+/// Errors are the same as for [`from_slice`].
+///
+/// [`from_str_with_unused`]: fn.from_str_with_unused.html
+/// [`from_slice`]: fn.from_slice.html
+///
+pub fn from_slice_with_unused<T>(s: &[u8], format: Format) -> Result<(T, Vec<String>), Error>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    let budget = Budget::new(&DeserializeOptions::default()).collect_unused();
+    let t = from_slice_budget(s, format, &budget)?;
+    Ok((t, budget.take_unused()))
+}
+
+/// Deserialize from a file, and return the paths of all fields that were not used
+///
+/// The format is detected in the same way as with [`from_file`],
+/// and unused fields are reported as with [`from_str_with_unused`].
+///
+/// # Errors
+///
+/// Errors are the same as for [`from_file`].
+///
+/// [`from_file`]: fn.from_file.html
+/// [`from_str_with_unused`]: fn.from_str_with_unused.html
+///
+pub fn from_file_with_unused<T, P>(path: P) -> Result<(T, Vec<String>), Error>
+where
+    T: DeserializeOwned,
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    read_file(path, |file| {
+        let data = DeserializeOptions::default().read_all(file)?;
+
+        if let Some(format) = guess_format(path) {
+            return from_slice_with_unused(&data, format);
+        }
+
+        let mut errors = Vec::new();
+        for format in supported_formats() {
+            match from_slice_with_unused(&data, format) {
+                Ok(t) => return Ok(t),
+                Err(err) => errors.push((format, err)),
+            }
+        }
+
+        Err(Error::NoSuccessfulParse(NoSuccessfulParse::new(errors, Some(&data))))
+    })
+}
+
+/// Open the file at `path` and read it with `read`, attaching the path to any error
+///
+/// If the name of the file ends with a compression suffix, `read` receives the decompressed data.
+fn read_file<T, F>(path: &Path, read: F) -> Result<T, Error>
+where
+    F: FnOnce(Box<dyn Read>) -> Result<T, Error>,
+{
+    open_file(path).and_then(read).map_err(|error| error.in_file(path))
+}
+
+fn exceeds_limit(error: &Error) -> bool {
+    match error {
+        Error::LimitExceeded { .. } => true,
+        Error::Field { error, .. } => exceeds_limit(error),
+        _ => false,
+    }
+}
+
+/// Deserialize while enforcing the options, keeping track of the current path
+fn deserialize_with<'de, D, T>(deserializer: D, budget: &Budget) -> Result<T, Error>
+where
+    D: Deserializer<'de>,
+    D::Error: Into<Error>,
+    T: Deserialize<'de>,
+{
+    deserialize_guarded(deserializer, budget).map_err(TrackedError::into_error)
+}
+
+/// Deserialize while enforcing the options, returning errors together with the path where they occurred
+fn deserialize_guarded<'de, D, T>(deserializer: D, budget: &Budget) -> Result<T, TrackedError>
+where
+    D: Deserializer<'de>,
+    D::Error: Into<Error>,
+    T: Deserialize<'de>,
+{
+    if !budget.is_guarded() {
+        return track(deserializer);
+    }
+
+    // The backend only sees a custom error message for violations, so replace it with the original error.
+    // The backend's error still tells where the violation happened.
+    track(Guard::new(deserializer, budget)).map_err(|TrackedError { mut path, error }| {
+        match budget.take_violation() {
+            Some(Error::DuplicateKey { key, first, .. }) => {
+                // The path does not include the key itself, because the error was raised while deserializing it
+                if let Some(None) = path.last() {
+                    path.pop();
+                }
+                path.push(Some(Segment::Key(key.clone())));
+                TrackedError {
+                    path,
+                    error: Box::new(Error::DuplicateKey {
+                        key,
+                        first,
+                        second: error.location(),
+                    }),
+                }
+            }
+            Some(violation) => TrackedError {
+                path,
+                error: Box::new(violation),
+            },
+            None => TrackedError { path, error },
+        }
+    })
+}
+
+/// An error from deserializing, together with the path to the value where it occurred
+struct TrackedError {
+    /// The segments of the path, with `None` for segments that are not known, such as keys being deserialized
+    path: Vec<Option<Segment>>,
+    error: Box<Error>,
+}
+
+impl TrackedError {
+    /// Wrap the error in [`Error::Field`], unless the path tells nothing about where the error is
+    ///
+    /// [`Error::Field`]: ../error/enum.Error.html#variant.Field
+    fn into_error(self) -> Error {
+        if self.path.iter().any(Option::is_some) {
+            Error::Field {
+                path: render_partial_path(&self.path),
+                error: self.error,
+            }
+        } else {
+            *self.error
+        }
+    }
+}
+
+fn with_first_location<F: FnOnce() -> Option<Location>>(error: Error, locate: F) -> Error {
+    match error {
+        Error::Field { path, error } => Error::Field {
+            path,
+            error: Box::new(with_first_location(*error, locate)),
+        },
+        Error::DuplicateKey {
+            key,
+            first: None,
+            second,
+        } => Error::DuplicateKey {
+            key,
+            first: locate(),
+            second,
+        },
+        error => error,
+    }
+}
+
+/// Add the location of the repeated key to an [`Error::DuplicateKey`] from RON
+///
+/// Custom errors from the RON backend have no position, but the deserializer stops right after the key,
+/// so the location is that of the last character of the key, as in JSON.
+///
+/// [`Error::DuplicateKey`]: ../error/enum.Error.html#variant.DuplicateKey
+#[cfg(feature = "ron")]
+fn ron_duplicate(error: Error, source: &[u8], remainder: usize) -> Error {
+    match error {
+        Error::Field { path, error } => Error::Field {
+            path,
+            error: Box::new(ron_duplicate(*error, source, remainder)),
+        },
+        Error::DuplicateKey {
+            key,
+            first,
+            second: None,
+        } => {
+            let second = str::from_utf8(source)
+                .ok()
+                .and_then(|source| Some((source, source.len().checked_sub(remainder)?.checked_sub(1)?)))
+                .filter(|&(source, offset)| source.is_char_boundary(offset))
+                .map(|(source, offset)| Location::at_offset(source, offset));
+            Error::DuplicateKey { key, first, second }
+        }
+        error => error,
+    }
+}
+
+/// Turn the errors the TOML backend reports for repeated keys and tables into [`Error::DuplicateKey`]
+///
+/// The backend reports these before any value is deserialized,
+/// so the locations of both occurrences are found by scanning the source.
+///
+/// [`Error::DuplicateKey`]: ../error/enum.Error.html#variant.DuplicateKey
+#[cfg(feature = "toml")]
+fn toml_duplicate(error: TrackedError, source: &str) -> Error {
+    let TrackedError { mut path, error } = error;
+    let error = match *error {
+        // Positions of custom errors point to the start of the table, so locate the keys from the path
+        Error::DuplicateKey { key, .. } => {
+            let (first, second) = toml_key_locations(source, &render_partial_path(&path), false);
+            Error::DuplicateKey { key, first, second }
+        }
+        Error::TomlDeserialize(ref e) if e.to_string().starts_with("redefinition of table `") => {
+            let message = e.to_string();
+            let table = message.split('`').nth(1).unwrap_or_default();
+            let (first, second) = toml_key_locations(source, table, true);
+            // A repeated table is reported while deserializing its first key
+            if let Some(None) = path.last() {
+                path.pop();
+            }
+            Error::DuplicateKey {
+                key: table.to_string(),
+                first,
+                second,
+            }
+        }
+        error => error,
+    };
+    TrackedError {
+        path,
+        error: Box::new(error),
+    }
+    .into_error()
+}
+
+/// Find the first and second definition of the key at `path`, or of the table header if `header` is true
+///
+/// The parser of `toml_edit` stops at the second definition, and the first one is found by deserializing
+/// the document up to there, failing at the key so that the error tells its span.
+#[cfg(all(feature = "toml", feature = "toml_edit"))]
+fn toml_key_locations(source: &str, path: &str, header: bool) -> (Option<Location>, Option<Location>) {
+    let second = match source.parse::<toml_edit::Document>() {
+        Err(e) if e.message().contains("duplicate key") => e.span().map(|span| span.start),
+        _ => None,
+    };
+    let second = match second {
+        Some(second) => second,
+        None => return (None, None),
+    };
+
+    let first = parse_path(path).ok().and_then(|segments| {
+        match source[..second]
+            .parse::<toml_edit::de::Deserializer>()
+            .and_then(|deserializer| TomlKeySeed { path: &segments }.deserialize(deserializer))
+        {
+            Err(ref e) if e.message() == TomlKeySeed::FOUND => e.span().map(|span| span.start),
+            _ => None,
+        }
+    });
+    // The span of a table header starts at the name of the table
+    let first = first.map(|first| match source[..first].rfind(['[', '\n']) {
+        Some(bracket) if header && source[bracket..].starts_with('[') => bracket,
+        _ => first,
+    });
+
+    (
+        first.map(|offset| Location::at_offset(source, offset)),
+        Some(Location::at_offset(source, second)),
+    )
+}
+
+#[cfg(all(feature = "toml", not(feature = "toml_edit")))]
+fn toml_key_locations(_source: &str, _path: &str, _header: bool) -> (Option<Location>, Option<Location>) {
+    (None, None)
+}
+
+/// Walks along a path through a TOML document, and fails when deserializing its last key
+///
+/// The error has no span, so the deserializer sets it to the span of the key.
+#[cfg(all(feature = "toml", feature = "toml_edit"))]
+struct TomlKeySeed<'a> {
+    path: &'a [Segment],
+}
+
+#[cfg(all(feature = "toml", feature = "toml_edit"))]
+impl<'a> TomlKeySeed<'a> {
+    const FOUND: &'static str = "found the key";
+}
+
+#[cfg(all(feature = "toml", feature = "toml_edit"))]
+impl<'a, 'de> DeserializeSeed<'de> for TomlKeySeed<'a> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+#[cfg(all(feature = "toml", feature = "toml_edit"))]
+impl<'a, 'de> Visitor<'de> for TomlKeySeed<'a> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a TOML value")
+    }
+
+    fn visit_bool<E>(self, _: bool) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_i64<E>(self, _: i64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_f64<E>(self, _: f64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_str<E>(self, _: &str) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        match self.path.split_first() {
+            Some((&Segment::Index(index), rest)) => {
+                for _ in 0..index {
+                    if seq.next_element::<IgnoredAny>()?.is_none() {
+                        return Ok(());
+                    }
+                }
+                seq.next_element_seed(TomlKeySeed { path: rest }).map(|_| ())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let (key, rest) = match self.path.split_first() {
+            Some((Segment::Key(key), rest)) => (key, rest),
+            _ => return Ok(()),
+        };
+        let last = if rest.is_empty() { Some(&key[..]) } else { None };
+        while let Some(current) = map.next_key_seed(TomlKeyName(last))? {
+            if current == *key {
+                return map.next_value_seed(TomlKeySeed { path: rest });
+            }
+            map.next_value::<IgnoredAny>()?;
+        }
+        Ok(())
+    }
+}
+
+/// Deserializes a key, failing if it is the given one
+#[cfg(all(feature = "toml", feature = "toml_edit"))]
+struct TomlKeyName<'a>(Option<&'a str>);
+
+#[cfg(all(feature = "toml", feature = "toml_edit"))]
+impl<'a, 'de> DeserializeSeed<'de> for TomlKeyName<'a> {
+    type Value = String;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<String, D::Error> {
+        let key = String::deserialize(deserializer)?;
+        if Some(&key[..]) == self.0 {
+            Err(de::Error::custom(TomlKeySeed::FOUND))
+        } else {
+            Ok(key)
+        }
+    }
+}
+
+/// Deserialize from a backend-specific deserializer, keeping track of the current path
+///
+/// If deserialization fails inside a nested value, the error is wrapped in [`Error::Field`]
+/// together with the path to that value.
+///
+/// [`Error::Field`]: ../error/enum.Error.html#variant.Field
+pub(crate) fn deserialize_tracked<'de, D, T>(deserializer: D) -> Result<T, Error>
+where
+    D: Deserializer<'de>,
+    D::Error: Into<Error>,
+    T: Deserialize<'de>,
+{
+    track(deserializer).map_err(TrackedError::into_error)
+}
+
+/// Deserialize, returning errors together with the path to the value that failed to deserialize
+fn track<'de, D, T>(deserializer: D) -> Result<T, TrackedError>
+where
+    D: Deserializer<'de>,
+    D::Error: Into<Error>,
+    T: Deserialize<'de>,
+{
+    serde_path_to_error::deserialize(deserializer).map_err(|e| {
+        let path = e
+            .path()
+            .iter()
+            .map(|segment| match segment {
+                serde_path_to_error::Segment::Seq { index } => Some(Segment::Index(*index)),
+                serde_path_to_error::Segment::Map { key } => Some(Segment::Key(key.clone())),
+                serde_path_to_error::Segment::Enum { variant } => Some(Segment::Key(variant.clone())),
+                serde_path_to_error::Segment::Unknown => None,
+            })
+            .collect();
+        TrackedError {
+            path,
+            error: Box::new(e.into_inner().into()),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize, PartialEq, Eq, Debug)]
+    pub struct Wizard {
+        pub name: String,
+        pub is_late: bool,
+        pub color: String,
+        pub age: u32,
+        pub friends: Vec<String>,
+    }
+
+    pub fn radagast() -> Wizard {
+        Wizard {
+            name: "Radagast".to_string(),
+            color: "Brown".to_string(),
+            is_late: true,
+            age: 8000,
+            friends: vec!["animals".to_string()],
+        }
+    }
+
+    fn assert_deserialized_any(expected: &Wizard, s: &str) {
+        let deserialized: Wizard = from_str_any(s).unwrap();
+        assert_eq!(&deserialized, expected);
+
+        let deserialized_from_bytes: Wizard = from_slice_any(s.as_bytes()).unwrap();
+        assert_eq!(&deserialized_from_bytes, expected);
+    }
+
+    #[test]
+    fn guess_from_json() {
+        let s = r#"{"name": "Radagast", "color": "Brown", "is_late": true, "age": 8000, friends: ["animals"]}"#;
+        assert_deserialized_any(&radagast(), s);
+    }
+
+    #[test]
+    #[should_panic]
+    fn guess_from_json_fail() {
+        let s = r#"{"name" = "Radagast", "color": "Brown", "is_late": true, "age": 8000, friends: ["animals"],}"#;
+        assert_deserialized_any(&radagast(), s);
+    }
+
+    #[test]
+    fn guess_from_yaml_inline() {
+        let s = r#"{name: Radagast, color: Brown, is_late: true, age: 8000, friends: [animals]}"#;
+        assert_deserialized_any(&radagast(), s);
+    }
+
+    #[test]
+    fn guess_from_yaml_long() {
+        let s = "name: Radagast\ncolor: Brown\nis_late: true\nage: 8000\nfriends:\n- animals\n";
+        assert_deserialized_any(&radagast(), s);
+    }
+
+    #[test]
+    #[should_panic]
+    fn guess_from_yaml_long_fail() {
+        let s = "name: Radagast\ncolor: Brown\nis_late: true\nage: 8000\nfriends:\nanimals\n";
+        assert_deserialized_any(&radagast(), s);
+    }
+
+    #[test]
+    fn guess_from_toml() {
+        let s = "name = \"Radagast\"\ncolor = \"Brown\"\nis_late = true\nage = 8000\nfriends = [\n  \"animals\",\n]\n";
+        assert_deserialized_any(&radagast(), s);
+    }
+
+    #[test]
+    fn guess_from_ron() {
+        let s = "Wizard (name: \"Radagast\", color: \"Brown\", is_late: true, age: 8000, friends: [\"animals\",],)";
+        assert_deserialized_any(&radagast(), s);
+    }
+
+    #[test]
+    fn invalid_data() {
+        let s = "invalid {} data [] that cannot <> be parsed by any format !!";
+
+        assert_matches!(from_str_any::<Wizard>(&s), Err(Error::NoSuccessfulParse(_)));
+        assert_matches!(
+            from_slice_any::<Wizard>(s.as_bytes()),
+            Err(Error::NoSuccessfulParse(_))
+        );
+    }
+
+    #[test]
     fn invalid_field_names() {
         let s = "name: Radagast\ncolor: Brown\nis_late: never\nage: 8000\n";
 
@@ -553,32 +1565,49 @@ mod tests {
         );
     }
 
+    fn file_error<T: ::std::fmt::Debug>(result: Result<T, Error>, path: &str) -> Error {
+        match result {
+            Err(Error::File { path: file, error }) => {
+                assert_eq!(file, Path::new(path));
+                *error
+            }
+            other => panic!("expected an error in {}, got {:?}", path, other),
+        }
+    }
+
     #[test]
     fn non_existing_file() {
-        assert_matches!(
-            from_file::<Wizard, _>("no_such_file.json"),
-            Err(Error::Io(_))
-        );
-        assert_matches!(
-            from_file::<Wizard, _>("no_such_file.yaml"),
-            Err(Error::Io(_))
-        );
-        assert_matches!(
-            from_file::<Wizard, _>("no_such_file.toml"),
-            Err(Error::Io(_))
-        );
-        assert_matches!(
-            from_file::<Wizard, _>("no_such_file.ron"),
-            Err(Error::Io(_))
-        );
+        let paths = ["no_such_file.json", "no_such_file.yaml", "no_such_file.toml", "no_such_file.ron", "no_such_file"];
+        for path in &paths {
+            assert_matches!(file_error(from_file::<Wizard, _>(path), path), Error::Io(_));
+        }
     }
 
     #[test]
     fn non_existing_file_stem() {
-        assert_matches!(
-            from_file_stem::<Wizard, _>("no_such_file_stem"),
-            Err(Error::NoSuccessfulParse(_))
-        );
+        match from_file_stem::<Wizard, _>("no_such_file_stem") {
+            Err(Error::NoFileFound { stem, tried }) => {
+                assert_eq!(stem, Path::new("no_such_file_stem"));
+                let compressions = ::compression::supported_compressions().len();
+                assert_eq!(tried.len(), ::format::supported_extensions().len() * (1 + compressions));
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn invalid_file_stem() {
+        let stem = ::std::env::temp_dir().join(format!("serde_any_invalid_{}", ::std::process::id()));
+        let path = stem.with_extension("yaml");
+        ::std::fs::write(&path, "name: Radagast\nage: old\n").unwrap();
+        let result = from_file_stem::<Wizard, _>(&stem);
+        ::std::fs::remove_file(&path).unwrap();
+
+        let error = result.unwrap_err();
+        assert_eq!(error.file(), Some(path.as_path()));
+        assert_eq!(error.format(), Some(Format::Yaml));
+        assert_eq!(error.path(), Some("age"));
+        assert_matches!(error, Error::File { .. });
     }
 
     #[test]
@@ -629,4 +1658,330 @@ mod tests {
             assert_matches!(v[3], (Format::Ron, Error::RonDeserialize(_)));
         }
     }
+
+    fn assert_limit<T: ::std::fmt::Debug>(result: Result<T, Error>, expected: Limit) {
+        match result {
+            Err(Error::LimitExceeded { limit, .. }) => assert_eq!(limit, expected),
+            Err(Error::Field { error, .. }) => assert_limit::<T>(Err(*error), expected),
+            other => panic!("expected {} limit to be exceeded, got {:?}", expected, other),
+        }
+    }
+
+    #[test]
+    fn input_size_limit() {
+        let s = "name: Radagast\ncolor: Brown\nis_late: true\nage: 8000\nfriends:\n- animals\n";
+        let options = DeserializeOptions::new().max_input_bytes(16);
+
+        assert_limit(from_str_with::<Wizard>(s, Format::Yaml, &options), Limit::InputBytes);
+        assert_limit(from_slice_any_with::<Wizard>(s.as_bytes(), &options), Limit::InputBytes);
+        assert_limit(from_reader_with::<Wizard, _>(s.as_bytes(), Format::Yaml, &options), Limit::InputBytes);
+
+        let options = DeserializeOptions::new().max_input_bytes(s.len());
+        assert_eq!(from_reader_with::<Wizard, _>(s.as_bytes(), Format::Yaml, &options).unwrap(), radagast());
+    }
+
+    #[test]
+    fn depth_limit() {
+        let options = DeserializeOptions::new().max_depth(2);
+        let nested = [
+            (Format::Json, "[[[1]]]"),
+            (Format::Yaml, "- - - 1"),
+            (Format::Toml, "a = [[1]]"),
+            (Format::Ron, "[[[1]]]"),
+        ];
+
+        for &(format, s) in &nested {
+            assert_limit(from_str_with::<::serde::de::IgnoredAny>(s, format, &options), Limit::Depth);
+        }
+        assert_eq!(from_str_with::<Vec<Vec<u32>>>("[[1]]", Format::Json, &options).unwrap(), vec![vec![1]]);
+    }
+
+    #[test]
+    fn collection_length_limit() {
+        let options = DeserializeOptions::new().max_collection_len(4);
+        let s = "name: Radagast\ncolor: Brown\nis_late: true\nage: 8000\nfriends:\n- animals\n";
+
+        assert_limit(from_str_with::<Wizard>(s, Format::Yaml, &options), Limit::CollectionLength);
+        assert_limit(from_str_with::<Vec<u32>>("[1, 2, 3, 4, 5]", Format::Json, &options), Limit::CollectionLength);
+        assert_eq!(from_str_with::<Vec<u32>>("[1, 2, 3, 4]", Format::Json, &options).unwrap(), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn value_limit() {
+        let options = DeserializeOptions::new().max_values(5);
+
+        assert_limit(from_str_with::<Vec<Vec<u32>>>("[[1, 2], [3]]", Format::Json, &options), Limit::Values);
+        assert_eq!(from_str_with::<Vec<u32>>("[1, 2, 3]", Format::Json, &options).unwrap(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn limit_stops_guessing() {
+        let options = DeserializeOptions::new().max_depth(1);
+        let result = from_str_any_with::<Vec<Vec<u32>>>("[[1]]", &options);
+
+        assert_limit(result, Limit::Depth);
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        use std::collections::HashMap;
+
+        let options = DeserializeOptions::new().reject_duplicate_keys(true);
+        let duplicates = [
+            (Format::Json, "{\"a\": 1,\n\"b\": 2,\n\"a\": 3}"),
+            (Format::Yaml, "a: 1\nb: 2\na: 3\n"),
+            (Format::Toml, "a = 1\nb = 2\na = 3\n"),
+            (Format::Ron, "{\"a\": 1, \"b\": 2, \"a\": 3}"),
+            (Format::Url, "a=1&b=2&a=3"),
+        ];
+
+        for &(format, s) in &duplicates {
+            assert_eq!(from_str::<HashMap<String, u32>>(s, format).unwrap()["a"], 3);
+            let error = from_str_with::<HashMap<String, u32>>(s, format, &options).unwrap_err();
+            assert_eq!(error.path(), Some("a"), "{}", format);
+            assert_eq!(error.to_string(), "a: Duplicate key a");
+        }
+
+        let s = "{\"name\": \"Radagast\", \"wizard\": {\"age\": 1, \"age\": 2}}";
+        let error = from_str_with::<::serde::de::IgnoredAny>(s, Format::Json, &options).unwrap_err();
+        assert_eq!(error.path(), Some("wizard.age"));
+
+        // Keys which look like the placeholder for unknown segments are kept as they are
+        let s = "{\"?\": {\"?\": 1, \"?\": 2}}";
+        let error = from_str_with::<HashMap<String, HashMap<String, u32>>>(s, Format::Json, &options).unwrap_err();
+        assert_eq!(error.path(), Some("?.?"));
+        let s = "{\"?\": [1, 2, 3]}";
+        let options = DeserializeOptions::new().max_collection_len(2);
+        let error = from_str_with::<HashMap<String, Vec<u32>>>(s, Format::Json, &options).unwrap_err();
+        assert_eq!(error.path(), Some("?"));
+    }
+
+    type Duplicate = (String, Option<(usize, usize)>, Option<(usize, usize)>);
+
+    fn duplicate_locations(error: Error) -> Duplicate {
+        match error {
+            Error::Field { error, .. } => duplicate_locations(*error),
+            Error::DuplicateKey { key, first, second } => (
+                key,
+                first.map(|l| (l.line, l.column)),
+                second.map(|l| (l.line, l.column)),
+            ),
+            other => panic!("Expected DuplicateKey, got {:?}", other),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "toml_edit")]
+    fn duplicate_key_locations() {
+        use std::collections::HashMap;
+
+        type Tables = HashMap<String, HashMap<String, u32>>;
+        let options = DeserializeOptions::new().reject_duplicate_keys(true);
+
+        let s = "{\"t\": {\"x\": 1},\n \"u\": {\"x\": 2,\n  \"x\": 3}}";
+        let error = from_str_with::<Tables>(s, Format::Json, &options).unwrap_err();
+        assert_eq!(error.path(), Some("u.x"));
+        assert_eq!(duplicate_locations(error), ("x".to_string(), Some((2, 10)), Some((3, 5))));
+
+        let s = "t:\n  x: 1\nu:\n  x: 2\n  x: 3\n";
+        let error = from_str_with::<Tables>(s, Format::Yaml, &options).unwrap_err();
+        assert_eq!(error.path(), Some("u.x"));
+        assert_eq!(duplicate_locations(error), ("x".to_string(), Some((4, 3)), Some((5, 3))));
+
+        let s = "[t]\nx = 1\n\n[u]\nx = 2\ny = 3\n  x = 4\n";
+        let error = from_str_with::<Tables>(s, Format::Toml, &options).unwrap_err();
+        assert_eq!(error.path(), Some("u.x"));
+        assert_eq!(duplicate_locations(error), ("x".to_string(), Some((5, 1)), Some((7, 3))));
+
+        let s = "[t]\nx = 1\n\n[u]\n\n[t]\ny = 2\n";
+        let error = from_str_with::<Tables>(s, Format::Toml, &options).unwrap_err();
+        assert_eq!(error.path(), Some("t"));
+        assert_eq!(duplicate_locations(error), ("t".to_string(), Some((1, 1)), Some((6, 1))));
+
+        let s = "s = \"\"\"\na = 0\n\"\"\"\na = 1\na = 2\n";
+        let error = from_str_with::<HashMap<String, ::value::Value>>(s, Format::Toml, &options).unwrap_err();
+        assert_eq!(duplicate_locations(error), ("a".to_string(), Some((4, 1)), Some((5, 1))));
+
+        let s = "[[servers]]\nport = 1\n\n[[servers]]\nport = 2\n  port = 3\n";
+        let error =
+            from_str_with::<HashMap<String, Vec<HashMap<String, u32>>>>(s, Format::Toml, &options).unwrap_err();
+        assert_eq!(error.path(), Some("servers[1].port"));
+        assert_eq!(duplicate_locations(error), ("port".to_string(), Some((5, 1)), Some((6, 3))));
+
+        let s = "{\"t\": {\"x\": 1},\n \"u\": {\"x\": 2,\n  \"x\": 3}}";
+        let error = from_str_with::<Tables>(s, Format::Ron, &options).unwrap_err();
+        assert_eq!(error.path(), Some("u.x"));
+        assert_eq!(duplicate_locations(error), ("x".to_string(), Some((2, 10)), Some((3, 5))));
+    }
+
+    #[test]
+    fn duplicate_keys_from_reader() {
+        use std::collections::HashMap;
+
+        let options = DeserializeOptions::new().reject_duplicate_keys(true);
+        let error = from_reader_with::<HashMap<String, u32>, _>(&b"a: 1\na: 2\n"[..], Format::Yaml, &options)
+            .unwrap_err();
+        assert_eq!(duplicate_locations(error), ("a".to_string(), Some((1, 1)), Some((2, 1))));
+    }
+
+
+    #[derive(Deserialize, PartialEq, Eq, Debug)]
+    struct Server {
+        #[serde(default)]
+        host: String,
+        #[serde(default)]
+        port: u16,
+    }
+
+    #[derive(Deserialize, PartialEq, Eq, Debug)]
+    struct Settings {
+        servers: Vec<Server>,
+        #[serde(default)]
+        debug: bool,
+    }
+
+    #[derive(Deserialize, PartialEq, Eq, Debug)]
+    struct Flat {
+        #[serde(default)]
+        name: String,
+    }
+
+    #[test]
+    fn unused_fields() {
+        let sources = [
+            (Format::Json, r#"{"servers": [{"host": "a", "prot": 1}], "verbose": {"level": 2}}"#),
+            (Format::Yaml, "servers:\n- host: a\n  prot: 1\nverbose:\n  level: 2\n"),
+            (Format::Toml, "verbose = { level = 2 }\n\n[[servers]]\nhost = \"a\"\nprot = 1\n"),
+            (Format::Ron, "(servers: [(host: \"a\", prot: 1)], verbose: (level: 2))"),
+        ];
+
+        for &(format, s) in &sources {
+            let (settings, mut unused) = from_str_with_unused::<Settings>(s, format).unwrap();
+            unused.sort();
+            assert_eq!(settings.servers[0].host, "a");
+            assert_eq!(unused, vec!["servers[0].prot", "verbose"], "{}", format);
+        }
+
+        let sources = [
+            (Format::Url, "name=a&nmae=b"),
+            (Format::Xml, "<Flat><name>a</name><nmae>b</nmae></Flat>"),
+        ];
+
+        for &(format, s) in &sources {
+            let (flat, unused) = from_str_with_unused::<Flat>(s, format).unwrap();
+            assert_eq!(flat.name, "a");
+            assert_eq!(unused, vec!["nmae"], "{}", format);
+        }
+
+        let (_, unused) = from_str_with_unused::<Flat>("name: a", Format::Yaml).unwrap();
+        assert!(unused.is_empty());
+    }
+
+    #[test]
+    fn unused_fields_from_file() {
+        let path = ::std::env::temp_dir().join(format!("serde_any_unused_{}.yaml", ::std::process::id()));
+        ::std::fs::write(&path, "name: a\nnmae: b\n").unwrap();
+        let result = from_file_with_unused::<Flat, _>(&path);
+        ::std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(result.unwrap(), (Flat { name: "a".to_string() }, vec!["nmae".to_string()]));
+    }
+
+    #[test]
+    fn deny_unknown_fields() {
+        let options = DeserializeOptions::new().deny_unknown_fields(true);
+
+        let s = r#"{"servers": [{"host": "a", "prot": 1}]}"#;
+        let error = from_str_with::<Settings>(s, Format::Json, &options).unwrap_err();
+        assert_eq!(error.path(), Some("servers[0].prot"));
+        assert_eq!(error.to_string(), "servers[0].prot: Unknown field prot");
+
+        let s = "servers:\n- host: a\n  port: 1\n";
+        assert!(from_str_with::<Settings>(s, Format::Yaml, &options).is_ok());
+    }
+
+    #[test]
+    fn ignored_values_are_skipped() {
+        // The JSON backend skips strings without decoding them, so it does not notice the invalid escape
+        let s = r#"{"name": "a", "skipped": ["\ud800"]}"#;
+        let (flat, unused) = from_str_with_unused::<Flat>(s, Format::Json).unwrap();
+        assert_eq!((flat.name.as_str(), unused), ("a", vec!["skipped".to_string()]));
+
+        let options = DeserializeOptions::new().max_values(3);
+        assert_limit(from_str_with::<Flat>(s, Format::Json, &options), Limit::Values);
+    }
+
+    #[test]
+    fn encodings() {
+        let json = r#"{"name": "Radagast", "is_late": true, "color": "Brown", "age": 8000, "friends": ["animals"]}"#;
+        let mut utf16 = vec![0xff, 0xfe];
+        utf16.extend(json.encode_utf16().flat_map(|u| u.to_le_bytes().to_vec()));
+
+        assert_eq!(from_slice::<Wizard>(&utf16, Format::Json).unwrap(), radagast());
+        assert_eq!(from_slice_any::<Wizard>(&utf16).unwrap(), radagast());
+        assert_eq!(from_reader::<Wizard, _>(&utf16[..], Format::Json).unwrap(), radagast());
+        assert_eq!(from_reader::<Wizard, _>(&utf16[2..], Format::Json).unwrap(), radagast());
+
+        let yaml = "\u{feff}name: Radagast\nis_late: true\ncolor: Brown\nage: 8000\nfriends: [animals]\n";
+        assert_eq!(from_str::<Wizard>(yaml, Format::Yaml).unwrap(), radagast());
+        assert_eq!(from_reader::<Wizard, _>(yaml.as_bytes(), Format::Yaml).unwrap(), radagast());
+
+        let options = DeserializeOptions::new().encoding(Encoding::Latin1);
+        let toml = b"name = \"Rad\xe1gast\"\nis_late = true\ncolor = \"Brown\"\nage = 8000\nfriends = []\n";
+        let wizard: Wizard = from_reader_with(&toml[..], Format::Toml, &options).unwrap();
+        assert_eq!(wizard.name, "Rad\u{e1}gast");
+        assert_matches!(from_slice::<Wizard>(toml, Format::Toml), Err(Error::TomlDeserialize(_)));
+
+        let error = from_slice::<Wizard>(&utf16[..utf16.len() - 1], Format::Json).unwrap_err();
+        assert_matches!(
+            error,
+            Error::InvalidEncoding {
+                encoding: Encoding::Utf16Le,
+                ..
+            }
+        );
+    }
+
+    #[derive(Deserialize, PartialEq, Debug)]
+    struct BorrowedWizard<'a> {
+        name: &'a str,
+        #[serde(borrow)]
+        color: ::std::borrow::Cow<'a, str>,
+        friends: Vec<&'a str>,
+    }
+
+    #[test]
+    fn borrowed() {
+        let inputs = [
+            (Format::Json, r#"{"name": "Radagast", "color": "Br\u006fwn", "friends": ["animals"]}"#),
+            (Format::Toml, "name = \"Radagast\"\ncolor = \"Br\\u006fwn\"\nfriends = [\"animals\"]\n"),
+        ];
+        for &(format, input) in &inputs {
+            let wizard: BorrowedWizard = from_str_borrowed(input, format).unwrap();
+            assert_eq!(wizard.name, "Radagast");
+            assert_eq!(wizard.color, "Brown");
+            assert_eq!(wizard.friends, vec!["animals"]);
+            assert!(input.as_ptr() <= wizard.name.as_ptr() && wizard.name.as_ptr() < input[input.len()..].as_ptr());
+        }
+
+        let url: ::std::collections::BTreeMap<&str, &str> = from_slice_borrowed(b"name=Radagast", Format::Url).unwrap();
+        assert_eq!(url["name"], "Radagast");
+        let bom: BorrowedWizard = from_slice_borrowed(
+            b"\xef\xbb\xbf{\"name\": \"Radagast\", \"color\": \"Brown\", \"friends\": []}",
+            Format::Json,
+        )
+        .unwrap();
+        assert_eq!(bom.name, "Radagast");
+
+        let escaped = from_str_borrowed::<BorrowedWizard>(r#"{"name": "R\u0061dagast"}"#, Format::Json).unwrap_err();
+        assert_eq!(escaped.path(), Some("name"));
+
+        for &format in &[Format::Yaml, Format::Ron, Format::Xml] {
+            assert!(!format.supports_borrowing());
+            assert_matches!(
+                from_str_borrowed::<BorrowedWizard>("", format),
+                Err(Error::UnsupportedBorrow(f)) if f == format
+            );
+        }
+    }
 }
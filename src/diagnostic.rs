@@ -0,0 +1,207 @@
+use std::fmt;
+
+use error::{Error, Location};
+
+/// A human-friendly rendering of an [`Error`] together with the source it came from
+///
+/// The diagnostic shows the error message, the file name and position of the error,
+/// a snippet of the offending line with a caret pointing to the exact spot,
+/// and the format that was used to parse the source.
+///
+/// If no format was able to parse the source, only the error from the most plausible
+/// format is shown, instead of listing the errors from all formats.
+///
+/// Diagnostics are created with [`Error::diagnostic`] and rendered with their `Display` implementation.
+///
+/// # Example
+///
+/// ```
+/// # use std::collections::HashMap;
+/// let source = "name = \"Bilbo\"\nage = = 111\n";
+/// let err = serde_any::from_str::<HashMap<String, String>>(source, serde_any::Format::Toml).unwrap_err();
+///
+/// let rendered = err.diagnostic(source, "hobbit.toml").to_string();
+/// assert!(rendered.contains("--> hobbit.toml:2:7"));
+/// assert!(rendered.contains("2 | age = = 111"));
+/// ```
+///
+/// [`Error`]: ../error/enum.Error.html
+/// [`Error::diagnostic`]: ../error/enum.Error.html#method.diagnostic
+pub struct Diagnostic<'a> {
+    error: &'a Error,
+    source: &'a str,
+    file_name: &'a str,
+}
+
+impl<'a> Diagnostic<'a> {
+    pub(crate) fn new(error: &'a Error, source: &'a str, file_name: &'a str) -> Diagnostic<'a> {
+        Diagnostic {
+            error,
+            source,
+            file_name,
+        }
+    }
+
+    fn fmt_error(&self, f: &mut fmt::Formatter, error: &Error) -> fmt::Result {
+        writeln!(f, "error: {}", error)?;
+
+        match error.location() {
+            Some(location) => {
+                writeln!(f, "  --> {}:{}:{}", self.file_name, location.line, location.column)?;
+                self.fmt_snippet(f, location)?;
+            }
+            None => writeln!(f, "  --> {}", self.file_name)?,
+        }
+
+        if let Some(first) = first_occurrence(error) {
+            writeln!(
+                f,
+                "   = note: first defined at {}:{}:{}",
+                self.file_name, first.line, first.column
+            )?;
+        }
+
+        if let Some(format) = error.format() {
+            writeln!(f, "   = format: {}", format)?;
+        }
+
+        Ok(())
+    }
+
+    fn fmt_snippet(&self, f: &mut fmt::Formatter, location: Location) -> fmt::Result {
+        let lines: Vec<&str> = self.source.lines().collect();
+        if location.line == 0 || location.line > lines.len() {
+            return Ok(());
+        }
+
+        let first = if location.line > 1 && !lines[location.line - 2].trim().is_empty() {
+            location.line - 1
+        } else {
+            location.line
+        };
+        let width = location.line.to_string().len();
+
+        writeln!(f, "{:width$} |", "", width = width)?;
+        for number in first..location.line + 1 {
+            writeln!(f, "{:>width$} | {}", number, lines[number - 1], width = width)?;
+        }
+
+        // Keep tabs in the padding so that the caret lines up with the offending character
+        let padding: String = lines[location.line - 1]
+            .chars()
+            .take(location.column.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        writeln!(f, "{:width$} | {}^", "", padding, width = width)
+    }
+}
+
+/// Return the location where a duplicate key was first defined
+fn first_occurrence(error: &Error) -> Option<Location> {
+    match error {
+        Error::DuplicateKey { first, .. } => *first,
+        Error::Field { error, .. } | Error::File { error, .. } => first_occurrence(error),
+        _ => None,
+    }
+}
+
+impl<'a> fmt::Display for Diagnostic<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.error {
+            Error::NoSuccessfulParse(errors) => match errors.most_likely() {
+                Some((format, error)) => {
+                    self.fmt_error(f, error)?;
+                    let others: Vec<String> = errors
+                        .iter()
+                        .map(|&(other, _)| other)
+                        .filter(|other| other != format)
+                        .map(|other| other.to_string())
+                        .collect();
+                    write!(
+                        f,
+                        "   = note: no format was able to parse the source, {} came the closest",
+                        format
+                    )?;
+                    if !others.is_empty() {
+                        write!(f, " (also tried {})", others.join(", "))?;
+                    }
+                    writeln!(f)
+                }
+                None => self.fmt_error(f, self.error),
+            },
+            error => self.fmt_error(f, error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use de::{from_str, from_str_any, from_str_with, DeserializeOptions};
+    use format::Format;
+    use std::collections::HashMap;
+
+    #[derive(Deserialize, Debug)]
+    struct Hobbit {
+        #[allow(dead_code)]
+        name: String,
+        #[allow(dead_code)]
+        age: u32,
+    }
+
+    #[test]
+    fn render_snippet() {
+        let source = "{\n  \"name\": \"Bilbo\",\n  \"age\": \"old\"\n}\n";
+        let err = from_str::<Hobbit>(source, Format::Json).unwrap_err();
+        let rendered = err.diagnostic(source, "bilbo.json").to_string();
+
+        let expected = "error: age: JSON error: invalid type: string \"old\", expected u32 at line 3 column 14
+  --> bilbo.json:3:14
+  |
+2 |   \"name\": \"Bilbo\",
+3 |   \"age\": \"old\"
+  |              ^
+   = format: Json
+";
+        assert_eq!(rendered, expected);
+    }
+
+    #[test]
+    fn render_without_location() {
+        let err = from_str::<Hobbit>("", Format::Url).unwrap_err();
+        let rendered = err.diagnostic("", "bilbo").to_string();
+
+        assert_eq!(rendered, format!("error: {}\n  --> bilbo\n   = format: Url\n", err));
+    }
+
+    #[test]
+    fn render_tabs() {
+        let source = "name: Bilbo\nage:\t\tnever\n";
+        let err = from_str::<Hobbit>(source, Format::Yaml).unwrap_err();
+        let rendered = err.diagnostic(source, "bilbo.yaml").to_string();
+
+        assert!(rendered.contains("2 | age:\t\tnever\n  |     \t\t^\n"), "{}", rendered);
+    }
+
+    #[test]
+    fn render_best_candidate() {
+        let source = "a = 1\nb = 2\nc = [1, 2\n";
+        let err = from_str_any::<HashMap<String, Vec<u32>>>(source).unwrap_err();
+        let rendered = err.diagnostic(source, "numbers").to_string();
+
+        assert!(rendered.starts_with("error: TOML deserialize error"), "{}", rendered);
+        assert!(rendered.contains("= note: no format was able to parse the source, Toml came the closest"));
+        assert!(!rendered.contains("JSON error"));
+        assert!(!rendered.contains("YAML error"));
+    }
+
+    #[test]
+    fn render_duplicate_key() {
+        let source = "name: Bilbo\nage: 111\nname: Frodo\n";
+        let options = DeserializeOptions::new().reject_duplicate_keys(true);
+        let err = from_str_with::<Hobbit>(source, Format::Yaml, &options).unwrap_err();
+        let rendered = err.diagnostic(source, "bilbo.yaml").to_string();
+
+        assert!(rendered.starts_with("error: name: Duplicate key name\n  --> bilbo.yaml:3:1\n"));
+        assert!(rendered.contains("   = note: first defined at bilbo.yaml:1:1\n"));
+    }
+}
@@ -0,0 +1,552 @@
+use std::fmt;
+use std::path::Path;
+
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+
+use compression::read_to_string;
+use de::from_str;
+use error::Error;
+use format::Format;
+use path::{render_path, Segment};
+use ser::format_from_path;
+use value::{KeySeed, Value};
+
+/// A single difference between two documents
+///
+/// Paths use the same syntax as paths in [`Error::Field`], such as `servers[0].port`,
+/// and are empty for the whole document.
+///
+/// [`Error::Field`]: ../error/enum.Error.html#variant.Field
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    /// A value exists only in the second document
+    Added {
+        /// Path to the value
+        path: String,
+        /// The added value
+        value: Value,
+    },
+    /// A value exists only in the first document
+    Removed {
+        /// Path to the value
+        path: String,
+        /// The removed value
+        value: Value,
+    },
+    /// A value is different in the two documents
+    Changed {
+        /// Path to the value
+        path: String,
+        /// The value in the first document
+        old: Value,
+        /// The value in the second document
+        new: Value,
+    },
+    /// A map has the same keys in a different order
+    ///
+    /// This is only reported if key order is not ignored, see [`DiffOptions::ignore_key_order`].
+    ///
+    /// [`DiffOptions::ignore_key_order`]: struct.DiffOptions.html#method.ignore_key_order
+    Reordered {
+        /// Path to the map
+        path: String,
+        /// The keys present in both documents, in the order of the first document
+        old: Vec<String>,
+        /// The keys present in both documents, in the order of the second document
+        new: Vec<String>,
+    },
+}
+
+impl Change {
+    /// Return the path to the changed value
+    pub fn path(&self) -> &str {
+        match self {
+            Change::Added { path, .. }
+            | Change::Removed { path, .. }
+            | Change::Changed { path, .. }
+            | Change::Reordered { path, .. } => path,
+        }
+    }
+}
+
+/// Renders the change as a single line, such as `~ server.port: 80 -> 8080`
+///
+/// Added values are marked with `+`, removed values with `-`, and changed or reordered values with `~`.
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let path = match self.path() {
+            "" => "(root)",
+            path => path,
+        };
+        match self {
+            Change::Added { value, .. } => write!(f, "+ {}: {}", path, value),
+            Change::Removed { value, .. } => write!(f, "- {}: {}", path, value),
+            Change::Changed { old, new, .. } => write!(f, "~ {}: {} -> {}", path, old, new),
+            Change::Reordered { old, new, .. } => {
+                write!(f, "~ {}: key order {:?} -> {:?}", path, old, new)
+            }
+        }
+    }
+}
+
+/// Render changes for showing to users, one change per line
+///
+/// # Example
+///
+/// ```
+/// use serde_any::Format;
+///
+/// # fn main() -> Result<(), serde_any::Error> {
+/// let deployed = "port: 80\nhosts: [a, b]\n";
+/// let generated = r#"{"port": 8080, "hosts": ["a"], "tls": true}"#;
+///
+/// let changes = serde_any::diff(deployed, Format::Yaml, generated, Format::Json)?;
+/// assert_eq!(
+///     serde_any::render_diff(&changes),
+///     "- hosts[1]: \"b\"\n~ port: 80 -> 8080\n+ tls: true\n"
+/// );
+/// # Ok(())
+/// # }
+/// ```
+pub fn render_diff(changes: &[Change]) -> String {
+    changes.iter().map(|change| format!("{}\n", change)).collect()
+}
+
+/// Options for comparing documents
+///
+/// # Example
+///
+/// ```
+/// use serde_any::{DiffOptions, Format};
+///
+/// # fn main() -> Result<(), serde_any::Error> {
+/// let options = DiffOptions::new().numeric_equivalence(true);
+/// let changes = serde_any::diff_with("a = 1", Format::Toml, r#"{"a": 1.0}"#, Format::Json, &options)?;
+/// assert!(changes.is_empty());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiffOptions {
+    ignore_key_order: bool,
+    numeric_equivalence: bool,
+}
+
+impl Default for DiffOptions {
+    fn default() -> DiffOptions {
+        DiffOptions {
+            ignore_key_order: true,
+            numeric_equivalence: false,
+        }
+    }
+}
+
+impl DiffOptions {
+    /// Create the default options
+    pub fn new() -> DiffOptions {
+        DiffOptions::default()
+    }
+
+    /// Ignore the order of keys in maps
+    ///
+    /// This is enabled by default. When disabled, maps whose common keys appear in a different order
+    /// are reported as [`Change::Reordered`]. Key order is only known when comparing sources,
+    /// not when comparing [`Value`]s with [`diff_values`].
+    ///
+    /// [`Change::Reordered`]: enum.Change.html#variant.Reordered
+    /// [`Value`]: ../value/enum.Value.html
+    /// [`diff_values`]: fn.diff_values.html
+    pub fn ignore_key_order(mut self, ignore: bool) -> DiffOptions {
+        self.ignore_key_order = ignore;
+        self
+    }
+
+    /// Treat integers and floats with the same value as equal, such as `1` and `1.0`
+    ///
+    /// This is disabled by default.
+    pub fn numeric_equivalence(mut self, equivalent: bool) -> DiffOptions {
+        self.numeric_equivalence = equivalent;
+        self
+    }
+}
+
+/// Compare two documents, each in its own format
+///
+/// Both documents are deserialized into a [`Value`], so the formats do not need to match.
+/// Maps are compared by key, and sequences are compared element by element,
+/// with extra elements at the end reported as added or removed.
+/// The changes are sorted by path, and a change to a value is not repeated for the values nested inside it.
+///
+/// # Errors
+///
+/// If either document cannot be parsed, the same error as from [`from_str`] is returned.
+///
+/// # Example
+///
+/// ```
+/// use serde_any::{Change, Format, Value};
+///
+/// # fn main() -> Result<(), serde_any::Error> {
+/// let changes = serde_any::diff("port: 80", Format::Yaml, "port = 8080", Format::Toml)?;
+/// assert_eq!(
+///     changes,
+///     vec![Change::Changed {
+///         path: "port".to_string(),
+///         old: Value::Int(80),
+///         new: Value::Int(8080),
+///     }]
+/// );
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`Value`]: ../value/enum.Value.html
+/// [`from_str`]: ../de/fn.from_str.html
+pub fn diff(a: &str, format_a: Format, b: &str, format_b: Format) -> Result<Vec<Change>, Error> {
+    diff_with(a, format_a, b, format_b, &DiffOptions::default())
+}
+
+/// Compare two documents, each in its own format, with the specified options
+///
+/// This works like [`diff`], with [`DiffOptions`] controlling which differences are reported.
+///
+/// # Errors
+///
+/// Errors are the same as for [`diff`].
+///
+/// [`diff`]: fn.diff.html
+/// [`DiffOptions`]: struct.DiffOptions.html
+pub fn diff_with(
+    a: &str,
+    format_a: Format,
+    b: &str,
+    format_b: Format,
+    options: &DiffOptions,
+) -> Result<Vec<Change>, Error> {
+    let value_a: Value = from_str(a, format_a)?;
+    let value_b: Value = from_str(b, format_b)?;
+
+    if options.ignore_key_order {
+        return Ok(diff_values(&value_a, &value_b, options));
+    }
+
+    let order_a: KeyOrder = from_str(a, format_a)?;
+    let order_b: KeyOrder = from_str(b, format_b)?;
+    let mut changes = Vec::new();
+    compare(
+        &value_a,
+        &value_b,
+        Some((&order_a, &order_b)),
+        &mut Vec::new(),
+        options,
+        &mut changes,
+    );
+    Ok(changes)
+}
+
+/// Compare two files, with the format of each inferred from its file name
+///
+/// # Errors
+///
+/// If the format cannot be inferred from a file name, [`Error::UnsupportedFileExtension`] is returned.
+/// If a file cannot be read, [`Error::Io`] is returned.
+/// Otherwise, errors are the same as for [`diff`].
+/// All of these errors are wrapped in [`Error::File`] with the path of the file that caused them.
+///
+/// [`diff`]: fn.diff.html
+/// [`Error::UnsupportedFileExtension`]: ../error/enum.Error.html#variant.UnsupportedFileExtension
+/// [`Error::Io`]: ../error/enum.Error.html#variant.Io
+/// [`Error::File`]: ../error/enum.Error.html#variant.File
+pub fn diff_files<P, Q>(a: P, b: Q, options: &DiffOptions) -> Result<Vec<Change>, Error>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let read = |path: &Path| -> Result<(String, Format), Error> {
+        let format = format_from_path(path)?;
+        let source = read_to_string(path)?;
+        // Parse each file on its own first, so that the error can be attributed to it
+        from_str::<Value>(&source, format)?;
+        Ok((source, format))
+    };
+    let (a, b) = (a.as_ref(), b.as_ref());
+    let (source_a, format_a) = read(a).map_err(|error| error.in_file(a))?;
+    let (source_b, format_b) = read(b).map_err(|error| error.in_file(b))?;
+
+    diff_with(&source_a, format_a, &source_b, format_b, options)
+}
+
+/// Compare two values
+///
+/// Values are compared in the same way as documents in [`diff`], except that
+/// key order is always ignored, because maps in a [`Value`] are sorted by key.
+///
+/// [`diff`]: fn.diff.html
+/// [`Value`]: ../value/enum.Value.html
+pub fn diff_values(a: &Value, b: &Value, options: &DiffOptions) -> Vec<Change> {
+    let mut changes = Vec::new();
+    compare(a, b, None, &mut Vec::new(), options, &mut changes);
+    changes
+}
+
+/// Compare two values at `path`, together with the order of their keys if it is not ignored
+fn compare(
+    a: &Value,
+    b: &Value,
+    orders: Option<(&KeyOrder, &KeyOrder)>,
+    path: &mut Vec<Segment>,
+    options: &DiffOptions,
+    changes: &mut Vec<Change>,
+) {
+    match (a, b) {
+        (Value::Map(map_a), Value::Map(map_b)) => {
+            if let Some((order_a, order_b)) = orders {
+                let common_a = order_a.common_keys(order_b);
+                let common_b = order_b.common_keys(order_a);
+                if common_a != common_b {
+                    changes.push(Change::Reordered {
+                        path: render_path(path),
+                        old: common_a,
+                        new: common_b,
+                    });
+                }
+            }
+
+            let mut keys: Vec<&String> = map_a.keys().chain(map_b.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                path.push(Segment::Key(key.clone()));
+                match (map_a.get(key), map_b.get(key)) {
+                    (Some(value_a), Some(value_b)) => {
+                        let orders = orders.map(|(order_a, order_b)| (order_a.key(key), order_b.key(key)));
+                        compare(value_a, value_b, orders, path, options, changes)
+                    }
+                    (Some(value), None) => changes.push(Change::Removed {
+                        path: render_path(path),
+                        value: value.clone(),
+                    }),
+                    (None, Some(value)) => changes.push(Change::Added {
+                        path: render_path(path),
+                        value: value.clone(),
+                    }),
+                    (None, None) => {}
+                }
+                path.pop();
+            }
+        }
+        (Value::Seq(seq_a), Value::Seq(seq_b)) => {
+            for index in 0..seq_a.len().max(seq_b.len()) {
+                path.push(Segment::Index(index));
+                match (seq_a.get(index), seq_b.get(index)) {
+                    (Some(value_a), Some(value_b)) => {
+                        let orders = orders.map(|(order_a, order_b)| (order_a.element(index), order_b.element(index)));
+                        compare(value_a, value_b, orders, path, options, changes)
+                    }
+                    (Some(value), None) => changes.push(Change::Removed {
+                        path: render_path(path),
+                        value: value.clone(),
+                    }),
+                    (None, Some(value)) => changes.push(Change::Added {
+                        path: render_path(path),
+                        value: value.clone(),
+                    }),
+                    (None, None) => {}
+                }
+                path.pop();
+            }
+        }
+        (a, b) if !scalars_equal(a, b, options) => changes.push(Change::Changed {
+            path: render_path(path),
+            old: a.clone(),
+            new: b.clone(),
+        }),
+        _ => {}
+    }
+}
+
+fn scalars_equal(a: &Value, b: &Value, options: &DiffOptions) -> bool {
+    match (a, b) {
+        (&Value::Int(i), &Value::Float(f)) | (&Value::Float(f), &Value::Int(i)) if options.numeric_equivalence => {
+            i as f64 == f
+        }
+        (&Value::UInt(u), &Value::Float(f)) | (&Value::Float(f), &Value::UInt(u)) if options.numeric_equivalence => {
+            u as f64 == f
+        }
+        (a, b) => a == b,
+    }
+}
+
+/// The order of keys in a document, with the keys of nested maps
+#[derive(Default)]
+struct KeyOrder {
+    keys: Vec<(String, KeyOrder)>,
+    elements: Vec<KeyOrder>,
+}
+
+static EMPTY: KeyOrder = KeyOrder {
+    keys: Vec::new(),
+    elements: Vec::new(),
+};
+
+impl KeyOrder {
+    fn key(&self, key: &str) -> &KeyOrder {
+        self.keys
+            .iter()
+            .find(|entry| entry.0 == key)
+            .map_or(&EMPTY, |entry| &entry.1)
+    }
+
+    fn element(&self, index: usize) -> &KeyOrder {
+        self.elements.get(index).unwrap_or(&EMPTY)
+    }
+
+    /// Return the keys which are also in `other`, in the order of this map
+    fn common_keys(&self, other: &KeyOrder) -> Vec<String> {
+        self.keys
+            .iter()
+            .filter(|entry| other.keys.iter().any(|other| other.0 == entry.0))
+            .map(|entry| entry.0.clone())
+            .collect()
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyOrder {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<KeyOrder, D::Error> {
+        deserializer.deserialize_any(KeyOrderVisitor)
+    }
+}
+
+struct KeyOrderVisitor;
+
+macro_rules! ignore_scalars {
+    ($($method:ident($($ty:ty)*);)*) => {
+        $(
+            fn $method<E: de::Error>(self $(, _: $ty)*) -> Result<KeyOrder, E> {
+                Ok(KeyOrder::default())
+            }
+        )*
+    };
+}
+
+impl<'de> Visitor<'de> for KeyOrderVisitor {
+    type Value = KeyOrder;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("any value")
+    }
+
+    ignore_scalars! {
+        visit_bool(bool);
+        visit_i64(i64);
+        visit_u64(u64);
+        visit_f64(f64);
+        visit_str(&str);
+        visit_bytes(&[u8]);
+        visit_none();
+        visit_unit();
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<KeyOrder, D::Error> {
+        KeyOrder::deserialize(deserializer)
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, deserializer: D) -> Result<KeyOrder, D::Error> {
+        KeyOrder::deserialize(deserializer)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<KeyOrder, A::Error> {
+        let mut order = KeyOrder::default();
+        while let Some(element) = seq.next_element()? {
+            order.elements.push(element);
+        }
+        Ok(order)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<KeyOrder, A::Error> {
+        let mut order = KeyOrder::default();
+        while let Some(key) = map.next_key_seed(KeySeed)? {
+            let value = map.next_value()?;
+            // Later duplicates replace the value but keep the position of the first occurrence, like in a `Value`
+            match order.keys.iter().position(|entry| entry.0 == key) {
+                Some(position) => order.keys[position].1 = value,
+                None => order.keys.push((key, value)),
+            }
+        }
+        Ok(order)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(changes: &[Change]) -> Vec<&str> {
+        changes.iter().map(Change::path).collect()
+    }
+
+    #[test]
+    fn changes_across_formats() {
+        let yaml = "name: app\nservers:\n  - host: a\n    port: 80\n  - host: b\n    port: 81\nratio: 1\n";
+        let json = r#"{"name": "app", "servers": [{"host": "a", "port": 8080}], "ratio": 1.0, "debug": false}"#;
+
+        let changes = diff(yaml, Format::Yaml, json, Format::Json).unwrap();
+        assert_eq!(paths(&changes), vec!["debug", "ratio", "servers[0].port", "servers[1]"]);
+        assert_matches!(
+            changes[0],
+            Change::Added {
+                value: Value::Bool(false),
+                ..
+            }
+        );
+        assert_matches!(changes[1], Change::Changed { old: Value::Int(1), .. });
+        assert_matches!(
+            changes[3],
+            Change::Removed {
+                value: Value::Map(_),
+                ..
+            }
+        );
+
+        let options = DiffOptions::new().numeric_equivalence(true);
+        let changes = diff_with(yaml, Format::Yaml, json, Format::Json, &options).unwrap();
+        assert_eq!(paths(&changes), vec!["debug", "servers[0].port", "servers[1]"]);
+
+        assert_eq!(
+            render_diff(&changes),
+            "+ debug: false\n~ servers[0].port: 80 -> 8080\n- servers[1]: {\"host\": \"b\", \"port\": 81}\n"
+        );
+        assert!(diff("a = 1", Format::Toml, "a: 1", Format::Yaml).unwrap().is_empty());
+    }
+
+    #[test]
+    fn key_order() {
+        let a = r#"{"x": {"a": 1, "b": 2, "c": 3}, "y": [{"p": 1, "q": 2}]}"#;
+        let b = "[x]\nb = 2\na = 1\n\n[[y]]\np = 1\nq = 2\n";
+
+        assert_eq!(paths(&diff(a, Format::Json, b, Format::Toml).unwrap()), vec!["x.c"]);
+
+        let options = DiffOptions::new().ignore_key_order(false);
+        let changes = diff_with(a, Format::Json, b, Format::Toml, &options).unwrap();
+        assert_eq!(paths(&changes), vec!["x", "x.c"]);
+        assert_eq!(
+            changes[0],
+            Change::Reordered {
+                path: "x".to_string(),
+                old: vec!["a".to_string(), "b".to_string()],
+                new: vec!["b".to_string(), "a".to_string()],
+            }
+        );
+        assert_eq!(changes[0].to_string(), r#"~ x: key order ["a", "b"] -> ["b", "a"]"#);
+    }
+
+    #[test]
+    fn toml_datetimes_are_strings() {
+        let toml = "created = 1979-05-27T07:32:00Z\n";
+        let json = r#"{"created": "1979-05-27T07:32:00Z"}"#;
+
+        assert!(diff(toml, Format::Toml, json, Format::Json).unwrap().is_empty());
+        let options = DiffOptions::new().ignore_key_order(false);
+        assert!(diff_with(toml, Format::Toml, json, Format::Json, &options).unwrap().is_empty());
+    }
+}
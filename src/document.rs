@@ -0,0 +1,882 @@
+use std::path::{Path, PathBuf};
+
+use serde::ser::Serialize;
+
+#[cfg(any(all(feature = "toml", feature = "toml_edit"), feature = "json"))]
+use backend::*;
+use compression::read_to_string;
+use de::from_str;
+use error::Error;
+use format::Format;
+use path::{parse_path, set_value};
+#[cfg(any(all(feature = "toml", feature = "toml_edit"), feature = "json"))]
+use path::{render_path, Segment};
+use ser::{format_from_path, to_string_with, write_file, SerializeOptions, WriteOptions};
+use value::{to_value, Value};
+
+/// A function called before a document is rewritten without preserving its layout
+pub type RewriteCallback = Box<dyn Fn(&Path, Format)>;
+
+/// The parsed contents of a document, in a representation that can be edited
+enum Contents {
+    /// A TOML document, edited in place
+    #[cfg(all(feature = "toml", feature = "toml_edit"))]
+    Toml(Box<toml_edit::Document>),
+    /// The source of a JSON document, edited by replacing the text of individual values
+    #[cfg(feature = "json")]
+    Json(String),
+    /// Data in a format that cannot be edited in place, which is serialized again when saved
+    Value(Value),
+}
+
+/// A data file that can be edited while keeping its comments and formatting
+///
+/// Values are set with paths in the same syntax as paths in [`Error::Field`], such as `servers[0].port`.
+/// Keys containing dots or brackets can be written in double quotes, such as `hosts."example.com"`.
+/// Maps along the path are created if they are missing, and a value can be appended to a sequence
+/// by using its length as the index.
+///
+/// TOML documents are edited with [`toml_edit`], which keeps comments, the order of keys and all formatting,
+/// and requires the `toml_edit` feature, enabled by default.
+/// JSON documents are edited by replacing only the text of the changed values.
+///
+/// Other formats cannot be edited in place. Their documents are deserialized into a [`Value`]
+/// and serialized again when saved, which loses comments and formatting and sorts map keys.
+/// Saving such a document fails unless this is allowed with [`allow_rewrite`] or [`on_rewrite`].
+///
+/// # Example
+///
+/// ```
+/// # use std::fs;
+/// use serde_any::Document;
+///
+/// # fn main() -> Result<(), serde_any::Error> {
+/// fs::write("server.toml", "[server]\n# The port to listen on\nport = 80  # default\n").unwrap();
+///
+/// Document::open("server.toml")?.set("server.port", 8080)?.save()?;
+///
+/// let saved = fs::read_to_string("server.toml").unwrap();
+/// assert_eq!(saved, "[server]\n# The port to listen on\nport = 8080  # default\n");
+/// # fs::remove_file("server.toml").unwrap();
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`Error::Field`]: ../error/enum.Error.html#variant.Field
+/// [`toml_edit`]: https://docs.rs/toml_edit
+/// [`Value`]: ../value/enum.Value.html
+/// [`allow_rewrite`]: struct.Document.html#method.allow_rewrite
+/// [`on_rewrite`]: struct.Document.html#method.on_rewrite
+pub struct Document {
+    path: PathBuf,
+    format: Format,
+    contents: Contents,
+    allow_rewrite: bool,
+    on_rewrite: Option<RewriteCallback>,
+}
+
+impl Document {
+    /// Open and parse a document, with the format inferred from the file name
+    ///
+    /// # Errors
+    ///
+    /// If the format cannot be inferred from the file name, [`Error::UnsupportedFileExtension`] is returned.
+    /// If the file cannot be read, [`Error::Io`] is returned.
+    /// If the file cannot be parsed, the same error as from [`from_str`] is returned.
+    /// All of these errors are wrapped in [`Error::File`] with the path of the file.
+    ///
+    /// [`Error::UnsupportedFileExtension`]: ../error/enum.Error.html#variant.UnsupportedFileExtension
+    /// [`Error::Io`]: ../error/enum.Error.html#variant.Io
+    /// [`Error::File`]: ../error/enum.Error.html#variant.File
+    /// [`from_str`]: ../de/fn.from_str.html
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Document, Error> {
+        let path = path.as_ref();
+        let open = || -> Result<Document, Error> {
+            let format = format_from_path(path)?;
+            let source = read_to_string(path)?;
+            Ok(Document {
+                path: path.to_path_buf(),
+                format,
+                contents: parse(source, format)?,
+                allow_rewrite: false,
+                on_rewrite: None,
+            })
+        };
+        open().map_err(|error| error.in_file(path))
+    }
+
+    /// Return the path of the file this document was opened from
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Return the format of this document
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Return whether comments and formatting are kept when this document is saved
+    pub fn preserves_layout(&self) -> bool {
+        !matches!(self.contents, Contents::Value(_))
+    }
+
+    /// Allow saving this document even if its comments and formatting cannot be preserved
+    ///
+    /// By default, [`save`] fails for such documents.
+    ///
+    /// [`save`]: struct.Document.html#method.save
+    pub fn allow_rewrite(&mut self) -> &mut Document {
+        self.allow_rewrite = true;
+        self
+    }
+
+    /// Set the function called before a document which cannot preserve its layout is saved
+    ///
+    /// This also allows such documents to be saved, as with [`allow_rewrite`].
+    ///
+    /// [`allow_rewrite`]: struct.Document.html#method.allow_rewrite
+    pub fn on_rewrite<F>(&mut self, f: F) -> &mut Document
+    where
+        F: Fn(&Path, Format) + 'static,
+    {
+        self.allow_rewrite = true;
+        self.on_rewrite = Some(Box::new(f));
+        self
+    }
+
+    /// Set the value at `path`
+    ///
+    /// If the value replaces an existing one, comments attached to the old value are kept.
+    ///
+    /// # Errors
+    ///
+    /// If the path is not valid, or does not fit the structure of the document,
+    /// for example because it refers to a key inside a string, [`Error::InvalidPath`] is returned.
+    ///
+    /// If the value cannot be represented in the format of the document, the format-specific [`Error`] variant
+    /// is returned, such as [`Error::TomlSerialize`] for a `None` in TOML.
+    ///
+    /// [`Error`]: ../error/enum.Error.html
+    /// [`Error::InvalidPath`]: ../error/enum.Error.html#variant.InvalidPath
+    /// [`Error::TomlSerialize`]: ../error/enum.Error.html#variant.TomlSerialize
+    pub fn set<T: Serialize>(&mut self, path: &str, value: T) -> Result<&mut Document, Error> {
+        let segments = parse_path(path).map_err(|reason| invalid_path(path, reason))?;
+        let value = to_value(&value)?;
+
+        match self.contents {
+            #[cfg(all(feature = "toml", feature = "toml_edit"))]
+            Contents::Toml(ref mut document) => {
+                toml_set_item(document.as_item_mut(), &segments, 0, value, false).map_err(|e| e.into_error(path))?
+            }
+            #[cfg(feature = "json")]
+            Contents::Json(ref mut source) => json_set(source, &segments, value).map_err(|e| e.into_error(path))?,
+            Contents::Value(ref mut root) => {
+                set_value(root, &segments, value).map_err(|reason| invalid_path(path, reason))?
+            }
+        }
+
+        Ok(self)
+    }
+
+    /// Return the contents of the document, as they would be saved
+    ///
+    /// # Errors
+    ///
+    /// If a document that cannot be edited in place fails to serialize, the format-specific [`Error`] variant
+    /// is returned.
+    ///
+    /// [`Error`]: ../error/enum.Error.html
+    pub fn to_string(&self) -> Result<String, Error> {
+        match self.contents {
+            #[cfg(all(feature = "toml", feature = "toml_edit"))]
+            Contents::Toml(ref document) => Ok(document.to_string()),
+            #[cfg(feature = "json")]
+            Contents::Json(ref source) => Ok(source.clone()),
+            // Sorting keys writes TOML tables after all other values of their table
+            Contents::Value(ref value) => to_string_with(value, self.format, &SerializeOptions::new().sort_keys(true)),
+        }
+    }
+
+    /// Write the document back to the file it was opened from
+    ///
+    /// The file is replaced atomically, in the same way as with [`to_file`].
+    /// If the layout of the document cannot be preserved, the function set with [`on_rewrite`]
+    /// is called first.
+    ///
+    /// # Errors
+    ///
+    /// If the layout of the document cannot be preserved and rewriting it was not allowed
+    /// with [`allow_rewrite`] or [`on_rewrite`], [`Error::RewriteNotAllowed`] is returned
+    /// and the file is left unchanged.
+    ///
+    /// If serialization or writing the file fails, the error is returned.
+    ///
+    /// All of these errors are wrapped in [`Error::File`] with the path of the file.
+    ///
+    /// [`to_file`]: ../ser/fn.to_file.html
+    /// [`allow_rewrite`]: struct.Document.html#method.allow_rewrite
+    /// [`on_rewrite`]: struct.Document.html#method.on_rewrite
+    /// [`Error::RewriteNotAllowed`]: ../error/enum.Error.html#variant.RewriteNotAllowed
+    /// [`Error::File`]: ../error/enum.Error.html#variant.File
+    pub fn save(&self) -> Result<(), Error> {
+        if !self.preserves_layout() && !self.allow_rewrite {
+            return Err(Error::RewriteNotAllowed(self.format).in_file(&self.path));
+        }
+
+        let contents = self.to_string().map_err(|error| error.in_file(&self.path))?;
+
+        if !self.preserves_layout() {
+            if let Some(ref on_rewrite) = self.on_rewrite {
+                on_rewrite(&self.path, self.format);
+            }
+        }
+
+        write_file(&self.path, contents.as_bytes(), &WriteOptions::default())
+            .map_err(|error| error.in_file(&self.path))
+    }
+}
+
+/// Parse the source of a document into an editable representation
+fn parse(source: String, format: Format) -> Result<Contents, Error> {
+    // Parse with the regular backend first, so that invalid documents are reported in the usual way
+    let value: Value = from_str(&source, format)?;
+
+    match format {
+        #[cfg(all(feature = "toml", feature = "toml_edit"))]
+        Format::Toml => Ok(Contents::Toml(Box::new(source.parse()?))),
+        #[cfg(feature = "json")]
+        Format::Json => Ok(Contents::Json(source)),
+        _ => Ok(Contents::Value(value)),
+    }
+}
+
+fn invalid_path(path: &str, reason: String) -> Error {
+    Error::InvalidPath {
+        path: path.to_string(),
+        reason,
+    }
+}
+
+/// An error while editing a document in place
+#[cfg(any(all(feature = "toml", feature = "toml_edit"), feature = "json"))]
+enum EditError {
+    /// The path does not fit the structure of the document
+    Path(String),
+    /// The value cannot be represented in the format
+    Value(Error),
+}
+
+#[cfg(any(all(feature = "toml", feature = "toml_edit"), feature = "json"))]
+impl EditError {
+    fn into_error(self, path: &str) -> Error {
+        match self {
+            EditError::Path(reason) => invalid_path(path, reason),
+            EditError::Value(error) => error,
+        }
+    }
+}
+
+/// Describe the value at the first `depth` segments of a path, for error messages
+#[cfg(any(all(feature = "toml", feature = "toml_edit"), feature = "json"))]
+fn describe(path: &[Segment], depth: usize) -> String {
+    if depth == 0 {
+        "the root".to_string()
+    } else {
+        format!("`{}`", render_path(&path[..depth]))
+    }
+}
+
+#[cfg(any(all(feature = "toml", feature = "toml_edit"), feature = "json"))]
+fn mismatch(path: &[Segment], depth: usize, found: &str) -> EditError {
+    let expected = match path[depth] {
+        Segment::Key(_) => "a map",
+        Segment::Index(_) => "a sequence",
+    };
+    EditError::Path(format!(
+        "expected {} at {}, found {}",
+        expected,
+        describe(path, depth),
+        found
+    ))
+}
+
+#[cfg(any(all(feature = "toml", feature = "toml_edit"), feature = "json"))]
+fn out_of_range(path: &[Segment], depth: usize, index: usize, len: usize) -> EditError {
+    EditError::Path(format!(
+        "index {} is out of range for {} with {} elements",
+        index,
+        describe(path, depth),
+        len
+    ))
+}
+
+/// Set the value at `path[depth..]` inside a TOML item
+///
+/// Missing maps are created as tables, or as inline tables inside inline values.
+#[cfg(all(feature = "toml", feature = "toml_edit"))]
+fn toml_set_item(
+    item: &mut toml_edit::Item,
+    path: &[Segment],
+    depth: usize,
+    value: Value,
+    inline: bool,
+) -> Result<(), EditError> {
+    use toml_edit::Item;
+
+    if depth == path.len() {
+        let replacement = toml_item(value, item, inline)?;
+        *item = replacement;
+        return Ok(());
+    }
+
+    if item.is_none() {
+        *item = if inline {
+            Item::Value(toml_edit::InlineTable::new().into())
+        } else {
+            let mut table = toml_edit::Table::new();
+            table.set_implicit(true);
+            Item::Table(table)
+        };
+    }
+
+    match (item, &path[depth]) {
+        (Item::Table(table), Segment::Key(key)) => {
+            let child = table.entry(key).or_insert(Item::None);
+            toml_set_item(child, path, depth + 1, value, false)
+        }
+        (Item::ArrayOfTables(array), &Segment::Index(index)) => {
+            if index == array.len() {
+                array.push(toml_edit::Table::new());
+            }
+            let len = array.len();
+            let table = array
+                .get_mut(index)
+                .ok_or_else(|| out_of_range(path, depth, index, len))?;
+            let mut child = Item::Table(table.clone());
+            toml_set_item(&mut child, path, depth + 1, value, false)?;
+            match child {
+                Item::Table(edited) => *table = edited,
+                _ => {
+                    return Err(EditError::Path(format!(
+                        "expected a table at {}",
+                        describe(path, depth + 1)
+                    )))
+                }
+            }
+            Ok(())
+        }
+        (Item::Value(inner), _) => toml_set_value(inner, path, depth, value),
+        (other, _) => Err(mismatch(path, depth, other.type_name())),
+    }
+}
+
+/// Set the value at `path[depth..]` inside an inline TOML value
+#[cfg(all(feature = "toml", feature = "toml_edit"))]
+fn toml_set_value(
+    target: &mut toml_edit::Value,
+    path: &[Segment],
+    depth: usize,
+    value: Value,
+) -> Result<(), EditError> {
+    use toml_edit::Value as TomlValue;
+
+    if depth == path.len() {
+        let decor = target.decor().clone();
+        *target = toml_value(value)?;
+        *target.decor_mut() = decor;
+        return Ok(());
+    }
+
+    match (target, &path[depth]) {
+        (TomlValue::InlineTable(table), Segment::Key(key)) => {
+            if !table.contains_key(key) {
+                table.insert(key, toml_edit::InlineTable::new().into());
+            }
+            let child = table.get_mut(key).expect("the key was just inserted");
+            toml_set_value(child, path, depth + 1, value)
+        }
+        (TomlValue::Array(array), &Segment::Index(index)) => {
+            if index == array.len() {
+                array.push(toml_edit::InlineTable::new());
+            }
+            let len = array.len();
+            let child = array
+                .get_mut(index)
+                .ok_or_else(|| out_of_range(path, depth, index, len))?;
+            toml_set_value(child, path, depth + 1, value)
+        }
+        (other, _) => Err(mismatch(path, depth, other.type_name())),
+    }
+}
+
+/// Convert a value to a TOML item replacing `existing`, keeping its kind and comments where possible
+#[cfg(all(feature = "toml", feature = "toml_edit"))]
+fn toml_item(value: Value, existing: &toml_edit::Item, inline: bool) -> Result<toml_edit::Item, EditError> {
+    use toml_edit::Item;
+
+    match (value, existing) {
+        (Value::Map(map), Item::Table(table)) => {
+            let mut replacement = toml_table(map)?;
+            *replacement.decor_mut() = table.decor().clone();
+            Ok(Item::Table(replacement))
+        }
+        (Value::Map(map), Item::None) if !inline => Ok(Item::Table(toml_table(map)?)),
+        (Value::Seq(ref elements), Item::ArrayOfTables(_)) if elements.iter().all(|e| matches!(e, Value::Map(_))) => {
+            let mut array = toml_edit::ArrayOfTables::new();
+            for element in elements.clone() {
+                if let Value::Map(map) = element {
+                    array.push(toml_table(map)?);
+                }
+            }
+            Ok(Item::ArrayOfTables(array))
+        }
+        (value, Item::Value(old)) => {
+            let mut replacement = toml_value(value)?;
+            *replacement.decor_mut() = old.decor().clone();
+            Ok(Item::Value(replacement))
+        }
+        (value, _) => Ok(Item::Value(toml_value(value)?)),
+    }
+}
+
+/// Convert a map to a TOML table, with nested maps as sub-tables
+#[cfg(all(feature = "toml", feature = "toml_edit"))]
+fn toml_table(map: ::value::Map) -> Result<toml_edit::Table, EditError> {
+    let mut table = toml_edit::Table::new();
+    for (key, value) in map {
+        let item = toml_item(value, &toml_edit::Item::None, false)?;
+        table.insert(&key, item);
+    }
+    Ok(table)
+}
+
+/// Convert a value to an inline TOML value
+#[cfg(all(feature = "toml", feature = "toml_edit"))]
+fn toml_value(value: Value) -> Result<toml_edit::Value, EditError> {
+    use serde::ser::Error as SerError;
+
+    Ok(match value {
+        Value::Null => {
+            return Err(EditError::Value(Error::TomlSerialize(
+                toml::ser::Error::UnsupportedNone,
+            )))
+        }
+        Value::Bool(b) => b.into(),
+        Value::Int(i) => i.into(),
+        Value::UInt(u) => {
+            let message = format!("integer {} is too large for TOML", u);
+            return Err(EditError::Value(Error::TomlSerialize(toml::ser::Error::custom(
+                message,
+            ))));
+        }
+        Value::Float(f) => f.into(),
+        Value::String(s) => s.into(),
+        Value::Seq(elements) => {
+            let mut array = toml_edit::Array::new();
+            for element in elements {
+                array.push(toml_value(element)?);
+            }
+            array.into()
+        }
+        Value::Map(map) => {
+            let mut table = toml_edit::InlineTable::new();
+            for (key, value) in map {
+                table.insert(&key, toml_value(value)?);
+            }
+            table.into()
+        }
+    })
+}
+
+/// A value in a JSON source, with the byte range of its text
+#[cfg(feature = "json")]
+struct JsonNode {
+    start: usize,
+    end: usize,
+    kind: JsonKind,
+}
+
+#[cfg(feature = "json")]
+enum JsonKind {
+    Object(Vec<(String, usize, JsonNode)>),
+    Array(Vec<JsonNode>),
+    Scalar(&'static str),
+}
+
+/// Scan the JSON value starting at `pos`, which must be valid
+#[cfg(feature = "json")]
+fn json_scan(source: &str, pos: usize) -> JsonNode {
+    let bytes = source.as_bytes();
+    let start = json_skip_whitespace(bytes, pos);
+
+    match bytes[start] {
+        b'{' => {
+            let mut entries = Vec::new();
+            let mut pos = json_skip_whitespace(bytes, start + 1);
+            while bytes[pos] != b'}' {
+                let key_end = json_string_end(bytes, pos);
+                let key = serde_json::from_str(&source[pos..key_end]).unwrap_or_default();
+                let colon = json_skip_whitespace(bytes, key_end);
+                let value = json_scan(source, colon + 1);
+                pos = json_skip_whitespace(bytes, value.end);
+                entries.push((key, key_end, value));
+                if bytes[pos] == b',' {
+                    pos = json_skip_whitespace(bytes, pos + 1);
+                }
+            }
+            JsonNode {
+                start,
+                end: pos + 1,
+                kind: JsonKind::Object(entries),
+            }
+        }
+        b'[' => {
+            let mut elements = Vec::new();
+            let mut pos = json_skip_whitespace(bytes, start + 1);
+            while bytes[pos] != b']' {
+                let element = json_scan(source, pos);
+                pos = json_skip_whitespace(bytes, element.end);
+                elements.push(element);
+                if bytes[pos] == b',' {
+                    pos = json_skip_whitespace(bytes, pos + 1);
+                }
+            }
+            JsonNode {
+                start,
+                end: pos + 1,
+                kind: JsonKind::Array(elements),
+            }
+        }
+        b'"' => JsonNode {
+            start,
+            end: json_string_end(bytes, start),
+            kind: JsonKind::Scalar("string"),
+        },
+        first => {
+            let mut end = start;
+            while end < bytes.len() && !b",]} \t\r\n".contains(&bytes[end]) {
+                end += 1;
+            }
+            let kind = match first {
+                b't' | b'f' => "boolean",
+                b'n' => "null",
+                _ if source[start..end].contains(&['.', 'e', 'E'][..]) => "float",
+                _ => "integer",
+            };
+            JsonNode {
+                start,
+                end,
+                kind: JsonKind::Scalar(kind),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+fn json_skip_whitespace(bytes: &[u8], mut pos: usize) -> usize {
+    while pos < bytes.len() && b" \t\r\n".contains(&bytes[pos]) {
+        pos += 1;
+    }
+    pos
+}
+
+/// Return the position after the string starting at `pos`
+#[cfg(feature = "json")]
+fn json_string_end(bytes: &[u8], pos: usize) -> usize {
+    let mut end = pos + 1;
+    while bytes[end] != b'"' {
+        end += if bytes[end] == b'\\' { 2 } else { 1 };
+    }
+    end + 1
+}
+
+/// Return the indentation of the line containing `pos`
+#[cfg(feature = "json")]
+fn json_line_indent(source: &str, pos: usize) -> &str {
+    let line_start = source[..pos].rfind('\n').map_or(0, |i| i + 1);
+    let line = &source[line_start..];
+    &line[..line.len() - line.trim_start_matches(&[' ', '\t'][..]).len()]
+}
+
+/// Return the indentation used for one level of nesting in the document
+#[cfg(feature = "json")]
+fn json_indent_unit(source: &str) -> String {
+    source
+        .lines()
+        .map(|line| &line[..line.len() - line.trim_start_matches(&[' ', '\t'][..]).len()])
+        .find(|indent| !indent.is_empty())
+        .unwrap_or("  ")
+        .to_string()
+}
+
+/// Render a value as JSON, continuing lines with `indent` if the document is pretty-printed
+#[cfg(feature = "json")]
+fn json_render(value: &Value, pretty: bool, unit: &str, indent: &str) -> Result<String, EditError> {
+    if !pretty {
+        return serde_json::to_string(value).map_err(|e| EditError::Value(e.into()));
+    }
+
+    let mut buffer = Vec::new();
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(unit.as_bytes());
+    let mut serializer = serde_json::Serializer::with_formatter(&mut buffer, formatter);
+    value
+        .serialize(&mut serializer)
+        .map_err(|e| EditError::Value(e.into()))?;
+    let rendered = String::from_utf8(buffer).expect("JSON output is valid UTF-8");
+    Ok(rendered.replace('\n', &format!("\n{}", indent)))
+}
+
+/// Set the value at `path` in a JSON source, replacing or inserting only the affected text
+#[cfg(feature = "json")]
+fn json_set(source: &mut String, path: &[Segment], value: Value) -> Result<(), EditError> {
+    let unit = json_indent_unit(source);
+    let root = json_scan(source, 0);
+    let mut node = &root;
+
+    for (depth, segment) in path.iter().enumerate() {
+        let child = match (&node.kind, segment) {
+            (JsonKind::Object(entries), Segment::Key(key)) => entries.iter().rev().find(|e| &e.0 == key).map(|e| &e.2),
+            (JsonKind::Array(elements), &Segment::Index(index)) if index > elements.len() => {
+                return Err(out_of_range(path, depth, index, elements.len()))
+            }
+            (JsonKind::Array(elements), &Segment::Index(index)) => elements.get(index),
+            (JsonKind::Object(_), _) => return Err(mismatch(path, depth, "map")),
+            (JsonKind::Array(_), _) => return Err(mismatch(path, depth, "sequence")),
+            (&JsonKind::Scalar(found), _) => return Err(mismatch(path, depth, found)),
+        };
+
+        match child {
+            Some(child) => node = child,
+            None => {
+                // Build the missing part of the path as a new value, and insert it into the current container
+                let mut inserted = Value::Null;
+                set_value(&mut inserted, &path[depth + 1..], value)
+                    .map_err(|reason| EditError::Path(format!("{} inside a new value", reason)))?;
+                json_insert(source, node, segment, &inserted, &unit)?;
+                return Ok(());
+            }
+        }
+    }
+
+    let pretty = source.trim().contains('\n');
+    let indent = json_line_indent(source, node.start).to_string();
+    let rendered = json_render(&value, pretty, &unit, &indent)?;
+    source.replace_range(node.start..node.end, &rendered);
+    Ok(())
+}
+
+/// Insert a new entry or element at the end of the object or array `container`
+#[cfg(feature = "json")]
+fn json_insert(
+    source: &mut String,
+    container: &JsonNode,
+    segment: &Segment,
+    value: &Value,
+    unit: &str,
+) -> Result<(), EditError> {
+    // Empty containers follow the layout of the whole document
+    let empty = match &container.kind {
+        JsonKind::Object(entries) => entries.is_empty(),
+        JsonKind::Array(elements) => elements.is_empty(),
+        JsonKind::Scalar(_) => false,
+    };
+    let pretty = source[container.start..container.end].contains('\n') || (empty && source.trim().contains('\n'));
+    let (last_end, last_start) = match &container.kind {
+        JsonKind::Object(entries) => match entries.last() {
+            Some((_, key_end, value)) => (
+                Some(value.end),
+                Some(
+                    source[..*key_end]
+                        .rfind('"')
+                        .map_or(0, |q| source[..q].rfind('"').unwrap_or(q)),
+                ),
+            ),
+            None => (None, None),
+        },
+        JsonKind::Array(elements) => match elements.last() {
+            Some(element) => (Some(element.end), Some(element.start)),
+            None => (None, None),
+        },
+        JsonKind::Scalar(_) => unreachable!("values can only be inserted into containers"),
+    };
+
+    let indent = match last_start {
+        Some(start) => json_line_indent(source, start).to_string(),
+        None => format!("{}{}", json_line_indent(source, container.start), unit),
+    };
+    let rendered = json_render(value, pretty, unit, &indent)?;
+    let entry = match segment {
+        Segment::Key(key) => format!(
+            "{}: {}",
+            serde_json::to_string(key).map_err(|e| EditError::Value(e.into()))?,
+            rendered
+        ),
+        Segment::Index(_) => rendered,
+    };
+
+    match last_end {
+        Some(end) if pretty => source.insert_str(end, &format!(",\n{}{}", indent, entry)),
+        Some(end) => source.insert_str(end, &format!(", {}", entry)),
+        None if pretty => {
+            let closing = json_line_indent(source, container.end - 1).to_string();
+            source.replace_range(
+                container.start + 1..container.end - 1,
+                &format!("\n{}{}\n{}", indent, entry, closing),
+            );
+        }
+        None => source.replace_range(container.start + 1..container.end - 1, &entry),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    fn temp_file(name: &str, contents: &str) -> PathBuf {
+        let path = ::std::env::temp_dir().join(format!("serde_any_document_{}_{}", ::std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn edit(name: &str, contents: &str, edits: &[(&str, Value)]) -> Result<String, Error> {
+        let path = temp_file(name, contents);
+        let result = Document::open(&path).and_then(|mut document| {
+            for (key, value) in edits {
+                document.set(key, value)?;
+            }
+            document.on_rewrite(|_, _| {}).save()
+        });
+        let saved = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        result.map(|_| saved)
+    }
+
+    #[test]
+    #[cfg(feature = "toml_edit")]
+    fn toml_keeps_comments() {
+        let source =
+            "# Server settings\n[server]\nhost = \"localhost\" # the host\nport = 80\n\n[[users]]\nname = \"a\"\n";
+        let saved = edit(
+            "keep.toml",
+            source,
+            &[
+                ("server.port", Value::Int(8080)),
+                ("server.host", Value::String("example.com".to_string())),
+                ("server.tls.enabled", Value::Bool(true)),
+                ("users[0].admin", Value::Bool(true)),
+                ("users[1].name", Value::String("b".to_string())),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            saved,
+            "# Server settings\n[server]\nhost = \"example.com\" # the host\nport = 8080\n\n[server.tls]\nenabled = true\n\n\
+             [[users]]\nname = \"a\"\nadmin = true\n\n[[users]]\nname = \"b\"\n"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "toml_edit")]
+    fn toml_errors() {
+        let error = edit("errors.toml", "a = 1\n", &[("a.b", Value::Int(2))]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid path a.b: expected a map at `a`, found integer"
+        );
+
+        let error = edit("none.toml", "a = 1\n", &[("a", Value::Null)]).unwrap_err();
+        assert_matches!(error, Error::TomlSerialize(_));
+    }
+
+    #[test]
+    fn json_keeps_layout() {
+        let source =
+            "{\n    \"name\": \"app\",\n    \"servers\": [\n        {\"port\": 80}\n    ],\n    \"empty\": {}\n}\n";
+        let saved = edit(
+            "layout.json",
+            source,
+            &[
+                ("servers[0].port", Value::Int(8080)),
+                ("servers[1]", Value::String("backup".to_string())),
+                ("empty.nested.flag", Value::Bool(false)),
+                ("version", Value::Int(2)),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            saved,
+            "{\n    \"name\": \"app\",\n    \"servers\": [\n        {\"port\": 8080},\n        \"backup\"\n    ],\n    \
+             \"empty\": {\n        \"nested\": {\n            \"flag\": false\n        }\n    },\n    \"version\": 2\n}\n"
+        );
+
+        let saved = edit(
+            "compact.json",
+            r#"{"a": [1, 2], "b": {}}"#,
+            &[("a[2]", Value::Int(3)), ("b.c", Value::Null)],
+        )
+        .unwrap();
+        assert_eq!(saved, r#"{"a": [1, 2, 3], "b": {"c": null}}"#);
+
+        let error = edit("errors.json", r#"{"a": [1]}"#, &[("a[3]", Value::Int(2))]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid path a[3]: index 3 is out of range for `a` with 1 elements"
+        );
+    }
+
+    #[test]
+    #[cfg(all(feature = "toml", not(feature = "toml_edit")))]
+    fn toml_without_toml_edit_keeps_tables_last() {
+        let path = temp_file("tables.toml", "zone = \"eu\"\n\n[server]\nport = 80\n");
+        let result = Document::open(&path).and_then(|mut document| {
+            document.allow_rewrite().set("server.port", 8080)?.save()
+        });
+        let saved = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        result.unwrap();
+        assert_eq!(saved, "zone = 'eu'\n\n[server]\nport = 8080\n");
+    }
+
+    #[test]
+    fn other_formats_are_rewritten() {
+        let path = temp_file("rewrite.yaml", "# comment\nb: 1\na: 2\n");
+        let warned = Rc::new(Cell::new(false));
+        let flag = warned.clone();
+
+        let mut document = Document::open(&path).unwrap();
+        assert!(!document.preserves_layout());
+        let error = document.set("b", 3).unwrap().save().unwrap_err();
+        assert_matches!(error, Error::File { ref error, .. } if matches!(**error, Error::RewriteNotAllowed(_)));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "# comment\nb: 1\na: 2\n");
+
+        document
+            .on_rewrite(move |_, format| flag.set(format == Format::Yaml))
+            .set("b", 3)
+            .unwrap()
+            .save()
+            .unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+
+        assert!(warned.get());
+        assert_eq!(
+            from_str::<Value>(&saved, Format::Yaml).unwrap().get("b"),
+            Some(&Value::Int(3))
+        );
+        assert!(!saved.contains("comment"));
+
+        Document::open(&path).unwrap().allow_rewrite().set("a", 4).unwrap().save().unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            from_str::<Value>(&saved, Format::Yaml).unwrap().get("a"),
+            Some(&Value::Int(4))
+        );
+    }
+}
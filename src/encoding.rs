@@ -0,0 +1,243 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{self, Read};
+
+use error::Error;
+
+/// Character encodings of input data
+///
+/// All formats are deserialized from UTF-8, so input in any other encoding is transcoded first.
+/// The encoding of input is detected with [`detect_encoding`], unless it is set explicitly with
+/// [`DeserializeOptions::encoding`].
+///
+/// [`detect_encoding`]: fn.detect_encoding.html
+/// [`DeserializeOptions::encoding`]: ../de/struct.DeserializeOptions.html#method.encoding
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    /// UTF-8, with or without a byte order mark
+    Utf8,
+    /// UTF-16, little endian
+    Utf16Le,
+    /// UTF-16, big endian
+    Utf16Be,
+    /// UTF-32, little endian
+    Utf32Le,
+    /// UTF-32, big endian
+    Utf32Be,
+    /// ISO-8859-1, where every byte is the Unicode code point with the same value
+    Latin1,
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+            Encoding::Utf32Le => "UTF-32LE",
+            Encoding::Utf32Be => "UTF-32BE",
+            Encoding::Latin1 => "ISO-8859-1",
+        };
+        f.write_str(name)
+    }
+}
+
+impl Encoding {
+    /// Return the byte order mark of this encoding, which is empty for Latin-1
+    pub fn bom(&self) -> &'static [u8] {
+        match self {
+            Encoding::Utf8 => &[0xef, 0xbb, 0xbf],
+            Encoding::Utf16Le => &[0xff, 0xfe],
+            Encoding::Utf16Be => &[0xfe, 0xff],
+            Encoding::Utf32Le => &[0xff, 0xfe, 0x00, 0x00],
+            Encoding::Utf32Be => &[0x00, 0x00, 0xfe, 0xff],
+            Encoding::Latin1 => &[],
+        }
+    }
+
+    /// Return the length of the byte order mark at the start of `data`, or 0 if there is none
+    pub(crate) fn bom_len(&self, data: &[u8]) -> usize {
+        if data.starts_with(self.bom()) {
+            self.bom().len()
+        } else {
+            0
+        }
+    }
+}
+
+/// Detect the encoding of data from its byte order mark, or from the position of zero bytes at its start
+///
+/// Without a byte order mark, UTF-16 and UTF-32 are recognized if the data starts with an ASCII character,
+/// which holds for the documents of all supported formats. Otherwise, the data is assumed to be UTF-8.
+/// Latin-1 cannot be told apart from UTF-8 and is never detected.
+///
+/// # Example
+///
+/// ```
+/// use serde_any::{detect_encoding, Encoding};
+///
+/// assert_eq!(detect_encoding(b"\xef\xbb\xbfname: a"), Encoding::Utf8);
+/// assert_eq!(detect_encoding(b"{\x00\"\x00"), Encoding::Utf16Le);
+/// assert_eq!(detect_encoding(b"{\"name\": \"a\"}"), Encoding::Utf8);
+/// ```
+pub fn detect_encoding(data: &[u8]) -> Encoding {
+    // The UTF-32LE mark starts with the UTF-16LE mark, so it has to be checked first
+    for &encoding in &[
+        Encoding::Utf32Le,
+        Encoding::Utf32Be,
+        Encoding::Utf8,
+        Encoding::Utf16Le,
+        Encoding::Utf16Be,
+    ] {
+        if data.starts_with(encoding.bom()) {
+            return encoding;
+        }
+    }
+
+    match data {
+        [0, 0, 0, b, ..] if *b != 0 => Encoding::Utf32Be,
+        [b, 0, 0, 0, ..] if *b != 0 => Encoding::Utf32Le,
+        [0, b, ..] if *b != 0 => Encoding::Utf16Be,
+        [b, 0, ..] if *b != 0 => Encoding::Utf16Le,
+        _ => Encoding::Utf8,
+    }
+}
+
+/// Transcode data to UTF-8 without a byte order mark, detecting the encoding unless it is given
+///
+/// UTF-8 data is not validated here, since every format reports invalid UTF-8 on its own.
+pub(crate) fn decode<'a>(data: &'a [u8], encoding: Option<Encoding>) -> Result<Cow<'a, [u8]>, Error> {
+    let encoding = encoding.unwrap_or_else(|| detect_encoding(data));
+    let bom = encoding.bom_len(data);
+    let data = &data[bom..];
+
+    let decoded = match encoding {
+        Encoding::Utf8 => return Ok(Cow::Borrowed(data)),
+        Encoding::Latin1 => Ok(data.iter().map(|&b| char::from(b)).collect()),
+        Encoding::Utf16Le => decode_utf16(data, u16::from_le_bytes),
+        Encoding::Utf16Be => decode_utf16(data, u16::from_be_bytes),
+        Encoding::Utf32Le => decode_utf32(data, u32::from_le_bytes),
+        Encoding::Utf32Be => decode_utf32(data, u32::from_be_bytes),
+    };
+    decoded
+        .map(|s: String| Cow::Owned(s.into_bytes()))
+        .map_err(|offset| Error::InvalidEncoding {
+            encoding,
+            offset: offset + bom,
+        })
+}
+
+/// Decode UTF-16, returning the offset of the first invalid code unit on failure
+fn decode_utf16(data: &[u8], unit: fn([u8; 2]) -> u16) -> Result<String, usize> {
+    let mut decoded = String::with_capacity(data.len() / 2);
+    let mut offset = 0;
+    let units = data.chunks_exact(2).map(|c| unit([c[0], c[1]]));
+    for c in ::std::char::decode_utf16(units) {
+        let c = c.map_err(|_| offset)?;
+        decoded.push(c);
+        offset += c.len_utf16() * 2;
+    }
+
+    if offset == data.len() {
+        Ok(decoded)
+    } else {
+        Err(offset)
+    }
+}
+
+/// Decode UTF-32, returning the offset of the first invalid code unit on failure
+fn decode_utf32(data: &[u8], unit: fn([u8; 4]) -> u32) -> Result<String, usize> {
+    let mut decoded = String::with_capacity(data.len() / 4);
+    let units = data.chunks_exact(4);
+    let rest = units.remainder().len();
+    for (i, c) in units.enumerate() {
+        let c = ::std::char::from_u32(unit([c[0], c[1], c[2], c[3]])).ok_or(i * 4)?;
+        decoded.push(c);
+    }
+
+    if rest == 0 {
+        Ok(decoded)
+    } else {
+        Err(data.len() - rest)
+    }
+}
+
+/// Read up to `prefix.len()` bytes, stopping early only at the end of the data
+pub(crate) fn read_prefix<R: Read>(reader: &mut R, prefix: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < prefix.len() {
+        match reader.read(&mut prefix[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(s: &str, encoding: Encoding) -> Vec<u8> {
+        let mut data = encoding.bom().to_vec();
+        match encoding {
+            Encoding::Utf8 => data.extend(s.bytes()),
+            Encoding::Latin1 => data.extend(s.chars().map(|c| c as u8)),
+            Encoding::Utf16Le => data.extend(s.encode_utf16().flat_map(|u| u.to_le_bytes().to_vec())),
+            Encoding::Utf16Be => data.extend(s.encode_utf16().flat_map(|u| u.to_be_bytes().to_vec())),
+            Encoding::Utf32Le => data.extend(s.chars().flat_map(|c| (c as u32).to_le_bytes().to_vec())),
+            Encoding::Utf32Be => data.extend(s.chars().flat_map(|c| (c as u32).to_be_bytes().to_vec())),
+        }
+        data
+    }
+
+    #[test]
+    fn transcode() {
+        let s = "{\"name\": \"Zo\u{eb}\", \"emoji\": \"\u{1f600}\"}";
+        for &encoding in &[
+            Encoding::Utf8,
+            Encoding::Utf16Le,
+            Encoding::Utf16Be,
+            Encoding::Utf32Le,
+            Encoding::Utf32Be,
+        ] {
+            let data = encode(s, encoding);
+            assert_eq!(detect_encoding(&data), encoding);
+            assert_eq!(decode(&data, None).unwrap(), s.as_bytes());
+
+            // Without a byte order mark
+            let data = &data[encoding.bom().len()..];
+            assert_eq!(detect_encoding(data), encoding);
+            assert_eq!(decode(data, Some(encoding)).unwrap(), s.as_bytes());
+        }
+
+        let latin1 = encode("caf\u{e9}", Encoding::Latin1);
+        assert_eq!(detect_encoding(&latin1), Encoding::Utf8);
+        assert_eq!(decode(&latin1, Some(Encoding::Latin1)).unwrap(), "caf\u{e9}".as_bytes());
+    }
+
+    #[test]
+    fn invalid() {
+        let unpaired = [0xff, 0xfe, b'a', 0, 0x00, 0xd8, b'b', 0];
+        assert_matches!(
+            decode(&unpaired, None),
+            Err(Error::InvalidEncoding {
+                encoding: Encoding::Utf16Le,
+                offset: 4
+            })
+        );
+        assert_matches!(
+            decode(b"a\x00b", None),
+            Err(Error::InvalidEncoding {
+                encoding: Encoding::Utf16Le,
+                offset: 2
+            })
+        );
+        assert_matches!(
+            decode(&[0, 0, 0x11, 0], Some(Encoding::Utf32Le)),
+            Err(Error::InvalidEncoding { offset: 0, .. })
+        );
+    }
+}
@@ -1,7 +1,17 @@
 use std;
+use std::cmp::Reverse;
+use std::fmt;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+use failure::{Backtrace, Fail};
 
 use backend::*;
+use compression::Compression;
+use encoding::Encoding;
+use diagnostic::Diagnostic;
 use format::Format;
+use value::{Value, ValueError};
 
 #[cfg(feature = "xml")]
 use failure::SyncFailure;
@@ -29,6 +39,11 @@ pub enum Error {
     #[fail(display = "TOML serialize error: {}", _0)]
     TomlSerialize(#[fail(cause)] toml::ser::Error),
 
+    /// Error parsing a TOML document for editing
+    #[cfg(all(feature = "toml", feature = "toml_edit"))]
+    #[fail(display = "TOML edit error: {}", _0)]
+    TomlEdit(#[fail(cause)] toml_edit::TomlError),
+
     /// Error deserializing with RON
     #[cfg(feature = "ron")]
     #[fail(display = "RON deserialize error: {}", _0)]
@@ -54,6 +69,12 @@ pub enum Error {
     #[fail(display = "URL serialize error: {}", _0)]
     UrlSerialize(#[fail(cause)] url::ser::Error),
 
+    /// Error deserializing from a [`Value`]
+    ///
+    /// [`Value`]: ../value/enum.Value.html
+    #[fail(display = "Value error: {}", _0)]
+    Value(#[fail(cause)] ValueError),
+
     /// IO error
     #[fail(display = "IO error: {}", _0)]
     Io(#[fail(cause)] std::io::Error),
@@ -66,11 +87,744 @@ pub enum Error {
     #[fail(display = "File extension {} not supported", _0)]
     UnsupportedFileExtension(String),
 
+    /// The specified format cannot deserialize values that borrow from the input
+    ///
+    /// Only formats for which [`Format::supports_borrowing`] is true can be used with [`from_str_borrowed`].
+    ///
+    /// [`Format::supports_borrowing`]: ../format/enum.Format.html#method.supports_borrowing
+    /// [`from_str_borrowed`]: ../de/fn.from_str_borrowed.html
+    #[fail(display = "Format {} does not support borrowing from the input", _0)]
+    UnsupportedBorrow(Format),
+
+    /// A [`Document`] in a format that cannot be edited in place was saved without allowing it to be rewritten
+    ///
+    /// Saving such a document would lose its comments and formatting. This is allowed with
+    /// [`Document::allow_rewrite`] or [`Document::on_rewrite`].
+    ///
+    /// [`Document`]: ../document/struct.Document.html
+    /// [`Document::allow_rewrite`]: ../document/struct.Document.html#method.allow_rewrite
+    /// [`Document::on_rewrite`]: ../document/struct.Document.html#method.on_rewrite
+    #[fail(display = "Format {} cannot be edited in place, saving would lose comments and formatting", _0)]
+    RewriteNotAllowed(Format),
+
+    /// The compression of a file or data was recognized, but is not supported
+    ///
+    /// Support for each compression is enabled by a feature.
+    #[fail(display = "Compression {} not supported", _0)]
+    UnsupportedCompression(Compression),
+
+    /// The input is not valid in its character encoding
+    ///
+    /// This is only reported for input which is transcoded to UTF-8, since invalid UTF-8
+    /// is reported by each format.
+    #[fail(display = "Invalid {} data at byte {}", encoding, offset)]
+    InvalidEncoding {
+        /// The detected or specified encoding
+        encoding: Encoding,
+        /// The offset of the first invalid byte in the input
+        offset: usize,
+    },
+
+    /// No file with the given stem and a supported extension exists
+    ///
+    /// The paths that were tried are listed in the order they were tried.
+    #[fail(display = "No file found for {:?}, tried {:?}", stem, tried)]
+    NoFileFound {
+        /// The stem of the file, without a directory or extension
+        stem: PathBuf,
+        /// All paths that were tried
+        tried: Vec<PathBuf>,
+    },
+
+    /// Several files with the same stem and different extensions exist in the same directory
+    ///
+    /// This is only reported if conflicts are rejected with [`ConflictPolicy::Error`].
+    ///
+    /// [`ConflictPolicy::Error`]: ../search/enum.ConflictPolicy.html#variant.Error
+    #[fail(display = "Conflicting files found for {:?}: {:?}", stem, found)]
+    ConflictingFiles {
+        /// The stem of the files, without a directory or extension
+        stem: PathBuf,
+        /// All files that were found, in order of preference
+        found: Vec<PathBuf>,
+    },
+
+    /// A path to a value inside a document is not valid
+    ///
+    /// This is returned if the path cannot be parsed, or if it does not fit the structure of the document,
+    /// for example because it refers to a key inside a string.
+    #[fail(display = "Invalid path {}: {}", path, reason)]
+    InvalidPath {
+        /// The path as given
+        path: String,
+        /// Why the path is not valid
+        reason: String,
+    },
+
+    /// There is no value at a path queried with [`get`]
+    ///
+    /// [`get`]: ../query/fn.get.html
+    #[fail(display = "Path {} not found, the longest existing prefix is {:?}", path, prefix)]
+    PathNotFound {
+        /// The path as given
+        path: String,
+        /// The longest prefix of the path that exists, in the same syntax as the path, empty for the root
+        prefix: String,
+    },
+
+    /// An operation of a JSON Patch is invalid or cannot be applied
+    ///
+    /// [`Error::PatchTestFailed`] is returned instead for failed `test` operations.
+    ///
+    /// [`Error::PatchTestFailed`]: enum.Error.html#variant.PatchTestFailed
+    #[fail(display = "Patch operation {} failed: {}", index, reason)]
+    PatchFailed {
+        /// The index of the operation in the patch
+        index: usize,
+        /// Why the operation failed
+        reason: String,
+    },
+
+    /// A `test` operation of a JSON Patch found a different value than expected
+    #[fail(display = "Patch operation {} failed: test at {} expected {}, found {}", index, path, expected, actual)]
+    PatchTestFailed {
+        /// The index of the operation in the patch
+        index: usize,
+        /// The JSON Pointer to the tested value
+        path: String,
+        /// The value given in the operation
+        expected: Value,
+        /// The value found in the document
+        actual: Value,
+    },
+
+    /// The input of [`stream_seq`] is not a sequence, or its elements cannot be told apart
+    ///
+    /// Errors in single elements are reported without ending the stream, wrapped in [`Error::Field`] instead.
+    ///
+    /// [`stream_seq`]: ../stream/fn.stream_seq.html
+    /// [`Error::Field`]: enum.Error.html#variant.Field
+    #[fail(display = "Invalid sequence at byte {}: {}", offset, reason)]
+    InvalidSequence {
+        /// The offset in the input where the problem was found
+        offset: usize,
+        /// What is wrong
+        reason: String,
+    },
+
     /// None of the supported formats was able to deserialize successfully
     ///
-    /// The tuple element is the list of all tried formats and the resulting errors
-    #[fail(display = "No format was able to parse the source")]
-    NoSuccessfulParse(Vec<(Format, Error)>),
+    /// The tuple element contains all tried formats and the resulting errors,
+    /// and can be used to find the format that most likely matches the source.
+    #[fail(display = "{}", _0)]
+    NoSuccessfulParse(NoSuccessfulParse),
+
+    /// Error deserializing a value nested inside the source data
+    ///
+    /// The `path` points to the value that failed to deserialize, such as `servers[2].port`,
+    /// and `error` is the underlying format-specific error.
+    ///
+    /// Errors at the root of the data, including most syntax errors, are returned unwrapped.
+    /// To match on format-specific variants such as `Error::Json` regardless of where the error occurred,
+    /// match on [`Error::root`] instead.
+    ///
+    /// [`Error::root`]: enum.Error.html#method.root
+    #[fail(display = "{}: {}", path, error)]
+    Field {
+        /// Path to the value that failed to deserialize
+        path: String,
+        /// The underlying error
+        #[fail(cause)]
+        error: Box<Error>,
+    },
+
+    /// Error reading, deserializing or writing a file
+    ///
+    /// The `path` is the file that caused the error, and `error` is the underlying error,
+    /// which can also be reached with [`Error::root`].
+    ///
+    /// [`Error::root`]: enum.Error.html#method.root
+    #[fail(display = "{:?}: {}", path, error)]
+    File {
+        /// Path to the file
+        path: PathBuf,
+        /// The underlying error
+        #[fail(cause)]
+        error: Box<Error>,
+    },
+
+    /// A limit set with [`DeserializeOptions`] was exceeded
+    ///
+    /// [`DeserializeOptions`]: ../de/struct.DeserializeOptions.html
+    #[fail(display = "{} limit of {} exceeded", limit, max)]
+    LimitExceeded {
+        /// The limit that was exceeded
+        limit: Limit,
+        /// The configured maximum
+        max: usize,
+    },
+
+    /// A map contains the same key more than once
+    ///
+    /// This is only reported if duplicate keys are rejected with [`DeserializeOptions::reject_duplicate_keys`].
+    /// The locations of both occurrences are included if the format reports positions,
+    /// which is not the case for XML and URL.
+    ///
+    /// [`DeserializeOptions::reject_duplicate_keys`]: ../de/struct.DeserializeOptions.html#method.reject_duplicate_keys
+    #[fail(display = "Duplicate key {}", key)]
+    DuplicateKey {
+        /// The repeated key
+        key: String,
+        /// Location of the first occurrence of the key
+        first: Option<Location>,
+        /// Location of the second occurrence of the key
+        second: Option<Location>,
+    },
+
+    /// The source contains a field which the target type does not use
+    ///
+    /// This is only reported if unknown fields are denied with [`DeserializeOptions::deny_unknown_fields`].
+    /// The error is wrapped in [`Error::Field`] with the full path to the field.
+    ///
+    /// [`DeserializeOptions::deny_unknown_fields`]: ../de/struct.DeserializeOptions.html#method.deny_unknown_fields
+    /// [`Error::Field`]: enum.Error.html#variant.Field
+    #[fail(display = "Unknown field {}", field)]
+    UnknownField {
+        /// The name of the field
+        field: String,
+    },
+}
+
+/// The limits that can be set with [`DeserializeOptions`]
+///
+/// [`DeserializeOptions`]: ../de/struct.DeserializeOptions.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Limit {
+    /// The size of the input in bytes
+    InputBytes,
+    /// The nesting depth of sequences and maps
+    Depth,
+    /// The number of elements in a single sequence or map
+    CollectionLength,
+    /// The total number of values
+    Values,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Limit::InputBytes => "Input size",
+            Limit::Depth => "Nesting depth",
+            Limit::CollectionLength => "Collection length",
+            Limit::Values => "Value count",
+        })
+    }
+}
+
+/// A position in the source data
+///
+/// Lines and columns are counted from 1.
+/// The byte offset is only known if the backend reports it,
+/// and can be computed from the source with [`Location::offset_in`].
+///
+/// [`Location::offset_in`]: struct.Location.html#method.offset_in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Location {
+    /// Line number, starting at 1
+    pub line: usize,
+    /// Column number, starting at 1
+    pub column: usize,
+    /// Byte offset from the start of the source
+    pub offset: Option<usize>,
+}
+
+impl Location {
+    pub(crate) fn new(line: usize, column: usize) -> Location {
+        Location {
+            line,
+            column,
+            offset: None,
+        }
+    }
+
+    /// Return the location of the byte offset `offset` in `source`, counting columns in characters
+    #[cfg(any(feature = "ron", all(feature = "toml", feature = "toml_edit")))]
+    pub(crate) fn at_offset(source: &str, offset: usize) -> Location {
+        let line_start = source[..offset].rfind('\n').map_or(0, |newline| newline + 1);
+        Location {
+            line: source[..line_start].matches('\n').count() + 1,
+            column: source[line_start..offset].chars().count() + 1,
+            offset: Some(offset),
+        }
+    }
+
+    /// Return the byte offset of this location in `source`
+    ///
+    /// If the backend reported the offset, it is returned directly.
+    /// Otherwise it is computed from the line and column, counting columns in characters.
+    /// Returns `None` if the location lies outside of `source`.
+    pub fn offset_in(&self, source: &str) -> Option<usize> {
+        if self.offset.is_some() {
+            return self.offset;
+        }
+
+        let mut line_start = 0;
+        for _ in 1..self.line {
+            line_start += source[line_start..].find('\n')? + 1;
+        }
+
+        let line = &source[line_start..];
+        let line = &line[..line.find('\n').unwrap_or(line.len())];
+        let column = line
+            .char_indices()
+            .nth(self.column.saturating_sub(1))
+            .map(|(i, _)| i)
+            .unwrap_or(line.len());
+
+        Some(line_start + column)
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+/// The general category of an [`Error`]
+///
+/// This allows reacting to errors without matching on the format-specific variants of [`Error`].
+///
+/// [`Error`]: enum.Error.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The source is not valid in the used format
+    Syntax,
+    /// The source is valid, but its data does not match the expected structure or types,
+    /// or the value cannot be serialized
+    Data,
+    /// The source ended unexpectedly
+    Eof,
+    /// Reading or writing failed
+    Io,
+    /// The format, file extension or type of value is not supported
+    Unsupported,
+    /// The source exceeds a limit set with [`DeserializeOptions`]
+    ///
+    /// [`DeserializeOptions`]: ../de/struct.DeserializeOptions.html
+    Limit,
+}
+
+/// Errors from all formats that were tried when deserializing data of an unknown format
+///
+/// Besides the errors, this records how far into the source each format got before failing.
+/// This allows finding the [most likely] format, which is usually the one the user intended,
+/// and whose error is the one worth reporting.
+///
+/// It dereferences to a slice of all tried formats and their errors, in the order they were tried.
+///
+/// [most likely]: struct.NoSuccessfulParse.html#method.most_likely
+#[derive(Debug)]
+pub struct NoSuccessfulParse {
+    errors: Vec<(Format, Error)>,
+    progress: Vec<Option<usize>>,
+}
+
+impl NoSuccessfulParse {
+    /// Collect the errors from deserializing `source` with each of the formats
+    ///
+    /// If `source` is not known, only progress reported directly by backends is recorded.
+    pub(crate) fn new(errors: Vec<(Format, Error)>, source: Option<&[u8]>) -> NoSuccessfulParse {
+        let source = source.map(String::from_utf8_lossy);
+        let progress = errors
+            .iter()
+            .map(|(_, error)| {
+                let location = error.location()?;
+                match source {
+                    Some(ref source) => location.offset_in(source),
+                    None => location.offset,
+                }
+            })
+            .collect();
+
+        NoSuccessfulParse { errors, progress }
+    }
+
+    /// Return how far into the source the given format got before failing, in bytes
+    ///
+    /// Returns `None` if the format was not tried, or if its error does not report a location.
+    pub fn progress(&self, format: Format) -> Option<usize> {
+        self.errors
+            .iter()
+            .position(|&(f, _)| f == format)
+            .and_then(|i| self.progress[i])
+    }
+
+    /// Return all tried formats and their errors, ordered from the most to the least likely format
+    ///
+    /// Formats that got further into the source before failing are considered more likely.
+    /// When two formats got equally far, the one that failed with an [`ErrorKind::Data`] error is more likely,
+    /// as that means the source was syntactically valid for it.
+    /// Remaining ties keep the order in which the formats were tried.
+    ///
+    /// [`ErrorKind::Data`]: enum.ErrorKind.html#variant.Data
+    pub fn ranked(&self) -> Vec<&(Format, Error)> {
+        let mut indices: Vec<usize> = (0..self.errors.len()).collect();
+        indices.sort_by_key(|&i| {
+            let (_, ref error) = self.errors[i];
+            Reverse((self.progress[i], error.kind() == ErrorKind::Data))
+        });
+        indices.into_iter().map(|i| &self.errors[i]).collect()
+    }
+
+    /// Return the most likely format of the source and its error
+    ///
+    /// See [`ranked`] for how formats are compared.
+    /// Returns `None` only if no formats were tried.
+    ///
+    /// [`ranked`]: struct.NoSuccessfulParse.html#method.ranked
+    pub fn most_likely(&self) -> Option<&(Format, Error)> {
+        self.ranked().into_iter().next()
+    }
+
+    /// Consume this value and return all tried formats and their errors
+    pub fn into_errors(self) -> Vec<(Format, Error)> {
+        self.errors
+    }
+}
+
+impl Deref for NoSuccessfulParse {
+    type Target = [(Format, Error)];
+
+    fn deref(&self) -> &[(Format, Error)] {
+        &self.errors
+    }
+}
+
+impl fmt::Display for NoSuccessfulParse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "No format was able to parse the source")?;
+        if let Some((format, error)) = self.most_likely() {
+            write!(f, ", the most likely format is {}: {}", format, error)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error {
+    /// Return the position in the source data where the error occurred
+    ///
+    /// Locations are reported for deserialization errors from JSON, YAML, TOML, RON and XML,
+    /// as long as the backend knows where the error happened.
+    /// Errors that are not tied to a position in the source, such as IO errors, return `None`.
+    #[allow(unreachable_patterns)]
+    pub fn location(&self) -> Option<Location> {
+        match self {
+            #[cfg(feature = "json")]
+            Error::Json(e) if e.line() > 0 => Some(Location::new(e.line(), e.column())),
+            #[cfg(feature = "yaml")]
+            Error::Yaml(e) => e.location().map(|l| Location {
+                line: l.line(),
+                column: l.column(),
+                offset: Some(l.index()),
+            }),
+            #[cfg(feature = "toml")]
+            Error::TomlDeserialize(e) => e.line_col().map(|(line, col)| Location::new(line + 1, col + 1)),
+            #[cfg(feature = "ron")]
+            Error::RonDeserialize(ron::de::Error::Parser(_, pos)) => Some(Location::new(pos.line, pos.col)),
+            #[cfg(feature = "xml")]
+            Error::Xml(e) => xml_location(&e.to_string()),
+            Error::Field { error, .. } | Error::File { error, .. } => error.location(),
+            Error::DuplicateKey { second, .. } => *second,
+            _ => None,
+        }
+    }
+
+    /// Return the general category of this error
+    ///
+    /// For [`Error::NoSuccessfulParse`], this is the kind of the error from the most likely format.
+    ///
+    /// [`Error::NoSuccessfulParse`]: enum.Error.html#variant.NoSuccessfulParse
+    #[allow(unreachable_patterns)]
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            #[cfg(feature = "json")]
+            Error::Json(e) => match e.classify() {
+                serde_json::error::Category::Io => ErrorKind::Io,
+                serde_json::error::Category::Syntax => ErrorKind::Syntax,
+                serde_json::error::Category::Data => ErrorKind::Data,
+                serde_json::error::Category::Eof => ErrorKind::Eof,
+            },
+            #[cfg(feature = "yaml")]
+            Error::Yaml(e) => yaml_kind(e),
+            #[cfg(feature = "toml")]
+            Error::TomlDeserialize(e) => toml_kind(&format!("{:?}", e)),
+            #[cfg(feature = "toml")]
+            Error::TomlSerialize(e) => match e {
+                toml::ser::Error::UnsupportedType
+                | toml::ser::Error::UnsupportedNone
+                | toml::ser::Error::KeyNotString
+                | toml::ser::Error::ValueAfterTable => ErrorKind::Unsupported,
+                _ => ErrorKind::Data,
+            },
+            #[cfg(all(feature = "toml", feature = "toml_edit"))]
+            Error::TomlEdit(_) => ErrorKind::Syntax,
+            #[cfg(feature = "ron")]
+            Error::RonDeserialize(e) => ron_kind(e),
+            #[cfg(feature = "ron")]
+            Error::RonSerialize(_) => ErrorKind::Data,
+            #[cfg(feature = "xml")]
+            Error::Xml(e) => xml_kind(&format!("{:?}", e)),
+            #[cfg(feature = "url")]
+            Error::UrlDeserialize(_) | Error::UrlSerialize(_) => ErrorKind::Data,
+            Error::Io(_) | Error::NoFileFound { .. } | Error::ConflictingFiles { .. } => ErrorKind::Io,
+            Error::Value(_) => ErrorKind::Data,
+            Error::UnsupportedFormat(_) | Error::UnsupportedFileExtension(_) | Error::UnsupportedCompression(_) => {
+                ErrorKind::Unsupported
+            }
+            Error::UnsupportedBorrow(_) | Error::RewriteNotAllowed(_) => ErrorKind::Unsupported,
+            Error::NoSuccessfulParse(errors) => errors
+                .most_likely()
+                .map(|(_, error)| error.kind())
+                .unwrap_or(ErrorKind::Syntax),
+            Error::Field { error, .. } | Error::File { error, .. } => error.kind(),
+            Error::LimitExceeded { .. } => ErrorKind::Limit,
+            Error::DuplicateKey { .. } | Error::UnknownField { .. } | Error::InvalidPath { .. } => ErrorKind::Data,
+            Error::PathNotFound { .. } => ErrorKind::Data,
+            Error::InvalidEncoding { .. } | Error::InvalidSequence { .. } => ErrorKind::Syntax,
+            Error::PatchFailed { .. } | Error::PatchTestFailed { .. } => ErrorKind::Data,
+        }
+    }
+
+    /// Return the format whose backend produced this error
+    ///
+    /// Returns `None` for errors that are not specific to a single format,
+    /// such as IO errors or [`Error::NoSuccessfulParse`].
+    ///
+    /// [`Error::NoSuccessfulParse`]: enum.Error.html#variant.NoSuccessfulParse
+    pub fn format(&self) -> Option<Format> {
+        match self {
+            #[cfg(feature = "json")]
+            Error::Json(_) => Some(Format::Json),
+            #[cfg(feature = "yaml")]
+            Error::Yaml(_) => Some(Format::Yaml),
+            #[cfg(feature = "toml")]
+            Error::TomlDeserialize(_) | Error::TomlSerialize(_) => Some(Format::Toml),
+            #[cfg(all(feature = "toml", feature = "toml_edit"))]
+            Error::TomlEdit(_) => Some(Format::Toml),
+            #[cfg(feature = "ron")]
+            Error::RonDeserialize(_) | Error::RonSerialize(_) => Some(Format::Ron),
+            #[cfg(feature = "xml")]
+            Error::Xml(_) => Some(Format::Xml),
+            #[cfg(feature = "url")]
+            Error::UrlDeserialize(_) | Error::UrlSerialize(_) => Some(Format::Url),
+            Error::UnsupportedFormat(format) | Error::UnsupportedBorrow(format) | Error::RewriteNotAllowed(format) => {
+                Some(*format)
+            }
+            Error::Field { error, .. } | Error::File { error, .. } => error.format(),
+            _ => None,
+        }
+    }
+
+    /// Render this error together with the source data it came from
+    ///
+    /// The returned [`Diagnostic`] implements `Display`, and shows a snippet of the source
+    /// with the position of the error marked, similar to compiler error messages.
+    /// `source` should be the data that failed to deserialize, and `file_name` is used
+    /// to tell the user where it came from.
+    ///
+    /// [`Diagnostic`]: ../diagnostic/struct.Diagnostic.html
+    pub fn diagnostic<'a>(&'a self, source: &'a str, file_name: &'a str) -> Diagnostic<'a> {
+        Diagnostic::new(self, source, file_name)
+    }
+
+    /// Return the path to the value that failed to deserialize
+    ///
+    /// The path is formatted as field names separated by dots, with sequence indices in brackets,
+    /// for example `servers[2].port`.
+    /// It is only available for errors that occurred inside a nested value.
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            Error::Field { path, .. } => Some(path),
+            Error::File { error, .. } => error.path(),
+            _ => None,
+        }
+    }
+
+    /// Return the file that caused this error
+    ///
+    /// It is only available for errors from functions that read or write files.
+    pub fn file(&self) -> Option<&Path> {
+        match self {
+            Error::File { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Return the underlying error, without the [`Error::Field`] and [`Error::File`] wrappers
+    ///
+    /// This is the error from the backend of the format for most deserialization errors,
+    /// and can be matched on regardless of the path and file the error occurred in.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # extern crate serde_any;
+    /// # use std::collections::HashMap;
+    /// use serde_any::{Error, Format};
+    ///
+    /// # fn main() {
+    /// let error = serde_any::from_str::<HashMap<String, u32>>(r#"{"port": "x"}"#, Format::Json).unwrap_err();
+    /// assert_eq!(error.path(), Some("port"));
+    /// assert!(match error.root() {
+    ///     Error::Json(_) => true,
+    ///     _ => false,
+    /// });
+    /// # }
+    /// ```
+    ///
+    /// [`Error::Field`]: enum.Error.html#variant.Field
+    /// [`Error::File`]: enum.Error.html#variant.File
+    pub fn root(&self) -> &Error {
+        match self {
+            Error::Field { error, .. } | Error::File { error, .. } => error.root(),
+            error => error,
+        }
+    }
+
+    /// Return the underlying error by value, without the [`Error::Field`] and [`Error::File`] wrappers
+    ///
+    /// This is the same as [`root`], but takes ownership of the error.
+    ///
+    /// [`Error::Field`]: enum.Error.html#variant.Field
+    /// [`Error::File`]: enum.Error.html#variant.File
+    /// [`root`]: enum.Error.html#method.root
+    pub fn into_root(self) -> Error {
+        match self {
+            Error::Field { error, .. } | Error::File { error, .. } => error.into_root(),
+            error => error,
+        }
+    }
+
+    /// Attach the path of the file that caused this error
+    pub(crate) fn in_file<P: AsRef<Path>>(self, path: P) -> Error {
+        match self {
+            Error::File { .. } => self,
+            error => Error::File {
+                path: path.as_ref().to_path_buf(),
+                error: Box::new(error),
+            },
+        }
+    }
+}
+
+/// Parse the location from an XML error message
+///
+/// The XML backend does not expose the position of syntax errors,
+/// but prefixes their messages with `row:column`.
+#[cfg(feature = "xml")]
+fn xml_location(message: &str) -> Option<Location> {
+    let position = message.split_whitespace().next()?;
+    let mut parts = position.splitn(2, ':');
+    let line = parts.next()?.parse().ok()?;
+    let column = parts.next()?.parse().ok()?;
+    Some(Location::new(line, column))
+}
+
+#[cfg(feature = "yaml")]
+fn yaml_kind(e: &serde_yaml::Error) -> ErrorKind {
+    use std::error::Error as StdError;
+
+    // The YAML backend does not expose the kind of its errors,
+    // but only scanner, IO and encoding errors have a source
+    let message = e.to_string();
+    match e.source() {
+        Some(source) if source.is::<std::io::Error>() => ErrorKind::Io,
+        Some(_) => ErrorKind::Syntax,
+        None if message == "EOF while parsing a value" => ErrorKind::Eof,
+        None if message.starts_with("deserializing from YAML containing more than one document") => ErrorKind::Syntax,
+        None => ErrorKind::Data,
+    }
+}
+
+/// Classify a TOML deserialization error from its debug representation
+///
+/// The TOML backend keeps the kind of its errors private,
+/// so this is the only way to tell syntax errors from data errors.
+#[cfg(feature = "toml")]
+fn toml_kind(debug: &str) -> ErrorKind {
+    let data_kinds = [
+        "kind: Custom",
+        "kind: ExpectedTuple",
+        "kind: ExpectedEmptyTable",
+        "kind: UnexpectedKeys",
+        "kind: DottedKeyInvalidType",
+    ];
+
+    if debug.contains("kind: UnexpectedEof") {
+        ErrorKind::Eof
+    } else if data_kinds.iter().any(|kind| debug.contains(kind)) {
+        ErrorKind::Data
+    } else {
+        ErrorKind::Syntax
+    }
+}
+
+#[cfg(feature = "ron")]
+fn ron_kind(e: &ron::de::Error) -> ErrorKind {
+    use ron::de::{Error, ParseError};
+
+    match e {
+        Error::IoError(_) => ErrorKind::Io,
+        Error::Message(_) => ErrorKind::Data,
+        Error::Parser(ParseError::Eof, _) => ErrorKind::Eof,
+        // The RON parser is driven by the expected type,
+        // so these mean that a valid value of another type was found
+        Error::Parser(ParseError::ExpectedArray, _)
+        | Error::Parser(ParseError::ExpectedBoolean, _)
+        | Error::Parser(ParseError::ExpectedChar, _)
+        | Error::Parser(ParseError::ExpectedEnum, _)
+        | Error::Parser(ParseError::ExpectedFloat, _)
+        | Error::Parser(ParseError::ExpectedInteger, _)
+        | Error::Parser(ParseError::ExpectedMap, _)
+        | Error::Parser(ParseError::ExpectedOption, _)
+        | Error::Parser(ParseError::ExpectedString, _)
+        | Error::Parser(ParseError::ExpectedStruct, _)
+        | Error::Parser(ParseError::ExpectedStructName, _)
+        | Error::Parser(ParseError::ExpectedUnit, _) => ErrorKind::Data,
+        Error::Parser(..) => ErrorKind::Syntax,
+    }
+}
+
+/// Classify an XML error from its debug representation
+///
+/// The XML error is wrapped in `SyncFailure`, which only gives access to its `Debug` and `Display` output.
+#[cfg(feature = "xml")]
+fn xml_kind(debug: &str) -> ErrorKind {
+    if debug.starts_with("Error(Io(") {
+        ErrorKind::Io
+    } else if debug.starts_with("Error(Syntax(")
+        && (debug.contains("kind: UnexpectedEof") || debug.contains("Unexpected end of stream"))
+    {
+        ErrorKind::Eof
+    } else if debug.starts_with("Error(Syntax(") || debug.starts_with("Error(FromUtf8Error(") {
+        ErrorKind::Syntax
+    } else if debug.starts_with("Error(UnsupportedOperation(") {
+        ErrorKind::Unsupported
+    } else {
+        ErrorKind::Data
+    }
+}
+
+// The boxed error is transparent, so that it can be used as the cause of `Error::Field` and `Error::File`
+impl Fail for Box<Error> {
+    fn cause(&self) -> Option<&dyn Fail> {
+        (**self).cause()
+    }
+
+    fn backtrace(&self) -> Option<&Backtrace> {
+        (**self).backtrace()
+    }
 }
 
 macro_rules! impl_error_from {
@@ -84,6 +838,7 @@ macro_rules! impl_error_from {
 }
 
 impl_error_from!(std::io::Error => Error::Io);
+impl_error_from!(ValueError => Error::Value);
 
 #[cfg(feature = "json")]
 impl_error_from!(serde_json::Error => Error::Json);
@@ -95,6 +850,8 @@ impl_error_from!(serde_yaml::Error => Error::Yaml);
 impl_error_from!(toml::ser::Error => Error::TomlSerialize);
 #[cfg(feature = "toml")]
 impl_error_from!(toml::de::Error => Error::TomlDeserialize);
+#[cfg(all(feature = "toml", feature = "toml_edit"))]
+impl_error_from!(toml_edit::TomlError => Error::TomlEdit);
 
 #[cfg(feature = "ron")]
 impl_error_from!(ron::ser::Error => Error::RonSerialize);
@@ -112,3 +869,178 @@ impl From<xml::Error> for Error {
 impl_error_from!(url::ser::Error => Error::UrlSerialize);
 #[cfg(feature = "url")]
 impl_error_from!(url::de::Error => Error::UrlDeserialize);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use de::{from_str, from_str_any};
+    use format::supported_formats;
+
+    #[allow(dead_code)]
+    #[derive(Deserialize, Debug)]
+    struct Server {
+        host: String,
+        port: u16,
+    }
+
+    #[allow(dead_code)]
+    #[derive(Deserialize, Debug)]
+    struct Config {
+        servers: Vec<Server>,
+    }
+
+    #[test]
+    fn syntax_error_locations() {
+        let cases = vec![
+            (Format::Json, "{\n  \"servers\": [\n    {\"host\" \"a\"}\n  ]\n}", 3, 13),
+            (Format::Yaml, "servers:\n  - host: a\n   port: 1\n", 3, 8),
+            (Format::Toml, "[[servers]]\nhost = \"a\"\nport = = 1\n", 3, 8),
+            (Format::Ron, "(\n  servers: [\n    (host: \"a\", port: ?),\n  ],\n)", 3, 23),
+            (Format::Xml, "<Config>\n  <servers>\n    <host>a</hst>\n", 3, 17),
+        ];
+
+        for (format, source, line, column) in cases {
+            let err = from_str::<Config>(source, format).unwrap_err();
+            let location = err
+                .location()
+                .unwrap_or_else(|| panic!("{} error has no location: {}", format, err));
+            assert_eq!((format, location.line, location.column), (format, line, column));
+            if let Some(offset) = location.offset {
+                let computed = Location::new(location.line, location.column).offset_in(source);
+                assert_eq!(computed, Some(offset));
+            }
+        }
+    }
+
+    #[test]
+    fn data_error_path() {
+        let source = "{\"servers\": [{\"host\": \"a\", \"port\": 1}, {\"host\": \"b\", \"port\": \"x\"}]}";
+        let err = from_str::<Config>(source, Format::Json).unwrap_err();
+
+        assert_matches!(err, Error::Field { .. });
+        assert_eq!(err.path(), Some("servers[1].port"));
+        assert_eq!(err.location().map(|l| l.line), Some(1));
+        assert_matches!(err.root(), Error::Json(_));
+
+        let err = err.in_file("servers.json");
+        assert_matches!(err.root(), Error::Json(_));
+        assert_matches!(err.into_root(), Error::Json(_));
+    }
+
+    #[test]
+    fn root_error_has_no_path() {
+        let err = from_str::<Config>("", Format::Json).unwrap_err();
+        assert_matches!(err, Error::Json(_));
+        assert_eq!(err.path(), None);
+
+        let err = from_str::<::value::Map>("{\"a\": 1", Format::Json).unwrap_err();
+        assert_matches!(err, Error::Json(_));
+        assert_eq!(err.path(), None);
+        assert!(err.to_string().starts_with("JSON error: "), "{}", err);
+    }
+
+    #[test]
+    fn location_offset() {
+        let source = "first line\nsecond line\n";
+        let location = Location::new(2, 8);
+        assert_eq!(location.offset_in(source), Some(18));
+        assert_eq!(&source[18..], "line\n");
+        assert_eq!(Location::new(5, 1).offset_in(source), None);
+    }
+
+    fn no_successful_parse<T: for<'de> ::serde::Deserialize<'de> + fmt::Debug>(source: &str) -> NoSuccessfulParse {
+        match from_str_any::<T>(source) {
+            Err(Error::NoSuccessfulParse(e)) => e,
+            other => panic!("Expected NoSuccessfulParse, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn most_likely_syntax_error() {
+        let errors = no_successful_parse::<Config>("[[servers]]\nhost = \"a\"\nport = = 1\n");
+
+        assert_eq!(errors.len(), supported_formats().len());
+        assert_eq!(errors.progress(Format::Toml), Some(30));
+        assert_matches!(errors.most_likely(), Some((Format::Toml, Error::TomlDeserialize(_))));
+        assert_eq!(errors.ranked()[0].0, Format::Toml);
+    }
+
+    #[test]
+    fn most_likely_data_error() {
+        let errors = no_successful_parse::<Config>("{\"servers\": [{\"host\": \"a\", \"port\": true}]}");
+        let (_, error) = errors.most_likely().unwrap();
+
+        assert_eq!(error.path(), Some("servers[0].port"));
+    }
+
+    #[test]
+    fn no_successful_parse_display() {
+        let errors = no_successful_parse::<Config>("[[servers]]\nhost = \"a\"\nport = = 1\n");
+
+        assert_eq!(
+            errors.to_string(),
+            "No format was able to parse the source, the most likely format is Toml: \
+             TOML deserialize error: expected a value, found an equals at line 3 column 8"
+        );
+    }
+
+    #[test]
+    fn error_kinds() {
+        let cases = vec![
+            (Format::Json, "{\"servers\": [}", ErrorKind::Syntax),
+            (Format::Json, "{\"servers\": [{\"host\": 1}]}", ErrorKind::Data),
+            (Format::Json, "{\"servers\": [", ErrorKind::Eof),
+            (Format::Yaml, "servers:\n  - host: a\n   port: 1\n", ErrorKind::Syntax),
+            (Format::Yaml, "servers:\n  - host: a\n    port: x\n", ErrorKind::Data),
+            (Format::Yaml, "", ErrorKind::Eof),
+            (Format::Toml, "[[servers]]\nport = = 1\n", ErrorKind::Syntax),
+            (Format::Toml, "[[servers]]\nhost = \"a\"\nport = \"x\"\n", ErrorKind::Data),
+            (Format::Toml, "[[servers]]\nhost = \"a", ErrorKind::Syntax),
+            (Format::Toml, "[[servers]]\nhost =", ErrorKind::Eof),
+            (Format::Ron, "(servers: [(host: \"a\", port: 1)]]", ErrorKind::Syntax),
+            (Format::Ron, "(servers: [(host: \"a\", port: \"x\")])", ErrorKind::Data),
+            (Format::Ron, "(servers: [(host: \"a\")])", ErrorKind::Data),
+            (Format::Ron, "(servers: [(host: \"a\",", ErrorKind::Eof),
+            (Format::Xml, "<Config><servers><host>a</hst>", ErrorKind::Syntax),
+            (Format::Xml, "<Config><servers><host>a</host><port>x</port></servers></Config>", ErrorKind::Data),
+            (Format::Xml, "<Config><servers>", ErrorKind::Eof),
+            (Format::Url, "servers=a", ErrorKind::Data),
+        ];
+
+        for (format, source, kind) in cases {
+            let err = from_str::<Config>(source, format).unwrap_err();
+            assert_eq!((format, source, err.kind()), (format, source, kind), "{}", err);
+        }
+    }
+
+    #[test]
+    fn other_error_kinds() {
+        use std::io;
+
+        let io = Error::from(io::Error::new(io::ErrorKind::NotFound, "not found"));
+        assert_eq!(io.kind(), ErrorKind::Io);
+        assert_eq!(Error::UnsupportedFormat(Format::Json).kind(), ErrorKind::Unsupported);
+
+        let errors = no_successful_parse::<Config>("[[servers]]\nhost = \"a\"\nport = \"x\"\n");
+        assert_eq!(Error::NoSuccessfulParse(errors).kind(), ErrorKind::Data);
+
+        let limit = Error::LimitExceeded {
+            limit: Limit::Depth,
+            max: 8,
+        };
+        assert_eq!(limit.kind(), ErrorKind::Limit);
+        assert_eq!(limit.to_string(), "Nesting depth limit of 8 exceeded");
+        let duplicate = Error::DuplicateKey {
+            key: "a".to_string(),
+            first: Some(Location::new(1, 1)),
+            second: Some(Location::new(3, 1)),
+        };
+        assert_eq!(duplicate.kind(), ErrorKind::Data);
+        assert_eq!(duplicate.location(), Some(Location::new(3, 1)));
+        let unknown = Error::UnknownField {
+            field: "prot".to_string(),
+        };
+        assert_eq!(unknown.kind(), ErrorKind::Data);
+        assert_eq!(unknown.to_string(), "Unknown field prot");
+    }
+}
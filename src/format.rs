@@ -3,6 +3,8 @@ use std::ffi::OsStr;
 use std::path::Path;
 use std::str::FromStr;
 
+use compression::guess_compression;
+
 /// Serialization or deserialization formats
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Format {
@@ -46,6 +48,17 @@ impl Format {
             Format::Url => cfg!(feature = "url"),
         }
     }
+
+    /// Checks whether this format can deserialize values that borrow from the input
+    ///
+    /// This is true for JSON, TOML and URL-encoded data, when the format is supported.
+    /// The other formats always copy strings out of the input.
+    pub fn supports_borrowing(&self) -> bool {
+        match self {
+            Format::Json | Format::Toml | Format::Url => self.is_supported(),
+            Format::Yaml | Format::Ron | Format::Xml => false,
+        }
+    }
 }
 
 impl FromStr for Format {
@@ -122,17 +135,30 @@ pub fn supported_extensions() -> Vec<&'static str> {
 
 /// Attempt to guess the serialization/deserialization format from a file name
 ///
+/// A compression suffix recognized by [`guess_compression`] is skipped,
+/// so the format of `data.json.gz` is JSON.
+///
 /// This function may recognize and return a format even if it's not supported due to feature flags.
+///
+/// [`guess_compression`]: ../compression/fn.guess_compression.html
 pub fn guess_format<P>(path: P) -> Option<Format>
 where
     P: AsRef<Path>,
 {
-    path.as_ref()
+    inner_path(path.as_ref())
         .extension()
         .and_then(OsStr::to_str)
         .and_then(guess_format_from_extension)
 }
 
+/// Strip a compression suffix from a path, such as `data.json` from `data.json.gz`
+pub(crate) fn inner_path(path: &Path) -> &Path {
+    match (guess_compression(path), path.file_stem()) {
+        (Some(_), Some(stem)) => Path::new(stem),
+        _ => path,
+    }
+}
+
 /// Attempt to guess the serialization/deserialization format from a file extension
 ///
 /// This function may recognize and return a format even if it's not supported due to feature flags.
@@ -161,7 +187,9 @@ mod tests {
             assert!(from_ext.is_some());
             assert!(from_path.is_some());
             assert_eq!(from_ext, from_path);
+            assert_eq!(guess_format(stem.with_extension(format!("{}.gz", ext))), from_ext);
         }
+        assert_eq!(guess_format("data.gz"), None);
     }
 
     #[test]
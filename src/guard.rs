@@ -0,0 +1,645 @@
+// A deserializer wrapper enforcing the limits of `DeserializeOptions` independently of the format.
+//
+// The wrapper sits between the backend's deserializer and the visitors of the target type,
+// so it sees every value, sequence and map as it is produced, including values expanded
+// from YAML aliases. Violations are reported to the backend as custom errors, and recorded
+// in the `Budget` so that they can be turned into the dedicated error variants afterwards.
+//
+// The same wrapper also keeps track of the path to the current value, in order to report
+// values which the target type ignored, such as misspelled fields.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::fmt;
+
+use serde::de::{self, DeserializeSeed, Deserializer, EnumAccess, MapAccess, SeqAccess, VariantAccess, Visitor};
+
+use de::DeserializeOptions;
+use encoding::Encoding;
+use error::{Error, Limit};
+use path::{render_partial_path, Segment};
+
+/// The state shared by all parts of a guarded deserializer
+pub(crate) struct Budget {
+    options: DeserializeOptions,
+    depth: Cell<usize>,
+    values: Cell<usize>,
+    maps: Cell<usize>,
+    keys: RefCell<Vec<(usize, HashSet<String>)>>,
+    duplicate: RefCell<Option<(usize, String)>>,
+    locate: Option<(usize, String)>,
+    /// The path to the current value, with `None` for keys that are not known
+    path: RefCell<Vec<Option<Segment>>>,
+    key: RefCell<Option<String>>,
+    ignored: Cell<usize>,
+    unused: Option<RefCell<Vec<String>>>,
+    violation: RefCell<Option<Error>>,
+}
+
+impl Budget {
+    pub fn new(options: &DeserializeOptions) -> Budget {
+        Budget {
+            options: options.clone(),
+            depth: Cell::new(0),
+            values: Cell::new(0),
+            maps: Cell::new(0),
+            keys: RefCell::new(Vec::new()),
+            duplicate: RefCell::new(None),
+            locate: None,
+            path: RefCell::new(Vec::new()),
+            key: RefCell::new(None),
+            ignored: Cell::new(0),
+            unused: None,
+            violation: RefCell::new(None),
+        }
+    }
+
+    /// Return the encoding of the input, if it was set explicitly
+    pub fn encoding(&self) -> Option<Encoding> {
+        self.options.encoding
+    }
+
+    /// Also collect the paths of values ignored by the target type
+    pub fn collect_unused(mut self) -> Budget {
+        self.unused = Some(RefCell::new(Vec::new()));
+        self
+    }
+
+    /// Return the paths of all ignored values, in the order they were found
+    pub fn take_unused(&self) -> Vec<String> {
+        match self.unused {
+            Some(ref unused) => unused.borrow_mut().split_off(0),
+            None => Vec::new(),
+        }
+    }
+
+    /// Create a budget which fails at the first occurrence of the duplicate found by `budget`
+    ///
+    /// Deserializing the same source again with this budget reports the duplicate key
+    /// at the location of its first occurrence.
+    pub fn locate_duplicate(budget: &Budget) -> Option<Budget> {
+        let duplicate = budget.duplicate.borrow().clone()?;
+        Some(Budget {
+            locate: Some(duplicate),
+            ..Budget::new(&budget.options)
+        })
+    }
+
+    pub fn is_guarded(&self) -> bool {
+        self.options.max_depth.is_some()
+            || self.options.max_collection_len.is_some()
+            || self.options.max_values.is_some()
+            || self.options.reject_duplicate_keys
+            || self.tracks_path()
+    }
+
+    /// Return whether the contents of every value have to be checked, including ignored ones
+    fn checks_contents(&self) -> bool {
+        self.options.max_depth.is_some()
+            || self.options.max_collection_len.is_some()
+            || self.options.max_values.is_some()
+            || self.options.reject_duplicate_keys
+    }
+
+    fn tracks_path(&self) -> bool {
+        self.unused.is_some() || self.options.deny_unknown_fields
+    }
+
+    #[cfg(any(feature = "toml", feature = "ron"))]
+    pub fn rejects_duplicate_keys(&self) -> bool {
+        self.options.reject_duplicate_keys
+    }
+
+    /// Return the first violation of the options, if there was one
+    pub fn take_violation(&self) -> Option<Error> {
+        self.violation.borrow_mut().take()
+    }
+
+    fn fail<E: de::Error>(&self, error: Error) -> E {
+        let e = E::custom(&error);
+        let mut violation = self.violation.borrow_mut();
+        if violation.is_none() {
+            *violation = Some(error);
+        }
+        e
+    }
+
+    fn check<E: de::Error>(&self, value: usize, max: Option<usize>, limit: Limit) -> Result<(), E> {
+        match max {
+            Some(max) if value > max => Err(self.fail(Error::LimitExceeded { limit, max })),
+            _ => Ok(()),
+        }
+    }
+
+    fn count_value<E: de::Error>(&self) -> Result<(), E> {
+        self.values.set(self.values.get() + 1);
+        self.check(self.values.get(), self.options.max_values, Limit::Values)
+    }
+
+    fn enter<E: de::Error>(&self) -> Result<(), E> {
+        self.depth.set(self.depth.get() + 1);
+        self.check(self.depth.get(), self.options.max_depth, Limit::Depth)
+    }
+
+    fn leave(&self) {
+        self.depth.set(self.depth.get() - 1);
+    }
+
+    fn enter_path<F: FnOnce() -> Option<Segment>>(&self, segment: F) {
+        if self.tracks_path() {
+            self.path.borrow_mut().push(segment());
+        }
+    }
+
+    fn leave_path(&self) {
+        if self.tracks_path() {
+            self.path.borrow_mut().pop();
+        }
+    }
+
+    /// Start deserializing a value which the target type ignores
+    ///
+    /// Only the outermost ignored value is reported, not the values nested inside it.
+    fn enter_ignored<E: de::Error>(&self) -> Result<(), E> {
+        let path = self.path.borrow();
+        if let (0, Some(last)) = (self.ignored.get(), path.last()) {
+            if self.options.deny_unknown_fields {
+                let field = match last {
+                    Some(Segment::Key(key)) => key.clone(),
+                    Some(Segment::Index(index)) => index.to_string(),
+                    None => "?".to_string(),
+                };
+                return Err(self.fail(Error::UnknownField { field }));
+            }
+            if let Some(ref unused) = self.unused {
+                unused.borrow_mut().push(render_partial_path(&path));
+            }
+        }
+        self.ignored.set(self.ignored.get() + 1);
+        Ok(())
+    }
+
+    fn leave_ignored(&self) {
+        self.ignored.set(self.ignored.get() - 1);
+    }
+
+    /// Start collecting the keys of a new map, numbering maps in the order they are visited
+    fn enter_map(&self) {
+        if self.options.reject_duplicate_keys {
+            self.keys.borrow_mut().push((self.maps.get(), HashSet::new()));
+        }
+        self.maps.set(self.maps.get() + 1);
+    }
+
+    fn leave_map(&self) {
+        if self.options.reject_duplicate_keys {
+            self.keys.borrow_mut().pop();
+        }
+    }
+
+    /// Remember a key of the innermost map for the path, and check that it is not repeated
+    ///
+    /// The check runs while the backend is still positioned at the key,
+    /// so that the error it reports carries the location of the key.
+    fn visit_key<E: de::Error, F: FnOnce() -> String>(&self, key: F) -> Result<(), E> {
+        if !self.options.reject_duplicate_keys && !self.tracks_path() {
+            return Ok(());
+        }
+
+        let key = key();
+        if self.tracks_path() {
+            *self.key.borrow_mut() = Some(key.clone());
+        }
+        if self.options.reject_duplicate_keys {
+            self.check_key(key)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn check_key<E: de::Error>(&self, key: String) -> Result<(), E> {
+        let mut keys = self.keys.borrow_mut();
+        let (map, ref mut seen) = match keys.last_mut() {
+            Some(keys) => keys,
+            None => return Ok(()),
+        };
+
+        let first = !seen.contains(&key);
+        let is_located = match self.locate {
+            Some((located_map, ref located_key)) => first && located_map == *map && *located_key == key,
+            None => false,
+        };
+
+        if first && !is_located {
+            seen.insert(key);
+            return Ok(());
+        }
+
+        if self.duplicate.borrow().is_none() {
+            *self.duplicate.borrow_mut() = Some((*map, key.clone()));
+        }
+        Err(self.fail(Error::DuplicateKey {
+            key,
+            first: None,
+            second: None,
+        }))
+    }
+}
+
+/// A deserializer which checks every value produced by `inner` against the budget
+pub(crate) struct Guard<'b, D> {
+    inner: D,
+    budget: &'b Budget,
+    is_key: bool,
+}
+
+impl<'b, D> Guard<'b, D> {
+    pub fn new(inner: D, budget: &'b Budget) -> Guard<'b, D> {
+        Guard {
+            inner,
+            budget,
+            is_key: false,
+        }
+    }
+}
+
+macro_rules! forward_guarded {
+    ($($method:ident($($arg:ident: $ty:ty),*);)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value, D::Error> {
+                self.budget.count_value()?;
+                let visitor = GuardVisitor {
+                    inner: visitor,
+                    budget: self.budget,
+                    is_key: self.is_key,
+                };
+                self.inner.$method($($arg,)* visitor)
+            }
+        )*
+    };
+}
+
+impl<'de, 'b, D> Deserializer<'de> for Guard<'b, D>
+where
+    D: Deserializer<'de>,
+{
+    type Error = D::Error;
+
+    forward_guarded! {
+        deserialize_any();
+        deserialize_bool();
+        deserialize_i8();
+        deserialize_i16();
+        deserialize_i32();
+        deserialize_i64();
+        deserialize_i128();
+        deserialize_u8();
+        deserialize_u16();
+        deserialize_u32();
+        deserialize_u64();
+        deserialize_u128();
+        deserialize_f32();
+        deserialize_f64();
+        deserialize_char();
+        deserialize_str();
+        deserialize_string();
+        deserialize_bytes();
+        deserialize_byte_buf();
+        deserialize_option();
+        deserialize_unit();
+        deserialize_unit_struct(name: &'static str);
+        deserialize_newtype_struct(name: &'static str);
+        deserialize_seq();
+        deserialize_tuple(len: usize);
+        deserialize_tuple_struct(name: &'static str, len: usize);
+        deserialize_map();
+        deserialize_struct(name: &'static str, fields: &'static [&'static str]);
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]);
+        deserialize_identifier();
+    }
+
+    // Backends may skip ignored values without visiting their contents, which is much cheaper.
+    // Limits and duplicate keys apply to the contents as well, so ignored values are visited if they are checked.
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, D::Error> {
+        let budget = self.budget;
+        budget.enter_ignored()?;
+        let value = if budget.checks_contents() {
+            self.deserialize_any(visitor)
+        } else {
+            budget.count_value().and_then(|()| {
+                self.inner.deserialize_ignored_any(GuardVisitor {
+                    inner: visitor,
+                    budget,
+                    is_key: self.is_key,
+                })
+            })
+        };
+        budget.leave_ignored();
+        value
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.inner.is_human_readable()
+    }
+}
+
+struct GuardVisitor<'b, V> {
+    inner: V,
+    budget: &'b Budget,
+    is_key: bool,
+}
+
+impl<'b, V> GuardVisitor<'b, V> {
+    fn visit_key<E: de::Error, F: FnOnce() -> String>(&self, key: F) -> Result<(), E> {
+        if self.is_key {
+            self.budget.visit_key(key)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+macro_rules! forward_scalar {
+    ($($method:ident($ty:ty);)*) => {
+        $(
+            fn $method<E: de::Error>(self, v: $ty) -> Result<V::Value, E> {
+                self.visit_key(|| v.to_string())?;
+                self.inner.$method(v)
+            }
+        )*
+    };
+}
+
+impl<'de, 'b, V> Visitor<'de> for GuardVisitor<'b, V>
+where
+    V: Visitor<'de>,
+{
+    type Value = V::Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.inner.expecting(f)
+    }
+
+    forward_scalar! {
+        visit_bool(bool);
+        visit_i8(i8);
+        visit_i16(i16);
+        visit_i32(i32);
+        visit_i64(i64);
+        visit_i128(i128);
+        visit_u8(u8);
+        visit_u16(u16);
+        visit_u32(u32);
+        visit_u64(u64);
+        visit_u128(u128);
+        visit_f32(f32);
+        visit_f64(f64);
+        visit_char(char);
+        visit_str(&str);
+        visit_borrowed_str(&'de str);
+        visit_string(String);
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<V::Value, E> {
+        self.visit_key(|| String::from_utf8_lossy(v).into_owned())?;
+        self.inner.visit_bytes(v)
+    }
+
+    fn visit_borrowed_bytes<E: de::Error>(self, v: &'de [u8]) -> Result<V::Value, E> {
+        self.visit_key(|| String::from_utf8_lossy(v).into_owned())?;
+        self.inner.visit_borrowed_bytes(v)
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<V::Value, E> {
+        self.visit_key(|| String::from_utf8_lossy(&v).into_owned())?;
+        self.inner.visit_byte_buf(v)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<V::Value, E> {
+        self.inner.visit_none()
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<V::Value, E> {
+        self.inner.visit_unit()
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<V::Value, D::Error> {
+        let deserializer = Guard {
+            inner: deserializer,
+            budget: self.budget,
+            is_key: self.is_key,
+        };
+        self.inner.visit_some(deserializer)
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, deserializer: D) -> Result<V::Value, D::Error> {
+        let deserializer = Guard {
+            inner: deserializer,
+            budget: self.budget,
+            is_key: self.is_key,
+        };
+        self.inner.visit_newtype_struct(deserializer)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<V::Value, A::Error> {
+        self.budget.enter()?;
+        let value = self.inner.visit_seq(GuardSeq {
+            inner: seq,
+            budget: self.budget,
+            len: 0,
+        });
+        self.budget.leave();
+        value
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<V::Value, A::Error> {
+        self.budget.enter()?;
+        self.budget.enter_map();
+        let value = self.inner.visit_map(GuardMap {
+            inner: map,
+            budget: self.budget,
+            len: 0,
+        });
+        self.budget.leave_map();
+        self.budget.leave();
+        value
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<V::Value, A::Error> {
+        self.inner.visit_enum(GuardEnum {
+            inner: data,
+            budget: self.budget,
+        })
+    }
+}
+
+/// Deserializes an element of a collection, checking its index against the length limit
+struct GuardSeed<'b, S> {
+    inner: S,
+    budget: &'b Budget,
+    index: Option<usize>,
+    is_key: bool,
+}
+
+impl<'de, 'b, S> DeserializeSeed<'de> for GuardSeed<'b, S>
+where
+    S: DeserializeSeed<'de>,
+{
+    type Value = S::Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<S::Value, D::Error> {
+        if let Some(index) = self.index {
+            self.budget
+                .check(index + 1, self.budget.options.max_collection_len, Limit::CollectionLength)?;
+        }
+
+        self.inner.deserialize(Guard {
+            inner: deserializer,
+            budget: self.budget,
+            is_key: self.is_key,
+        })
+    }
+}
+
+struct GuardSeq<'b, A> {
+    inner: A,
+    budget: &'b Budget,
+    len: usize,
+}
+
+impl<'de, 'b, A> SeqAccess<'de> for GuardSeq<'b, A>
+where
+    A: SeqAccess<'de>,
+{
+    type Error = A::Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, A::Error> {
+        let index = self.len;
+        self.budget.enter_path(|| Some(Segment::Index(index)));
+        let element = self.inner.next_element_seed(GuardSeed {
+            inner: seed,
+            budget: self.budget,
+            index: Some(self.len),
+            is_key: false,
+        });
+        self.budget.leave_path();
+        let element = element?;
+        if element.is_some() {
+            self.len += 1;
+        }
+        Ok(element)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.inner.size_hint()
+    }
+}
+
+struct GuardMap<'b, A> {
+    inner: A,
+    budget: &'b Budget,
+    len: usize,
+}
+
+impl<'de, 'b, A> MapAccess<'de> for GuardMap<'b, A>
+where
+    A: MapAccess<'de>,
+{
+    type Error = A::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, A::Error> {
+        self.budget.key.borrow_mut().take();
+        let key = self.inner.next_key_seed(GuardSeed {
+            inner: seed,
+            budget: self.budget,
+            index: Some(self.len),
+            is_key: true,
+        })?;
+
+        if key.is_some() {
+            self.len += 1;
+        }
+        Ok(key)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, A::Error> {
+        let budget = self.budget;
+        budget.enter_path(|| budget.key.borrow_mut().take().map(Segment::Key));
+        let value = self.inner.next_value_seed(GuardSeed {
+            inner: seed,
+            budget,
+            index: None,
+            is_key: false,
+        });
+        budget.leave_path();
+        value
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.inner.size_hint()
+    }
+}
+
+struct GuardEnum<'b, A> {
+    inner: A,
+    budget: &'b Budget,
+}
+
+impl<'de, 'b, A> EnumAccess<'de> for GuardEnum<'b, A>
+where
+    A: EnumAccess<'de>,
+{
+    type Error = A::Error;
+    type Variant = GuardEnum<'b, A::Variant>;
+
+    fn variant_seed<S: DeserializeSeed<'de>>(self, seed: S) -> Result<(S::Value, Self::Variant), A::Error> {
+        let (value, variant) = self.inner.variant_seed(seed)?;
+        let variant = GuardEnum {
+            inner: variant,
+            budget: self.budget,
+        };
+        Ok((value, variant))
+    }
+}
+
+impl<'de, 'b, A> VariantAccess<'de> for GuardEnum<'b, A>
+where
+    A: VariantAccess<'de>,
+{
+    type Error = A::Error;
+
+    fn unit_variant(self) -> Result<(), A::Error> {
+        self.inner.unit_variant()
+    }
+
+    fn newtype_variant_seed<S: DeserializeSeed<'de>>(self, seed: S) -> Result<S::Value, A::Error> {
+        self.inner.newtype_variant_seed(GuardSeed {
+            inner: seed,
+            budget: self.budget,
+            index: None,
+            is_key: false,
+        })
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, A::Error> {
+        let visitor = GuardVisitor {
+            inner: visitor,
+            budget: self.budget,
+            is_key: false,
+        };
+        self.inner.tuple_variant(len, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, A::Error> {
+        let visitor = GuardVisitor {
+            inner: visitor,
+            budget: self.budget,
+            is_key: false,
+        };
+        self.inner.struct_variant(fields, visitor)
+    }
+}
@@ -26,6 +26,15 @@
 //! # }
 //! ```
 //!
+//! Files with a compression suffix after the extension, such as `greek.json.gz`, are compressed and decompressed
+//! transparently, with the format inferred from the inner extension. Gzip, Zstandard, XZ and bzip2 are supported
+//! with the `gzip`, `zstd`, `xz` and `bzip2` features, which are not enabled by default.
+//! Compressed data is also recognized by [`from_slice_any`](de/fn.from_slice_any.html).
+//!
+//! With the `tokio` feature, the [`async_io`](async_io/index.html) module provides futures for reading
+//! and writing asynchronously, such as `from_async_reader` and `to_file_async`, with the same format
+//! dispatch and errors as their blocking counterparts.
+//!
 //! ## Deserialization with a known format
 //!
 //! ```
@@ -48,6 +57,10 @@
 //! [`from_file`] function is provided as a convenience wrapper around
 //! [`from_reader`] for the common case of reading from a file.
 //!
+//! These functions require types that own their data. For formats that can borrow from the input,
+//! which are JSON, TOML and URL-encoded data, [`from_str_borrowed`] and [`from_slice_borrowed`] also accept
+//! types holding references such as `&'a str`.
+//!
 //! ## Deserialization by guessing
 //!
 //! ```
@@ -68,6 +81,8 @@
 //! * with [`from_file_stem`], each filename with the given stem and a supported extension
 //!   is checked. If any such file exists, its data is deserialized and returned.
 //!   This is useful for configuration files with a known set of filenames.
+//!   With [`from_file_stem_in`] or a [`FileSearch`], the stem is looked up in a list of directories,
+//!   such as the current directory, `$XDG_CONFIG_HOME/app` and `/etc/app`.
 //! * with [`from_slice_any`] and [`from_str_any`], deserialization
 //!   using each supported format is tried until one succeeds.
 //!   This is useful when you receive data from an unknown source and don't know what format it is in.
@@ -76,6 +91,109 @@
 //! consume its data. In order to deserialize from a [`io::Read`], read the data into a [`Vec<u8>`] or [`String`] and
 //! call [`from_slice_any`] or [`from_str_any`].
 //!
+//! ## Extracting single values
+//!
+//! ```
+//! # use serde_any::Format;
+//! let source = r#"{"servers": [{"host": "example.com", "port": 8080}]}"#;
+//! let host: String = serde_any::get(source, Format::Json, "servers[0].host").unwrap();
+//! let port: u16 = serde_any::get(source, Format::Json, "/servers/0/port").unwrap();
+//! ```
+//!
+//! With [`get`], a single value can be deserialized from a document in any format,
+//! addressed by a path such as `servers[0].host` or by a JSON Pointer such as `/servers/0/host`.
+//! If the path does not exist, [`Error::PathNotFound`] tells how much of it does.
+//!
+//! ## Streaming sequences
+//!
+//! With [`stream_seq`], the elements of a JSON array, a RON list or a YAML sequence are read and deserialized
+//! one at a time, without holding the whole input in memory. An element that fails to deserialize yields an
+//! error with its index, and the following elements are still read.
+//!
+//! ## Comparing documents
+//!
+//! ```
+//! # use serde_any::Format;
+//! let changes = serde_any::diff("port: 80", Format::Yaml, r#"{"port": 8080}"#, Format::Json).unwrap();
+//! print!("{}", serde_any::render_diff(&changes));
+//! ```
+//!
+//! With [`diff`], two documents in any formats are compared value by value,
+//! listing the paths that were added, removed or changed. [`DiffOptions`] control whether key order
+//! matters and whether integers and floats with the same value are equal.
+//!
+//! ## Patching documents
+//!
+//! [`apply_merge_patch`] applies an RFC 7386 merge patch, and [`apply_patch`] applies an RFC 6902 patch,
+//! to a document in any format. The patch can be in a different format than the document,
+//! and the result is returned in the format of the document.
+//!
+//! ## Untrusted input
+//!
+//! ```
+//! # use std::collections::HashMap;
+//! use serde_any::DeserializeOptions;
+//!
+//! let options = DeserializeOptions::new().max_input_bytes(1024).max_depth(8).max_values(1000);
+//! let m: HashMap<String, String> = serde_any::from_str_any_with("a: alpha", &options).unwrap();
+//! # assert_eq!(m.get("a"), Some(&"alpha".to_string()));
+//! ```
+//!
+//! All deserialization functions have variants with a `_with` suffix, such as [`from_slice_any_with`],
+//! which accept [`DeserializeOptions`]. These limit the size of the input, the nesting depth,
+//! the length of collections and the total number of values, and can reject duplicate keys.
+//! The limits are enforced in the same way for all formats.
+//! In particular, a repeated key is an error in every format when duplicate keys are rejected,
+//! instead of the later value silently winning in some formats.
+//!
+//! ## Character encodings
+//!
+//! Byte slices, readers and files in UTF-16 or UTF-32 are detected by their byte order mark,
+//! or by the zero bytes around the first character, and transcoded to UTF-8 before deserializing them.
+//! A UTF-8 byte order mark is skipped. Input in an encoding which cannot be detected, such as Latin-1,
+//! can be read by setting [`DeserializeOptions::encoding`].
+//!
+//! ## Unknown fields
+//!
+//! ```
+//! # use std::collections::HashMap;
+//! # use serde_any::Format;
+//! #[derive(serde::Deserialize)]
+//! struct Server {
+//!     #[serde(default)]
+//!     port: u16,
+//! }
+//!
+//! let (server, unused) = serde_any::from_str_with_unused::<Server>("prot = 8080", Format::Toml).unwrap();
+//! assert_eq!(server.port, 0);
+//! assert_eq!(unused, vec!["prot"]);
+//! ```
+//!
+//! Fields that the target type does not use, such as misspelled fields that silently fall back to defaults,
+//! are reported by [`from_str_with_unused`] and [`from_file_with_unused`].
+//! Alternatively, [`DeserializeOptions::deny_unknown_fields`] turns them into errors.
+//!
+//! ## Layered configuration
+//!
+//! ```
+//! # use serde_any::{ConfigBuilder, Format, Value};
+//! let config = ConfigBuilder::new()
+//!     .string("defaults", "port = 80\nhost = \"localhost\"", Format::Toml)
+//!     .optional_file("/etc/app/config.yaml")
+//!     .optional_file_stem("app")
+//!     .env("APP")
+//!     .build_layered::<Value>()
+//!     .unwrap();
+//! # assert_eq!(config.source_of("port"), Some("defaults"));
+//! ```
+//!
+//! A [`ConfigBuilder`] loads configuration from several files and strings, possibly in different formats,
+//! and deep-merges them in order, with later sources taking precedence.
+//! The result is deserialized into any type, and the source of each value can be queried.
+//! Merging works on [`Value`], which can hold data from any format.
+//! Environment variables such as `APP_DATABASE__PORT=5432` can be layered on top,
+//! with their string values converted to the numbers, booleans or lists that the target type expects.
+//!
 //! ## Serialization
 //!
 //! ```
@@ -104,6 +222,48 @@
 //! In such cases, the output from pretty printing functions will be identical to the output
 //! from serialization functions without pretty printing.
 //!
+//! The output can be configured further with [`SerializeOptions`], accepted by functions with a `_with` suffix,
+//! such as [`to_string_with`]. These control indentation, line endings, sorting of keys
+//! and several format-specific settings.
+//! With [`SerializeOptions::canonical`], the output is byte-identical for equal values,
+//! which [`canonical_hash`] uses to hash values by their content.
+//!
+//! ## Editing files
+//!
+//! ```no_run
+//! # fn main() -> Result<(), serde_any::Error> {
+//! serde_any::Document::open("config.toml")?.set("server.port", 8080)?.save()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! A [`Document`] changes individual values in a file, addressed by paths such as `servers[0].host`.
+//! TOML and JSON files keep their comments and formatting. Files in other formats are rewritten
+//! completely, with a warning.
+//!
+//! ## Error reporting
+//!
+//! ```
+//! # use std::collections::HashMap;
+//! # use serde_any::Format;
+//! let source = "a = 1\nb = = 2\n";
+//! let err = serde_any::from_str::<HashMap<String, u32>>(source, Format::Toml).unwrap_err();
+//!
+//! let location = err.location().unwrap();
+//! assert_eq!((location.line, location.column), (2, 5));
+//!
+//! eprintln!("{}", err.diagnostic(source, "numbers.toml"));
+//! ```
+//!
+//! Deserialization errors report the [`Location`] in the source where parsing failed,
+//! and the path to the offending value if it was nested inside the data.
+//! Errors inside nested values are wrapped in [`Error::Field`] together with that path,
+//! while errors that cannot be tied to a value are returned as the format-specific variant.
+//! With [`Error::diagnostic`], an error can be rendered together with a snippet of the source,
+//! in a format suitable for showing to end users.
+//! Errors from functions that read or write files are wrapped in [`Error::File`] together with the path of the file.
+//! [`Error::root`] returns the underlying error without these wrappers, for matching on format-specific variants.
+//!
 //! ## Known limitations
 //!
 //! * Serialization to TOML requires that all non-table values come before any tables.
@@ -114,10 +274,20 @@
 //! [`from_reader`]: de/fn.from_reader.html
 //! [`from_slice`]: de/fn.from_slice.html
 //! [`from_str`]: de/fn.from_str.html
+//! [`from_str_borrowed`]: de/fn.from_str_borrowed.html
+//! [`from_slice_borrowed`]: de/fn.from_slice_borrowed.html
 //! [`from_file`]: de/fn.from_file.html
 //! [`from_file_stem`]: de/fn.from_file_stem.html
+//! [`from_file_stem_in`]: de/fn.from_file_stem_in.html
+//! [`FileSearch`]: search/struct.FileSearch.html
 //! [`from_slice_any`]: de/fn.from_slice_any.html
 //! [`from_str_any`]: de/fn.from_str_any.html
+//! [`from_slice_any_with`]: de/fn.from_slice_any_with.html
+//! [`DeserializeOptions::encoding`]: de/struct.DeserializeOptions.html#method.encoding
+//! [`DeserializeOptions`]: de/struct.DeserializeOptions.html
+//! [`DeserializeOptions::deny_unknown_fields`]: de/struct.DeserializeOptions.html#method.deny_unknown_fields
+//! [`from_str_with_unused`]: de/fn.from_str_with_unused.html
+//! [`from_file_with_unused`]: de/fn.from_file_with_unused.html
 //! [`to_string`]: ser/fn.to_string.html
 //! [`to_vec`]: ser/fn.to_vec.html
 //! [`to_writer`]: ser/fn.to_writer.html
@@ -127,7 +297,26 @@
 //! [`io::Read`]: https://doc.rust-lang.org/std/io/trait.Read.html
 //! [`io::Write`]: https://doc.rust-lang.org/std/io/trait.Write.html
 //! [`to_string_pretty`]: ser/fn.to_string_pretty.html
+//! [`SerializeOptions`]: ser/struct.SerializeOptions.html
+//! [`to_string_with`]: ser/fn.to_string_with.html
+//! [`SerializeOptions::canonical`]: ser/struct.SerializeOptions.html#method.canonical
+//! [`canonical_hash`]: ser/fn.canonical_hash.html
 //! [`toml::ser`]: https://docs.rs/toml/0.4.6/toml/ser/index.html
+//! [`ConfigBuilder`]: config/struct.ConfigBuilder.html
+//! [`Document`]: document/struct.Document.html
+//! [`get`]: query/fn.get.html
+//! [`stream_seq`]: stream/fn.stream_seq.html
+//! [`diff`]: diff/fn.diff.html
+//! [`apply_merge_patch`]: patch/fn.apply_merge_patch.html
+//! [`apply_patch`]: patch/fn.apply_patch.html
+//! [`DiffOptions`]: diff/struct.DiffOptions.html
+//! [`Error::PathNotFound`]: error/enum.Error.html#variant.PathNotFound
+//! [`Value`]: value/enum.Value.html
+//! [`Location`]: error/struct.Location.html
+//! [`Error::diagnostic`]: error/enum.Error.html#method.diagnostic
+//! [`Error::Field`]: error/enum.Error.html#variant.Field
+//! [`Error::File`]: error/enum.Error.html#variant.File
+//! [`Error::root`]: error/enum.Error.html#method.root
 //!
 
 #[macro_use]
@@ -137,6 +326,9 @@ extern crate serde;
 #[cfg(feature = "toml")]
 extern crate toml;
 
+#[cfg(all(feature = "toml", feature = "toml_edit"))]
+extern crate toml_edit;
+
 #[cfg(feature = "json")]
 extern crate serde_json;
 
@@ -152,6 +344,29 @@ extern crate serde_xml_any;
 #[cfg(feature = "url")]
 extern crate serde_urlencoded;
 
+#[cfg(feature = "url")]
+extern crate form_urlencoded;
+
+extern crate serde_path_to_error;
+
+#[cfg(feature = "sha2")]
+extern crate sha2;
+
+#[cfg(feature = "gzip")]
+extern crate flate2;
+
+#[cfg(feature = "zstd")]
+extern crate zstd;
+
+#[cfg(feature = "xz")]
+extern crate xz2;
+
+#[cfg(feature = "bzip2")]
+extern crate bzip2;
+
+#[cfg(feature = "tokio")]
+extern crate tokio;
+
 #[cfg(test)]
 #[macro_use]
 extern crate serde_derive;
@@ -161,15 +376,31 @@ extern crate serde_derive;
 extern crate matches;
 
 mod backend;
+#[cfg(feature = "json")]
+mod canonical;
+mod content;
+mod guard;
+mod path;
 
 /// Contains the common error type
 pub mod error;
 pub use error::Error;
 
+/// Human-friendly rendering of errors
+pub mod diagnostic;
+
 /// Types and functions for specifying or determining serialization formats
 pub mod format;
 pub use format::*;
 
+/// Types and functions for compressed files and data
+pub mod compression;
+pub use compression::*;
+
+/// Types and functions for character encodings of input data
+pub mod encoding;
+pub use encoding::*;
+
 /// Deserialize data to a Rust structure
 pub mod de;
 pub use de::*;
@@ -177,3 +408,41 @@ pub use de::*;
 /// Serialize a Rust structure to any data format
 pub mod ser;
 pub use ser::*;
+
+/// A format-independent representation of data
+pub mod value;
+pub use value::{from_value, to_value, Value};
+
+/// Edit files while preserving their comments and formatting
+pub mod document;
+pub use document::*;
+
+/// Extract single values from data by their path
+pub mod query;
+pub use query::*;
+
+/// Compare documents in any formats
+pub mod diff;
+pub use diff::*;
+
+/// Apply JSON Merge Patches and JSON Patches to documents in any formats
+pub mod patch;
+pub use patch::*;
+
+/// Asynchronous reading and writing with Tokio
+#[cfg(feature = "tokio")]
+pub mod async_io;
+#[cfg(feature = "tokio")]
+pub use async_io::*;
+
+/// Deserialize the elements of a sequence one at a time
+pub mod stream;
+pub use stream::*;
+
+/// Search for files in a list of directories
+pub mod search;
+pub use search::*;
+
+/// Configuration loaded from several layered sources
+pub mod config;
+pub use config::*;
@@ -0,0 +1,426 @@
+use de::from_str;
+use diff::{diff_values, DiffOptions};
+use error::Error;
+use format::Format;
+use path::{parse_pointer, render_pointer, Segment};
+use ser::{to_string_with, SerializeOptions};
+use value::{Map, Value};
+
+/// Apply a JSON Merge Patch (RFC 7386) to a document
+///
+/// The document and the patch can be in different formats, and the result is serialized
+/// in the format of the document. Maps in the patch are merged into the document recursively,
+/// a `null` removes the key, and any other value replaces the value in the document.
+///
+/// The result is serialized again, so comments and formatting of the document are not preserved.
+/// Use a [`Document`] to change individual values while keeping them.
+///
+/// # Errors
+///
+/// If the document or the patch cannot be parsed, the same error as from [`from_str`] is returned.
+/// If the result cannot be serialized in the format of the document, the format-specific [`Error`] variant
+/// is returned.
+///
+/// # Example
+///
+/// ```
+/// use serde_any::Format;
+///
+/// # fn main() -> Result<(), serde_any::Error> {
+/// let document = "[server]\nhost = \"localhost\"\nport = 80\n";
+/// let patch = r#"{"server": {"port": 8080, "host": null}}"#;
+///
+/// let patched = serde_any::apply_merge_patch(document, Format::Toml, patch, Format::Json)?;
+/// assert_eq!(patched, "[server]\nport = 8080\n");
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`Document`]: ../document/struct.Document.html
+/// [`Error`]: ../error/enum.Error.html
+/// [`from_str`]: ../de/fn.from_str.html
+pub fn apply_merge_patch(document: &str, format: Format, patch: &str, patch_format: Format) -> Result<String, Error> {
+    let mut target: Value = from_str(document, format)?;
+    let patch: Value = from_str(patch, patch_format)?;
+    merge_patch(&mut target, &patch);
+    serialize(&target, format)
+}
+
+/// Apply a JSON Patch (RFC 6902) to a document
+///
+/// The document and the patch can be in different formats, and the result is serialized
+/// in the format of the document. The patch is a sequence of operations, each a map with the keys
+/// `op`, `path`, and `value` or `from` depending on the operation. Paths are JSON Pointers.
+/// The operations are applied in order, and if one of them fails, no result is returned.
+///
+/// The result is serialized again, so comments and formatting of the document are not preserved.
+///
+/// # Errors
+///
+/// If the document or the patch cannot be parsed, the same error as from [`from_str`] is returned.
+/// If a `test` operation finds a different value, [`Error::PatchTestFailed`] is returned
+/// with both values. If any other operation is invalid or cannot be applied, for example because its path
+/// does not exist, [`Error::PatchFailed`] is returned. Both errors include the index of the operation.
+/// If the result cannot be serialized in the format of the document, the format-specific [`Error`] variant
+/// is returned.
+///
+/// # Example
+///
+/// ```
+/// use serde_any::{Error, Format, Value};
+///
+/// # fn main() -> Result<(), serde_any::Error> {
+/// let document = "hosts:\n  - a\n  - b\n";
+/// let patch = r#"[
+///     {"op": "test", "path": "/hosts/0", "value": "a"},
+///     {"op": "add", "path": "/hosts/-", "value": "c"},
+///     {"op": "remove", "path": "/hosts/1"}
+/// ]"#;
+///
+/// let patched = serde_any::apply_patch(document, Format::Yaml, patch, Format::Json)?;
+/// assert_eq!(serde_any::get::<Vec<String>>(&patched, Format::Yaml, "hosts")?, vec!["a", "c"]);
+///
+/// let patch = r#"[{"op": "test", "path": "/hosts/0", "value": "b"}]"#;
+/// let error = serde_any::apply_patch(document, Format::Yaml, patch, Format::Json).unwrap_err();
+/// assert_eq!(error.to_string(), r#"Patch operation 0 failed: test at /hosts/0 expected "b", found "a""#);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`Error`]: ../error/enum.Error.html
+/// [`Error::PatchTestFailed`]: ../error/enum.Error.html#variant.PatchTestFailed
+/// [`Error::PatchFailed`]: ../error/enum.Error.html#variant.PatchFailed
+/// [`from_str`]: ../de/fn.from_str.html
+pub fn apply_patch(document: &str, format: Format, patch: &str, patch_format: Format) -> Result<String, Error> {
+    let mut target: Value = from_str(document, format)?;
+    let operations: Vec<Value> = from_str(patch, patch_format)?;
+    patch_value(&mut target, &operations)?;
+    serialize(&target, format)
+}
+
+/// Serialize a patched document
+///
+/// Keys are sorted so that TOML tables are written after all other values of their table,
+/// which a map sorted only by key does not guarantee.
+fn serialize(target: &Value, format: Format) -> Result<String, Error> {
+    to_string_with(target, format, &SerializeOptions::new().sort_keys(true))
+}
+
+/// Apply a JSON Merge Patch (RFC 7386) to a value
+///
+/// This works like [`apply_merge_patch`], on values that were already deserialized.
+///
+/// [`apply_merge_patch`]: fn.apply_merge_patch.html
+pub fn merge_patch(target: &mut Value, patch: &Value) {
+    let entries = match patch {
+        Value::Map(entries) => entries,
+        patch => {
+            *target = patch.clone();
+            return;
+        }
+    };
+
+    if !matches!(target, Value::Map(_)) {
+        *target = Value::Map(Map::new());
+    }
+    if let Value::Map(map) = target {
+        for (key, value) in entries {
+            match value {
+                Value::Null => {
+                    map.remove(key);
+                }
+                value => merge_patch(map.entry(key.clone()).or_insert(Value::Null), value),
+            }
+        }
+    }
+}
+
+/// Apply a JSON Patch (RFC 6902) to a value
+///
+/// This works like [`apply_patch`], on values that were already deserialized.
+/// If an operation fails, `target` is left unchanged.
+///
+/// # Errors
+///
+/// Errors are the same as for [`apply_patch`], except for parsing and serialization.
+///
+/// [`apply_patch`]: fn.apply_patch.html
+pub fn patch_value(target: &mut Value, operations: &[Value]) -> Result<(), Error> {
+    let mut patched = target.clone();
+    for (index, operation) in operations.iter().enumerate() {
+        apply_operation(&mut patched, index, operation)?;
+    }
+    *target = patched;
+    Ok(())
+}
+
+fn apply_operation(target: &mut Value, index: usize, operation: &Value) -> Result<(), Error> {
+    let failed = |reason: String| Error::PatchFailed { index, reason };
+
+    let op = match operation.get("op") {
+        Some(Value::String(op)) => op.as_str(),
+        Some(other) => return Err(failed(format!("\"op\" must be a string, found {}", other.type_name()))),
+        None => return Err(failed("missing \"op\"".to_string())),
+    };
+    let pointer = |key: &str| -> Result<Vec<Segment>, Error> {
+        match operation.get(key) {
+            Some(Value::String(pointer)) => {
+                parse_pointer(pointer).map_err(|reason| failed(format!("invalid \"{}\": {}", key, reason)))
+            }
+            Some(other) => Err(failed(format!(
+                "\"{}\" must be a string, found {}",
+                key,
+                other.type_name()
+            ))),
+            None => Err(failed(format!("missing \"{}\" for {}", key, op))),
+        }
+    };
+    let value = || -> Result<Value, Error> {
+        operation
+            .get("value")
+            .cloned()
+            .ok_or_else(|| failed(format!("missing \"value\" for {}", op)))
+    };
+
+    match op {
+        "add" => add(target, &pointer("path")?, value()?),
+        "remove" => remove(target, &pointer("path")?).map(|_| ()),
+        "replace" => {
+            let (path, value) = (pointer("path")?, value()?);
+            remove(target, &path).and_then(|_| add(target, &path, value))
+        }
+        "move" => {
+            let (from, path) = (pointer("from")?, pointer("path")?);
+            if path.len() > from.len() && path.starts_with(&from) {
+                return Err(failed(format!("cannot move {} into itself", render_pointer(&from))));
+            }
+            remove(target, &from).and_then(|moved| add(target, &path, moved))
+        }
+        "copy" => {
+            let (from, path) = (pointer("from")?, pointer("path")?);
+            let copied = find(target, &from).cloned().ok_or_else(|| failed(not_found(&from)))?;
+            add(target, &path, copied)
+        }
+        "test" => {
+            let (path, expected) = (pointer("path")?, value()?);
+            let actual = find(target, &path).ok_or_else(|| failed(not_found(&path)))?;
+            let options = DiffOptions::new().numeric_equivalence(true);
+            if !diff_values(actual, &expected, &options).is_empty() {
+                return Err(Error::PatchTestFailed {
+                    index,
+                    path: render_pointer(&path),
+                    expected,
+                    actual: actual.clone(),
+                });
+            }
+            Ok(())
+        }
+        op => Err(format!("unknown operation \"{}\"", op)),
+    }
+    .map_err(failed)
+}
+
+fn not_found(path: &[Segment]) -> String {
+    format!("no value at {}", render_pointer(path))
+}
+
+/// Return the map key for a segment of a JSON Pointer, where indices are also valid keys
+fn key(segment: &Segment) -> String {
+    match segment {
+        Segment::Key(key) => key.clone(),
+        Segment::Index(index) => index.to_string(),
+    }
+}
+
+fn find<'a>(target: &'a Value, path: &[Segment]) -> Option<&'a Value> {
+    path.iter()
+        .try_fold(target, |current, segment| match (current, segment) {
+            (Value::Map(map), segment) => map.get(&key(segment)),
+            (Value::Seq(seq), &Segment::Index(index)) => seq.get(index),
+            _ => None,
+        })
+}
+
+fn find_mut<'a>(target: &'a mut Value, path: &[Segment]) -> Option<&'a mut Value> {
+    path.iter()
+        .try_fold(target, |current, segment| match (current, segment) {
+            (Value::Map(map), segment) => map.get_mut(&key(segment)),
+            (Value::Seq(seq), &Segment::Index(index)) => seq.get_mut(index),
+            _ => None,
+        })
+}
+
+/// Add a value at `path`, whose parent must exist
+///
+/// Values in maps are replaced, and values in sequences are inserted before the element at the index,
+/// or appended if the index is `-`.
+fn add(target: &mut Value, path: &[Segment], value: Value) -> Result<(), String> {
+    let (last, parent) = match path.split_last() {
+        Some(split) => split,
+        None => {
+            *target = value;
+            return Ok(());
+        }
+    };
+
+    match (find_mut(target, parent), last) {
+        (Some(Value::Map(map)), segment) => {
+            map.insert(key(segment), value);
+            Ok(())
+        }
+        (Some(Value::Seq(seq)), &Segment::Index(index)) if index <= seq.len() => {
+            seq.insert(index, value);
+            Ok(())
+        }
+        (Some(Value::Seq(seq)), Segment::Key(key)) if key == "-" => {
+            seq.push(value);
+            Ok(())
+        }
+        (Some(Value::Seq(seq)), _) => Err(format!(
+            "{} is not a valid index into a sequence of {} elements",
+            render_pointer(path),
+            seq.len()
+        )),
+        (Some(other), _) => Err(format!(
+            "cannot add to {}, found {}",
+            render_pointer(parent),
+            other.type_name()
+        )),
+        (None, _) => Err(not_found(parent)),
+    }
+}
+
+/// Remove and return the value at `path`, which must exist
+fn remove(target: &mut Value, path: &[Segment]) -> Result<Value, String> {
+    let (last, parent) = match path.split_last() {
+        Some(split) => split,
+        None => return Ok(::std::mem::replace(target, Value::Null)),
+    };
+
+    let removed = match (find_mut(target, parent), last) {
+        (Some(Value::Map(map)), segment) => map.remove(&key(segment)),
+        (Some(Value::Seq(seq)), &Segment::Index(index)) if index < seq.len() => Some(seq.remove(index)),
+        _ => None,
+    };
+    removed.ok_or_else(|| not_found(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patch(target: &mut Value, patch: &str) -> Result<(), Error> {
+        patch_value(target, &from_str::<Vec<Value>>(patch, Format::Json).unwrap())
+    }
+
+    fn json(source: &str) -> Value {
+        from_str(source, Format::Json).unwrap()
+    }
+
+    #[test]
+    fn merge_patches() {
+        // Examples from RFC 7386, appendix A
+        let cases = [
+            (r#"{"a":"b"}"#, r#"{"a":"c"}"#, r#"{"a":"c"}"#),
+            (r#"{"a":"b"}"#, r#"{"b":"c"}"#, r#"{"a":"b","b":"c"}"#),
+            (r#"{"a":"b"}"#, r#"{"a":null}"#, r#"{}"#),
+            (
+                r#"{"a":{"b":"c"}}"#,
+                r#"{"a":{"b":"d","c":null}}"#,
+                r#"{"a":{"b":"d"}}"#,
+            ),
+            (r#"{"a":[{"b":"c"}]}"#, r#"{"a":[1]}"#, r#"{"a":[1]}"#),
+            (r#"["a","b"]"#, r#"["c","d"]"#, r#"["c","d"]"#),
+            (r#"{"a":"foo"}"#, r#""bar""#, r#""bar""#),
+            (r#"{"e":null}"#, r#"{"a":1}"#, r#"{"e":null,"a":1}"#),
+            (r#"[1,2]"#, r#"{"a":"b","c":null}"#, r#"{"a":"b"}"#),
+            (r#"{}"#, r#"{"a":{"bb":{"ccc":null}}}"#, r#"{"a":{"bb":{}}}"#),
+        ];
+
+        for &(target, patch, result) in &cases {
+            let mut target = json(target);
+            merge_patch(&mut target, &json(patch));
+            assert_eq!(target, json(result));
+        }
+
+        let patched = apply_merge_patch("a: 1\nb: [x]\n", Format::Yaml, "b = [\"y\"]", Format::Toml).unwrap();
+        assert_eq!(
+            from_str::<Value>(&patched, Format::Yaml).unwrap(),
+            json(r#"{"a": 1, "b": ["y"]}"#)
+        );
+
+        let document = "zone = \"eu\"\n\n[server]\nport = 80\n";
+        let patched = apply_merge_patch(document, Format::Toml, "{}", Format::Json).unwrap();
+        assert_eq!(patched, "zone = 'eu'\n\n[server]\nport = 80\n");
+        let patched = apply_patch(document, Format::Toml, "[]", Format::Json).unwrap();
+        assert_eq!(patched, "zone = 'eu'\n\n[server]\nport = 80\n");
+
+        // Datetimes are kept as strings instead of the private map the TOML backend uses for them
+        let patched = apply_merge_patch("created = 1979-05-27T07:32:00Z\n", Format::Toml, "{}", Format::Json).unwrap();
+        assert_eq!(patched, "created = '1979-05-27T07:32:00Z'\n");
+    }
+
+    #[test]
+    fn patches() {
+        let mut target = json(r#"{"foo": ["bar", "baz"], "a": {"b": 1}}"#);
+        patch(
+            &mut target,
+            r#"[
+                {"op": "add", "path": "/foo/1", "value": "qux"},
+                {"op": "replace", "path": "/a/b", "value": 2.0},
+                {"op": "test", "path": "/a/b", "value": 2},
+                {"op": "copy", "from": "/a", "path": "/c"},
+                {"op": "move", "from": "/foo/0", "path": "/d"},
+                {"op": "remove", "path": "/foo/1"},
+                {"op": "add", "path": "/foo/-", "value": {"x": null}},
+                {"op": "add", "path": "/1", "value": true}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            target,
+            json(r#"{"foo": ["qux", {"x": null}], "a": {"b": 2.0}, "c": {"b": 2.0}, "d": "bar", "1": true}"#)
+        );
+    }
+
+    #[test]
+    fn patch_errors() {
+        let original = json(r#"{"a": [1, 2], "b": "x"}"#);
+        let mut target = original.clone();
+
+        let error = patch(
+            &mut target,
+            r#"[{"op": "remove", "path": "/b"}, {"op": "test", "path": "/a", "value": [1, 3]}]"#,
+        )
+        .unwrap_err();
+        assert_matches!(error, Error::PatchTestFailed { index: 1, ref path, .. } if path == "/a");
+        assert_eq!(
+            error.to_string(),
+            "Patch operation 1 failed: test at /a expected [1, 3], found [1, 2]"
+        );
+        assert_eq!(target, original);
+
+        let cases = [
+            (r#"[{"op": "remove", "path": "/c"}]"#, "no value at /c"),
+            (
+                r#"[{"op": "add", "path": "/a/5", "value": 0}]"#,
+                "/a/5 is not a valid index into a sequence of 2 elements",
+            ),
+            (
+                r#"[{"op": "add", "path": "/b/c", "value": 0}]"#,
+                "cannot add to /b, found string",
+            ),
+            (
+                r#"[{"op": "move", "from": "/a", "path": "/a/0"}]"#,
+                "cannot move /a into itself",
+            ),
+            (r#"[{"op": "test", "path": "/a"}]"#, "missing \"value\" for test"),
+            (r#"[{"op": "take", "path": "/a"}]"#, "unknown operation \"take\""),
+            (r#"[{"path": "/a"}]"#, "missing \"op\""),
+        ];
+        for &(operations, expected) in &cases {
+            let error = patch(&mut target, operations).unwrap_err();
+            assert_matches!(error, Error::PatchFailed { index: 0, ref reason } if reason == expected);
+        }
+    }
+}
@@ -0,0 +1,341 @@
+// Paths to values nested inside data, such as `servers[0].host`.
+//
+// Paths are rendered in error messages and parsed from user input in the same syntax:
+// map keys separated by dots and sequence indices in brackets. Keys containing dots, brackets
+// or quotes can be written in double quotes when parsing, for example `hosts."example.com".port`.
+// Queries also accept JSON Pointers (RFC 6901), such as `/servers/0/host`.
+
+use value::Value;
+
+/// A part of the path to a value
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+/// Render a path in the same way as paths in [`Error::Field`]
+///
+/// [`Error::Field`]: ../error/enum.Error.html#variant.Field
+pub(crate) fn render_path(path: &[Segment]) -> String {
+    let mut rendered = String::new();
+    for segment in path {
+        match segment {
+            Segment::Key(key) if rendered.is_empty() => rendered.push_str(key),
+            Segment::Key(key) => {
+                rendered.push('.');
+                rendered.push_str(key);
+            }
+            Segment::Index(index) => rendered.push_str(&format!("[{}]", index)),
+        }
+    }
+    rendered
+}
+
+/// Render a path in which some segments are not known, such as keys which are not strings, as `?`
+pub(crate) fn render_partial_path(path: &[Option<Segment>]) -> String {
+    let mut rendered = String::new();
+    for segment in path {
+        match segment {
+            Some(Segment::Index(index)) => rendered.push_str(&format!("[{}]", index)),
+            key => {
+                if !rendered.is_empty() {
+                    rendered.push('.');
+                }
+                match key {
+                    Some(Segment::Key(key)) => rendered.push_str(key),
+                    _ => rendered.push('?'),
+                }
+            }
+        }
+    }
+    rendered
+}
+
+/// Parse a path, returning the reason if it is not valid
+///
+/// An empty path refers to the whole data.
+pub(crate) fn parse_path(path: &str) -> Result<Vec<Segment>, String> {
+    let mut segments = Vec::new();
+    let mut chars = path.char_indices().peekable();
+    let mut expect_key = true;
+
+    while let Some(&(position, c)) = chars.peek() {
+        match c {
+            '[' => {
+                chars.next();
+                let mut digits = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, ']')) => break,
+                        Some((_, d)) if d.is_ascii_digit() => digits.push(d),
+                        _ => return Err(format!("invalid index at position {}", position)),
+                    }
+                }
+                let index = digits
+                    .parse()
+                    .map_err(|_| format!("invalid index at position {}", position))?;
+                segments.push(Segment::Index(index));
+                expect_key = false;
+            }
+            '.' if !expect_key => {
+                chars.next();
+                expect_key = true;
+                if chars.peek().is_none() {
+                    return Err("path ends with a dot".to_string());
+                }
+            }
+            '"' if expect_key => {
+                chars.next();
+                let mut key = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, escaped)) => key.push(escaped),
+                            None => return Err(format!("unterminated key at position {}", position)),
+                        },
+                        Some((_, k)) => key.push(k),
+                        None => return Err(format!("unterminated key at position {}", position)),
+                    }
+                }
+                segments.push(Segment::Key(key));
+                expect_key = false;
+            }
+            _ if expect_key => {
+                let mut key = String::new();
+                while let Some(&(_, k)) = chars.peek() {
+                    if k == '.' || k == '[' || k == '"' {
+                        break;
+                    }
+                    key.push(k);
+                    chars.next();
+                }
+                if key.is_empty() {
+                    return Err(format!("empty key at position {}", position));
+                }
+                segments.push(Segment::Key(key));
+                expect_key = false;
+            }
+            _ => return Err(format!("unexpected character '{}' at position {}", c, position)),
+        }
+    }
+
+    Ok(segments)
+}
+
+/// Parse a JSON Pointer, returning the reason if it is not valid
+///
+/// Tokens consisting only of digits are parsed as indices, but still match map keys with the same digits.
+pub(crate) fn parse_pointer(pointer: &str) -> Result<Vec<Segment>, String> {
+    if pointer.is_empty() {
+        return Ok(vec![]);
+    }
+    if !pointer.starts_with('/') {
+        return Err("a JSON Pointer must start with '/'".to_string());
+    }
+
+    let mut segments = Vec::new();
+    for token in pointer[1..].split('/') {
+        let mut key = String::new();
+        let mut chars = token.chars();
+        while let Some(c) = chars.next() {
+            if c != '~' {
+                key.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('0') => key.push('~'),
+                Some('1') => key.push('/'),
+                _ => return Err(format!("invalid escape in token \"{}\"", token)),
+            }
+        }
+        let is_index =
+            !key.is_empty() && key.bytes().all(|b| b.is_ascii_digit()) && (key == "0" || !key.starts_with('0'));
+        match key.parse() {
+            Ok(index) if is_index => segments.push(Segment::Index(index)),
+            _ => segments.push(Segment::Key(key)),
+        }
+    }
+    Ok(segments)
+}
+
+/// Render a path as a JSON Pointer
+pub(crate) fn render_pointer(path: &[Segment]) -> String {
+    let mut rendered = String::new();
+    for segment in path {
+        rendered.push('/');
+        match segment {
+            Segment::Key(key) => rendered.push_str(&key.replace('~', "~0").replace('/', "~1")),
+            Segment::Index(index) => rendered.push_str(&index.to_string()),
+        }
+    }
+    rendered
+}
+
+/// Take the value at `path` out of `root`
+///
+/// Indices also match map keys with the same digits, and keys consisting of digits also match indices.
+/// If the path does not exist, returns the number of segments of the longest prefix that does.
+pub(crate) fn take_value(root: Value, path: &[Segment]) -> Result<Value, usize> {
+    let mut current = root;
+    for (depth, segment) in path.iter().enumerate() {
+        let next = match (current, segment) {
+            (Value::Map(mut map), Segment::Key(key)) => map.remove(key),
+            (Value::Map(mut map), Segment::Index(index)) => map.remove(&index.to_string()),
+            (Value::Seq(seq), &Segment::Index(index)) => seq.into_iter().nth(index),
+            (Value::Seq(seq), Segment::Key(key)) => key.parse().ok().and_then(|index| seq.into_iter().nth(index)),
+            _ => None,
+        };
+        current = next.ok_or(depth)?;
+    }
+    Ok(current)
+}
+
+/// Describe the value at a path for error messages
+fn describe(path: &[Segment]) -> String {
+    if path.is_empty() {
+        "the root".to_string()
+    } else {
+        format!("`{}`", render_path(path))
+    }
+}
+
+/// Set the value at `path` inside `root`, creating missing maps along the way
+///
+/// A sequence index may be equal to the length of the sequence, in which case the value is appended.
+/// Returns the reason if the path does not fit the structure of `root`.
+pub(crate) fn set_value(root: &mut Value, path: &[Segment], value: Value) -> Result<(), String> {
+    let mut current = root;
+    for (depth, segment) in path.iter().enumerate() {
+        if let (Value::Null, Segment::Key(_)) = (&*current, segment) {
+            *current = Value::Map(Default::default());
+        }
+        current = match (current, segment) {
+            (Value::Map(map), Segment::Key(key)) => map.entry(key.clone()).or_insert(Value::Null),
+            (Value::Seq(seq), &Segment::Index(index)) => {
+                if index == seq.len() {
+                    seq.push(Value::Null);
+                }
+                let len = seq.len();
+                seq.get_mut(index).ok_or_else(|| {
+                    format!(
+                        "index {} is out of range for {} with {} elements",
+                        index,
+                        describe(&path[..depth]),
+                        len
+                    )
+                })?
+            }
+            (other, Segment::Key(_)) => {
+                return Err(format!(
+                    "expected a map at {}, found {}",
+                    describe(&path[..depth]),
+                    other.type_name()
+                ))
+            }
+            (other, Segment::Index(_)) => {
+                return Err(format!(
+                    "expected a sequence at {}, found {}",
+                    describe(&path[..depth]),
+                    other.type_name()
+                ))
+            }
+        };
+    }
+
+    *current = value;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(k: &str) -> Segment {
+        Segment::Key(k.to_string())
+    }
+
+    #[test]
+    fn parse_paths() {
+        assert_eq!(parse_path(""), Ok(vec![]));
+        assert_eq!(parse_path("server.port"), Ok(vec![key("server"), key("port")]));
+        assert_eq!(
+            parse_path("servers[0].tags[12]"),
+            Ok(vec![key("servers"), Segment::Index(0), key("tags"), Segment::Index(12)])
+        );
+        assert_eq!(
+            parse_path(r#"hosts."example.com"."a\"b""#),
+            Ok(vec![key("hosts"), key("example.com"), key("a\"b")])
+        );
+        assert_eq!(parse_path("[1]"), Ok(vec![Segment::Index(1)]));
+
+        assert!(parse_path("server.").is_err());
+        assert!(parse_path("server..port").is_err());
+        assert!(parse_path("servers[x]").is_err());
+        assert!(parse_path("servers[0]port").is_err());
+        assert!(parse_path("\"open").is_err());
+    }
+
+    #[test]
+    fn parse_pointers() {
+        assert_eq!(parse_pointer(""), Ok(vec![]));
+        assert_eq!(
+            parse_pointer("/servers/0/host"),
+            Ok(vec![key("servers"), Segment::Index(0), key("host")])
+        );
+        assert_eq!(
+            parse_pointer("/a~1b/m~0n/01/"),
+            Ok(vec![key("a/b"), key("m~n"), key("01"), key("")])
+        );
+        assert_eq!(render_pointer(&parse_pointer("/a~1b/m~0n/3").unwrap()), "/a~1b/m~0n/3");
+
+        assert!(parse_pointer("servers").is_err());
+        assert!(parse_pointer("/a~2").is_err());
+    }
+
+    #[test]
+    fn take_values() {
+        let mut root = Value::Null;
+        set_value(&mut root, &parse_path("servers").unwrap(), Value::Seq(vec![])).unwrap();
+        set_value(
+            &mut root,
+            &parse_path("servers[0].host").unwrap(),
+            Value::String("a".to_string()),
+        )
+        .unwrap();
+        set_value(&mut root, &parse_path("ports.80").unwrap(), Value::Bool(true)).unwrap();
+
+        assert_eq!(
+            take_value(root.clone(), &parse_path("servers[0].host").unwrap()),
+            Ok(Value::String("a".to_string()))
+        );
+        assert_eq!(
+            take_value(root.clone(), &parse_pointer("/servers/0/host").unwrap()),
+            Ok(Value::String("a".to_string()))
+        );
+        assert_eq!(
+            take_value(root.clone(), &parse_pointer("/ports/80").unwrap()),
+            Ok(Value::Bool(true))
+        );
+        assert_eq!(
+            take_value(root.clone(), &parse_path("servers[1].host").unwrap()),
+            Err(1)
+        );
+        assert_eq!(take_value(root, &parse_path("servers[0].host.name").unwrap()), Err(3));
+    }
+
+    #[test]
+    fn set_values() {
+        let mut root = Value::Null;
+        set_value(&mut root, &parse_path("server.port").unwrap(), Value::Int(80)).unwrap();
+        set_value(&mut root, &parse_path("server.tags").unwrap(), Value::Seq(vec![])).unwrap();
+        set_value(&mut root, &parse_path("server.tags[0]").unwrap(), Value::Bool(true)).unwrap();
+        assert_eq!(root.get("server").and_then(|s| s.get("port")), Some(&Value::Int(80)));
+
+        let error = set_value(&mut root, &parse_path("server.tags[2]").unwrap(), Value::Null).unwrap_err();
+        assert_eq!(error, "index 2 is out of range for `server.tags` with 1 elements");
+        let error = set_value(&mut root, &parse_path("server.port.number").unwrap(), Value::Null).unwrap_err();
+        assert_eq!(error, "expected a map at `server.port`, found integer");
+    }
+}
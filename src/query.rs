@@ -0,0 +1,194 @@
+use serde::de::DeserializeOwned;
+
+use de::from_slice;
+use error::Error;
+use format::Format;
+use path::{parse_path, parse_pointer, render_path, render_pointer, take_value, Segment};
+use value::{from_value, from_value_coerced, Value};
+
+/// Deserialize a single value at `path` from a string in the specified format
+///
+/// The path can be written in the same syntax as paths in [`Error::Field`], such as `servers[0].host`,
+/// or as a JSON Pointer, such as `/servers/0/host`. An empty path refers to the whole document.
+/// Keys containing dots or brackets can be written in double quotes, such as `hosts."example.com"`.
+///
+/// The document is parsed into a [`Value`], and only the value at the path is deserialized into `T`,
+/// so the rest of the document does not need a matching type.
+/// In XML and URL-encoded data, where all values are strings, numbers and booleans are parsed from strings.
+///
+/// # Errors
+///
+/// If the path cannot be parsed, [`Error::InvalidPath`] is returned.
+/// If the source cannot be parsed, the same error as from [`from_str`] is returned.
+/// If there is no value at the path, [`Error::PathNotFound`] is returned with the longest prefix of the path
+/// that exists in the document.
+/// If the value cannot be deserialized into `T`, the error is wrapped in [`Error::Field`] with the full path.
+///
+/// # Example
+///
+/// ```
+/// use serde_any::Format;
+///
+/// # fn main() -> Result<(), serde_any::Error> {
+/// let source = "[[servers]]\nhost = \"example.com\"\nport = 8080\n";
+///
+/// let host: String = serde_any::get(source, Format::Toml, "servers[0].host")?;
+/// assert_eq!(host, "example.com");
+///
+/// let port: u16 = serde_any::get(source, Format::Toml, "/servers/0/port")?;
+/// assert_eq!(port, 8080);
+///
+/// let error = serde_any::get::<String>(source, Format::Toml, "servers[0].user").unwrap_err();
+/// assert_eq!(error.to_string(), r#"Path servers[0].user not found, the longest existing prefix is "servers[0]""#);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`Error::Field`]: ../error/enum.Error.html#variant.Field
+/// [`Error::InvalidPath`]: ../error/enum.Error.html#variant.InvalidPath
+/// [`Error::PathNotFound`]: ../error/enum.Error.html#variant.PathNotFound
+/// [`Value`]: ../value/enum.Value.html
+/// [`from_str`]: ../de/fn.from_str.html
+pub fn get<T>(s: &str, format: Format, path: &str) -> Result<T, Error>
+where
+    T: DeserializeOwned,
+{
+    get_slice(s.as_bytes(), format, path)
+}
+
+/// Deserialize a single value at `path` from a byte slice in the specified format
+///
+/// This works in the same way as [`get`].
+///
+/// # Errors
+///
+/// Errors are the same as for [`get`].
+///
+/// [`get`]: fn.get.html
+pub fn get_slice<T>(s: &[u8], format: Format, path: &str) -> Result<T, Error>
+where
+    T: DeserializeOwned,
+{
+    let is_pointer = path.is_empty() || path.starts_with('/');
+    let parsed = if is_pointer {
+        parse_pointer(path)
+    } else {
+        parse_path(path)
+    };
+    let segments = parsed.map_err(|reason| Error::InvalidPath {
+        path: path.to_string(),
+        reason,
+    })?;
+
+    let root: Value = from_slice(s, format)?;
+    let value = take_value(root, &segments).map_err(|depth| {
+        let render = if is_pointer { render_pointer } else { render_path };
+        Error::PathNotFound {
+            path: path.to_string(),
+            prefix: render(&segments[..depth]),
+        }
+    })?;
+
+    #[allow(unreachable_patterns)]
+    let result = match format {
+        Format::Xml | Format::Url => from_value_coerced(value),
+        _ => from_value(value),
+    };
+    result.map_err(|error| in_path(error, &segments))
+}
+
+/// Prepend the queried path to the path of an error from deserializing the value found there
+pub(crate) fn in_path(error: Error, segments: &[Segment]) -> Error {
+    if segments.is_empty() {
+        return error;
+    }
+
+    let prefix = render_path(segments);
+    match error {
+        Error::Field { path, error } => Error::Field {
+            path: if path.starts_with('[') {
+                format!("{}{}", prefix, path)
+            } else {
+                format!("{}.{}", prefix, path)
+            },
+            error,
+        },
+        error => Error::Field {
+            path: prefix,
+            error: Box::new(error),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Server {
+        host: String,
+        port: u16,
+    }
+
+    #[test]
+    fn same_value_in_all_formats() {
+        let sources = [
+            (
+                Format::Json,
+                r#"{"servers": [{"host": "a", "port": 80}, {"host": "b", "port": 81}]}"#,
+            ),
+            (
+                Format::Yaml,
+                "servers:\n  - host: a\n    port: 80\n  - host: b\n    port: 81\n",
+            ),
+            (
+                Format::Toml,
+                "[[servers]]\nhost = \"a\"\nport = 80\n\n[[servers]]\nhost = \"b\"\nport = 81\n",
+            ),
+            (
+                Format::Ron,
+                r#"(servers: [(host: "a", port: 80), (host: "b", port: 81)])"#,
+            ),
+        ];
+
+        for &(format, source) in &sources {
+            let server: Server = get(source, format, "servers[1]").unwrap();
+            assert_eq!(
+                server,
+                Server {
+                    host: "b".to_string(),
+                    port: 81
+                }
+            );
+            assert_eq!(get::<u16>(source, format, "/servers/0/port").unwrap(), 80);
+        }
+
+        assert_eq!(get::<u16>("host=a&port=80", Format::Url, "port").unwrap(), 80);
+    }
+
+    #[test]
+    fn toml_datetimes_are_strings() {
+        let created: String = get("created = 1979-05-27T07:32:00Z\n", Format::Toml, "created").unwrap();
+        assert_eq!(created, "1979-05-27T07:32:00Z");
+    }
+
+    #[test]
+    fn errors() {
+        let source = r#"{"servers": [{"host": "a", "port": "x"}], "a/b": {}}"#;
+
+        let error = get::<String>(source, Format::Json, "servers[3].host").unwrap_err();
+        assert_matches!(error, Error::PathNotFound { ref prefix, .. } if prefix == "servers");
+        let error = get::<String>(source, Format::Json, "/a~1b/c").unwrap_err();
+        assert_matches!(error, Error::PathNotFound { ref prefix, .. } if prefix == "/a~1b");
+        let error = get::<String>(source, Format::Json, "nothing").unwrap_err();
+        assert_matches!(error, Error::PathNotFound { ref prefix, .. } if prefix.is_empty());
+
+        let error = get::<Server>(source, Format::Json, "servers[0]").unwrap_err();
+        assert_matches!(error, Error::Field { ref path, .. } if path == "servers[0].port");
+        let error = get::<u16>(source, Format::Json, "/servers/0/host").unwrap_err();
+        assert_matches!(error, Error::Field { ref path, .. } if path == "servers[0].host");
+
+        let error = get::<String>(source, Format::Json, "servers[").unwrap_err();
+        assert_matches!(error, Error::InvalidPath { .. });
+    }
+}
@@ -0,0 +1,433 @@
+use std::env;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::de::DeserializeOwned;
+
+use compression::supported_compressions;
+use de::from_file;
+use error::Error;
+use format::supported_extensions;
+
+/// A function called with all conflicting files
+pub type ConflictCallback = Arc<dyn Fn(&[PathBuf]) + Send + Sync>;
+
+/// What to do when a directory contains several files with the same stem, such as `app.json` and `app.yaml`
+///
+/// Only files in the same directory conflict. Files in different directories are expected to override each other.
+#[derive(Clone, Default)]
+pub enum ConflictPolicy {
+    /// Use the file with the preferred extension and ignore the others
+    #[default]
+    Ignore,
+    /// Return [`Error::ConflictingFiles`] listing all of the files
+    ///
+    /// [`Error::ConflictingFiles`]: ../error/enum.Error.html#variant.ConflictingFiles
+    Error,
+    /// Use the file with the preferred extension, and call the function with all of the files
+    ///
+    /// The file that is used is the first one in the list.
+    Warn(ConflictCallback),
+}
+
+impl ConflictPolicy {
+    /// Create a policy that calls `f` with all conflicting files, and then uses the first one
+    pub fn warn<F>(f: F) -> ConflictPolicy
+    where
+        F: Fn(&[PathBuf]) + Send + Sync + 'static,
+    {
+        ConflictPolicy::Warn(Arc::new(f))
+    }
+}
+
+impl fmt::Debug for ConflictPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConflictPolicy::Ignore => f.write_str("Ignore"),
+            ConflictPolicy::Error => f.write_str("Error"),
+            ConflictPolicy::Warn(_) => f.write_str("Warn(..)"),
+        }
+    }
+}
+
+/// Search for a configuration file with a known stem in a list of directories
+///
+/// Every combination of directory and extension is a candidate, ordered first by directory
+/// and then by extension. Directories are listed in order of decreasing priority,
+/// and extensions default to [`supported_extensions`] but can be set to a preferred order.
+/// Files compressed with a supported compression, such as `settings.toml.gz`, are tried
+/// after the uncompressed files in the same directory.
+///
+/// Directories may refer to environment variables as `$NAME` or `${NAME}`, such as `$XDG_CONFIG_HOME/app`.
+/// A directory referring to a variable that is not set, or is empty, is skipped.
+/// Without any directories, the stem itself is used, relative to the current directory.
+///
+/// By default, if a directory contains files with several of the extensions, the preferred one is used.
+/// Set a [`ConflictPolicy`] to report such conflicts instead.
+///
+/// # Example
+///
+/// ```
+/// #[macro_use]
+/// extern crate serde;
+/// extern crate serde_any;
+///
+/// use serde_any::FileSearch;
+///
+/// #[derive(Deserialize, Debug)]
+/// struct Settings {
+///     name: String,
+/// }
+///
+/// fn main() {
+///     let search = FileSearch::new("settings")
+///         .dirs(&["./", "$XDG_CONFIG_HOME/app", "/etc/app"])
+///         .extensions(&["toml", "yaml"]);
+///
+///     match search.load::<Settings>() {
+///         Ok(settings) => println!("{:#?}", settings),
+///         Err(e) => println!("Error loading settings: {}", e),
+///     }
+/// }
+/// ```
+///
+/// [`supported_extensions`]: ../format/fn.supported_extensions.html
+/// [`ConflictPolicy`]: enum.ConflictPolicy.html
+#[derive(Clone, Debug)]
+pub struct FileSearch {
+    stem: PathBuf,
+    dirs: Vec<PathBuf>,
+    extensions: Vec<String>,
+    conflicts: ConflictPolicy,
+}
+
+impl FileSearch {
+    /// Create a search for files named `stem` with any supported extension
+    pub fn new<S: AsRef<Path>>(stem: S) -> FileSearch {
+        FileSearch {
+            stem: stem.as_ref().to_path_buf(),
+            dirs: Vec::new(),
+            extensions: supported_extensions().into_iter().map(String::from).collect(),
+            conflicts: ConflictPolicy::Ignore,
+        }
+    }
+
+    /// Add a directory to search, with a lower priority than the directories added before
+    pub fn dir<D: AsRef<Path>>(mut self, dir: D) -> FileSearch {
+        self.dirs.push(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Add several directories to search, in order of decreasing priority
+    pub fn dirs<I>(mut self, dirs: I) -> FileSearch
+    where
+        I: IntoIterator,
+        I::Item: AsRef<Path>,
+    {
+        self.dirs.extend(dirs.into_iter().map(|dir| dir.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Set the extensions to try in each directory, in order of preference
+    ///
+    /// Files with other extensions are ignored.
+    pub fn extensions<I>(mut self, extensions: I) -> FileSearch
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        self.extensions = extensions
+            .into_iter()
+            .map(|extension| extension.as_ref().trim_start_matches('.').to_string())
+            .collect();
+        self
+    }
+
+    /// Set what to do when a directory contains several matching files
+    ///
+    /// By default, conflicts are ignored and the file with the preferred extension is used.
+    pub fn conflicts(mut self, policy: ConflictPolicy) -> FileSearch {
+        self.conflicts = policy;
+        self
+    }
+
+    /// Return all candidate paths, in the order they are tried
+    pub fn candidates(&self) -> Vec<PathBuf> {
+        self.candidates_by_dir().into_iter().flatten().collect()
+    }
+
+    /// Return the candidate paths for each directory
+    fn candidates_by_dir(&self) -> Vec<Vec<PathBuf>> {
+        let dirs = if self.dirs.is_empty() {
+            vec![PathBuf::new()]
+        } else {
+            self.dirs.iter().filter_map(|dir| expand(dir)).collect()
+        };
+
+        dirs.iter()
+            .map(|dir| {
+                let base = dir.join(&self.stem);
+                let compressed = self.extensions.iter().flat_map(|extension| {
+                    supported_compressions()
+                        .into_iter()
+                        .map(move |compression| format!("{}.{}", extension, compression.extension()))
+                });
+                self.extensions
+                    .iter()
+                    .cloned()
+                    .chain(compressed)
+                    .map(|extension| base.with_extension(extension))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Return the existing files in one directory, after applying the conflict policy
+    fn existing(&self, candidates: &[PathBuf]) -> Result<Vec<PathBuf>, Error> {
+        let found: Vec<PathBuf> = candidates.iter().filter(|path| path.is_file()).cloned().collect();
+        if found.len() > 1 {
+            match self.conflicts {
+                ConflictPolicy::Ignore => {}
+                ConflictPolicy::Error => {
+                    return Err(Error::ConflictingFiles {
+                        stem: self.stem.clone(),
+                        found,
+                    })
+                }
+                ConflictPolicy::Warn(ref warn) => warn(&found),
+            }
+        }
+        Ok(found)
+    }
+
+    /// Return the first candidate that exists
+    ///
+    /// # Errors
+    ///
+    /// If no candidate exists, [`Error::NoFileFound`] is returned with all paths that were tried.
+    ///
+    /// If the conflict policy is [`ConflictPolicy::Error`] and the directory of the first candidate
+    /// contains other candidates, [`Error::ConflictingFiles`] is returned.
+    ///
+    /// [`Error::NoFileFound`]: ../error/enum.Error.html#variant.NoFileFound
+    /// [`Error::ConflictingFiles`]: ../error/enum.Error.html#variant.ConflictingFiles
+    /// [`ConflictPolicy::Error`]: enum.ConflictPolicy.html#variant.Error
+    pub fn find(&self) -> Result<PathBuf, Error> {
+        let candidates = self.candidates_by_dir();
+        for dir in &candidates {
+            if let Some(path) = self.existing(dir)?.into_iter().next() {
+                return Ok(path);
+            }
+        }
+
+        Err(Error::NoFileFound {
+            stem: self.stem.clone(),
+            tried: candidates.into_iter().flatten().collect(),
+        })
+    }
+
+    /// Return all candidates that exist, in order of decreasing priority
+    ///
+    /// The conflict policy is not applied.
+    pub fn find_all(&self) -> Vec<PathBuf> {
+        self.candidates().into_iter().filter(|path| path.is_file()).collect()
+    }
+
+    /// Return all candidates that exist, in order of decreasing priority, after applying the conflict policy
+    pub(crate) fn find_all_checked(&self) -> Result<Vec<PathBuf>, Error> {
+        let mut found = Vec::new();
+        for dir in self.candidates_by_dir() {
+            found.extend(self.existing(&dir)?);
+        }
+        Ok(found)
+    }
+
+    /// Deserialize the first candidate that exists
+    ///
+    /// # Errors
+    ///
+    /// Errors are the same as for [`find`]. If the file fails to deserialize,
+    /// the error from [`from_file`] is returned, wrapped in [`Error::File`] with the path of the file.
+    ///
+    /// [`find`]: struct.FileSearch.html#method.find
+    /// [`from_file`]: ../de/fn.from_file.html
+    /// [`Error::File`]: ../error/enum.Error.html#variant.File
+    pub fn load<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        from_file(self.find()?)
+    }
+
+    /// Deserialize every candidate that exists, in order of decreasing priority
+    ///
+    /// This is useful for layering, where values from files in more specific directories
+    /// override those from more general ones. No error is returned if no candidate exists.
+    ///
+    /// # Errors
+    ///
+    /// If the conflict policy is [`ConflictPolicy::Error`] and any directory contains several candidates,
+    /// [`Error::ConflictingFiles`] is returned. Otherwise, the first error from [`from_file`] is returned,
+    /// wrapped in [`Error::File`] with the path of the file.
+    ///
+    /// [`ConflictPolicy::Error`]: enum.ConflictPolicy.html#variant.Error
+    /// [`Error::ConflictingFiles`]: ../error/enum.Error.html#variant.ConflictingFiles
+    /// [`from_file`]: ../de/fn.from_file.html
+    /// [`Error::File`]: ../error/enum.Error.html#variant.File
+    pub fn load_all<T: DeserializeOwned>(&self) -> Result<Vec<(PathBuf, T)>, Error> {
+        self.find_all_checked()?
+            .into_iter()
+            .map(|path| from_file(&path).map(|value| (path, value)))
+            .collect()
+    }
+}
+
+/// Replace references to environment variables in `dir`, returning `None` if any of them is unset or empty
+fn expand(dir: &Path) -> Option<PathBuf> {
+    let s = match dir.to_str() {
+        Some(s) if s.contains('$') => s,
+        _ => return Some(dir.to_path_buf()),
+    };
+
+    let mut expanded = String::new();
+    let mut rest = s;
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+
+        let (name, after) = if rest.starts_with('{') {
+            let end = rest.find('}')?;
+            (&rest[1..end], &rest[end + 1..])
+        } else {
+            let end = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            (&rest[..end], &rest[end..])
+        };
+
+        match env::var(name) {
+            Ok(ref value) if !value.is_empty() => expanded.push_str(value),
+            _ => return None,
+        }
+        rest = after;
+    }
+    expanded.push_str(rest);
+
+    Some(PathBuf::from(expanded))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::fs;
+
+    use compression::guess_compression;
+
+    type Map = HashMap<String, String>;
+
+    #[test]
+    fn candidates_in_order() {
+        env::set_var("SERDE_ANY_SEARCH_HOME", "/home/bilbo");
+        let search = FileSearch::new("app")
+            .dirs(&[
+                "local",
+                "$SERDE_ANY_SEARCH_HOME/.config",
+                "${SERDE_ANY_SEARCH_UNSET}/app",
+            ])
+            .extensions(&[".yaml", "toml"]);
+
+        let candidates = search.candidates();
+        env::remove_var("SERDE_ANY_SEARCH_HOME");
+
+        let (compressed, candidates): (Vec<PathBuf>, Vec<PathBuf>) = candidates
+            .into_iter()
+            .partition(|path| guess_compression(path).is_some());
+        assert_eq!(compressed.len(), 4 * supported_compressions().len());
+
+        let expected: Vec<PathBuf> = vec![
+            "local/app.yaml".into(),
+            "local/app.toml".into(),
+            "/home/bilbo/.config/app.yaml".into(),
+            "/home/bilbo/.config/app.toml".into(),
+        ];
+        assert_eq!(candidates, expected);
+    }
+
+    #[test]
+    fn first_and_all_hits() {
+        let dir = env::temp_dir().join(format!("serde_any_search_{}", ::std::process::id()));
+        fs::create_dir_all(dir.join("user")).unwrap();
+        fs::create_dir_all(dir.join("system")).unwrap();
+        fs::write(dir.join("user/app.json"), r#"{"name": "user"}"#).unwrap();
+        fs::write(dir.join("system/app.toml"), "name = \"system\"\n").unwrap();
+        fs::write(dir.join("system/app.yaml"), "name: yaml\n").unwrap();
+
+        let search = FileSearch::new("app").dirs(vec![dir.join("missing"), dir.join("user"), dir.join("system")]);
+        let first = search.load::<Map>();
+        let all = search.extensions(&["yaml", "toml"]).load_all::<Map>();
+        let missing = FileSearch::new("missing")
+            .dir(&dir)
+            .extensions(&["toml", "json"])
+            .load::<Map>();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(first.unwrap()["name"], "user");
+
+        let all: Vec<(PathBuf, String)> = all
+            .unwrap()
+            .into_iter()
+            .map(|(path, m)| (path, m["name"].clone()))
+            .collect();
+        assert_eq!(
+            all,
+            vec![
+                (dir.join("system/app.yaml"), "yaml".to_string()),
+                (dir.join("system/app.toml"), "system".to_string()),
+            ]
+        );
+
+        match missing {
+            Err(Error::NoFileFound { stem, tried }) => {
+                assert_eq!(stem, PathBuf::from("missing"));
+                assert_eq!(tried[..2], [dir.join("missing.toml"), dir.join("missing.json")]);
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn conflicting_files() {
+        let dir = env::temp_dir().join(format!("serde_any_conflicts_{}", ::std::process::id()));
+        fs::create_dir_all(dir.join("etc")).unwrap();
+        fs::write(dir.join("settings.json"), r#"{"name": "json"}"#).unwrap();
+        fs::write(dir.join("settings.yaml"), "name: yaml\n").unwrap();
+        fs::write(dir.join("etc/settings.toml"), "name = \"etc\"\n").unwrap();
+
+        let search = FileSearch::new("settings")
+            .dirs(vec![&dir, &dir.join("etc")])
+            .extensions(&["json", "yaml", "toml"]);
+        let ignored = search.load::<Map>();
+        let rejected = search.clone().conflicts(ConflictPolicy::Error).load::<Map>();
+        let warnings = Arc::new(::std::sync::Mutex::new(Vec::new()));
+        let sink = warnings.clone();
+        let warned = search
+            .conflicts(ConflictPolicy::warn(move |found| {
+                sink.lock().unwrap().push(found.to_vec())
+            }))
+            .load_all::<Map>();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(ignored.unwrap()["name"], "json");
+        match rejected {
+            Err(Error::ConflictingFiles { stem, found }) => {
+                assert_eq!(stem, PathBuf::from("settings"));
+                assert_eq!(found, vec![dir.join("settings.json"), dir.join("settings.yaml")]);
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(warned.unwrap().len(), 3);
+        assert_eq!(
+            *warnings.lock().unwrap(),
+            vec![vec![dir.join("settings.json"), dir.join("settings.yaml")]]
+        );
+    }
+}
@@ -1,13 +1,21 @@
 use serde::ser::Serialize;
 
-use std::fs::File;
+use std::fs::{self, Metadata, OpenOptions};
 use std::ffi::OsStr;
-use std::io::Write;
-use std::path::Path;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use backend::*;
-use format::{guess_format, Format};
+#[cfg(feature = "json")]
+use canonical::write_jcs;
+use compression::compress_for_path;
+use content::Buffered;
+use format::{guess_format, inner_path, Format};
 use error::Error;
+#[cfg(feature = "json")]
+use value::to_value;
 
 /// Serialize to a `String`
 ///
@@ -76,6 +84,7 @@ where
 /// Not all serialization formats support pretty printing.
 /// In such cases, the output from this function will be identical to the output
 /// of [`to_string`].
+/// Use [`to_string_with`] to configure the output.
 ///
 /// # Errors
 ///
@@ -113,32 +122,13 @@ where
 ///
 /// [`Error`]: ../error/enum.Error.html
 /// [`to_string`]: fn.to_string.html
+/// [`to_string_with`]: fn.to_string_with.html
 ///
-#[allow(unused_mut)]
 pub fn to_string_pretty<T>(value: &T, format: Format) -> Result<String, Error>
 where
     T: Serialize,
 {
-    #[allow(unreachable_patterns)]
-    match format {
-        #[cfg(feature = "yaml")]
-        Format::Yaml => Ok(serde_yaml::to_string(value)?),
-        #[cfg(feature = "json")]
-        Format::Json => Ok(serde_json::to_string_pretty(value)?),
-        #[cfg(feature = "toml")]
-        Format::Toml => Ok(toml::to_string_pretty(value)?),
-        #[cfg(feature = "ron")]
-        Format::Ron => Ok(ron::ser::to_string_pretty(
-            value,
-            ron::ser::PrettyConfig::default(),
-        )?),
-        #[cfg(feature = "xml")]
-        Format::Xml => Ok(xml::to_string(value)?),
-        #[cfg(feature = "url")]
-        Format::Url => Ok(url::to_string(value)?),
-
-        _ => Err(Error::UnsupportedFormat(format)),
-    }
+    to_string_with(value, format, &SerializeOptions::default())
 }
 
 /// Serialize to a byte vector
@@ -209,6 +199,7 @@ where
 /// Not all serialization formats support pretty printing.
 /// In such cases, the output from this function will be identical to the output
 /// of [`to_vec`].
+/// Use [`to_vec_with`] to configure the output.
 ///
 /// # Errors
 ///
@@ -248,28 +239,13 @@ where
 ///
 /// [`Error`]: ../error/enum.Error.html
 /// [`to_vec`]: fn.to_vec.html
+/// [`to_vec_with`]: fn.to_vec_with.html
 ///
 pub fn to_vec_pretty<T>(value: &T, format: Format) -> Result<Vec<u8>, Error>
 where
     T: Serialize,
 {
-    #[allow(unreachable_patterns)]
-    match format {
-        #[cfg(feature = "yaml")]
-        Format::Yaml => Ok(serde_yaml::to_vec(value)?),
-        #[cfg(feature = "json")]
-        Format::Json => Ok(serde_json::to_vec_pretty(value)?),
-        #[cfg(feature = "toml")]
-        Format::Toml => Ok(toml::ser::to_string_pretty(value)?.into_bytes()),
-        #[cfg(feature = "ron")]
-        Format::Ron => Ok(ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default())?.into_bytes()),
-        #[cfg(feature = "xml")]
-        Format::Xml => Ok(xml::to_string(value)?.into_bytes()),
-        #[cfg(feature = "url")]
-        Format::Url => Ok(url::to_string(value)?.into_bytes()),
-
-        _ => Err(Error::UnsupportedFormat(format)),
-    }
+    to_vec_with(value, format, &SerializeOptions::default())
 }
 
 /// Serialize to a writer
@@ -327,7 +303,7 @@ where
         #[cfg(feature = "toml")]
         Format::Toml => {
             let s = toml::to_vec(value)?;
-            writer.write(&s)?;
+            writer.write_all(&s)?;
             Ok(())
         }
         #[cfg(feature = "ron")]
@@ -354,6 +330,7 @@ where
 /// Not all serialization formats support pretty printing.
 /// In such cases, the output from this function will be identical to the output
 /// of [`to_writer`].
+/// Use [`to_writer_with`] to configure the output.
 ///
 /// # Errors
 ///
@@ -393,55 +370,748 @@ where
 ///
 /// [`Error`]: ../error/enum.Error.html
 /// [`to_writer`]: fn.to_writer.html
+/// [`to_writer_with`]: fn.to_writer_with.html
 ///
-#[allow(unused_mut)]
-pub fn to_writer_pretty<W, T>(mut writer: W, value: &T, format: Format) -> Result<(), Error>
+pub fn to_writer_pretty<W, T>(writer: W, value: &T, format: Format) -> Result<(), Error>
+where
+    W: Write,
+    T: Serialize,
+{
+    to_writer_with(writer, value, format, &SerializeOptions::default())
+}
+
+/// Line endings used in serialized output
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineEnding {
+    /// A line feed (`\n`), as used on Unix
+    Lf,
+    /// A carriage return followed by a line feed (`\r\n`), as used on Windows
+    CrLf,
+}
+
+impl LineEnding {
+    fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+}
+
+/// Options for serialization
+///
+/// By default, the output is pretty-printed with the defaults of each format's backend,
+/// which is the same output as produced by [`to_string_pretty`].
+/// Options are mapped onto the backends where they are supported,
+/// and are ignored by the formats which have no use for them:
+///
+/// | Option              | JSON | YAML | TOML        | RON | XML | URL |
+/// |---------------------|------|------|-------------|-----|-----|-----|
+/// | `pretty`            | yes  |      | yes         | yes | yes |     |
+/// | `indent`            | yes  |      | arrays only | yes | yes |     |
+/// | `indent_char`       | yes  |      |             | yes | yes |     |
+/// | `line_ending`       | yes  | yes  | yes         | yes | yes |     |
+/// | `trailing_newline`  | yes  | yes  | yes         | yes | yes | yes |
+/// | `sort_keys`         | yes  | yes  | yes         | yes | yes | yes |
+/// | `compact_arrays`    | yes  |      | yes         |     |     |     |
+/// | `struct_names`      |      |      |             | yes |     |     |
+/// | `xml_declaration`   |      |      |             |     | yes |     |
+/// | `xml_root_name`     |      |      |             |     | yes |     |
+/// | `canonical`         | yes  | yes  | yes         | yes | yes | yes |
+///
+/// # Example
+///
+/// ```
+/// # use std::collections::BTreeMap;
+/// # fn main() -> Result<(), serde_any::Error> {
+/// use serde_any::{Format, LineEnding, SerializeOptions};
+///
+/// let mut ports = BTreeMap::new();
+/// ports.insert("http", vec![80, 8080]);
+///
+/// let options = SerializeOptions::new()
+///     .indent(4)
+///     .compact_arrays(true)
+///     .line_ending(LineEnding::CrLf)
+///     .trailing_newline(true);
+/// let data = serde_any::to_string_with(&ports, Format::Json, &options)?;
+/// assert_eq!(data, "{\r\n    \"http\": [80, 8080]\r\n}\r\n");
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`to_string_pretty`]: fn.to_string_pretty.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SerializeOptions {
+    pretty: bool,
+    indent: Option<usize>,
+    indent_char: Option<char>,
+    line_ending: Option<LineEnding>,
+    trailing_newline: Option<bool>,
+    sort_keys: bool,
+    compact_arrays: bool,
+    struct_names: bool,
+    xml_declaration: bool,
+    xml_root_name: Option<String>,
+    canonical: bool,
+}
+
+impl Default for SerializeOptions {
+    fn default() -> SerializeOptions {
+        SerializeOptions {
+            pretty: true,
+            indent: None,
+            indent_char: None,
+            line_ending: None,
+            trailing_newline: None,
+            sort_keys: false,
+            compact_arrays: false,
+            struct_names: false,
+            xml_declaration: false,
+            xml_root_name: None,
+            canonical: false,
+        }
+    }
+}
+
+impl SerializeOptions {
+    /// Create the default options
+    pub fn new() -> SerializeOptions {
+        SerializeOptions::default()
+    }
+
+    /// Pretty-print the output
+    ///
+    /// This is enabled by default. When disabled, the output is the same as produced by [`to_string`],
+    /// and the options controlling indentation have no effect.
+    ///
+    /// [`to_string`]: fn.to_string.html
+    pub fn pretty(mut self, pretty: bool) -> SerializeOptions {
+        self.pretty = pretty;
+        self
+    }
+
+    /// Indent each level by `width` indentation characters
+    ///
+    /// If not set, the default indentation of the format is used.
+    /// TOML only indents the elements of arrays split over multiple lines, and always uses spaces.
+    pub fn indent(mut self, width: usize) -> SerializeOptions {
+        self.indent = Some(width);
+        self
+    }
+
+    /// Indent using `c` instead of spaces, typically `'\t'`
+    pub fn indent_char(mut self, c: char) -> SerializeOptions {
+        self.indent_char = Some(c);
+        self
+    }
+
+    /// End lines with `line_ending`
+    ///
+    /// If not set, the line ending produced by the format's backend is kept.
+    pub fn line_ending(mut self, line_ending: LineEnding) -> SerializeOptions {
+        self.line_ending = Some(line_ending);
+        self
+    }
+
+    /// Make sure the output ends with a line ending, or that it does not end with one
+    ///
+    /// If not set, the output is left as produced by the format's backend.
+    pub fn trailing_newline(mut self, trailing_newline: bool) -> SerializeOptions {
+        self.trailing_newline = Some(trailing_newline);
+        self
+    }
+
+    /// Sort map keys and struct fields alphabetically
+    ///
+    /// Keys are sorted at every level of nesting, which makes the output independent
+    /// of the iteration order of maps such as `HashMap`.
+    pub fn sort_keys(mut self, sort_keys: bool) -> SerializeOptions {
+        self.sort_keys = sort_keys;
+        self
+    }
+
+    /// Write arrays on a single line, even when pretty-printing
+    pub fn compact_arrays(mut self, compact_arrays: bool) -> SerializeOptions {
+        self.compact_arrays = compact_arrays;
+        self
+    }
+
+    /// Write the names of structs in RON, as in `Person(name: "Bran")`
+    pub fn struct_names(mut self, struct_names: bool) -> SerializeOptions {
+        self.struct_names = struct_names;
+        self
+    }
+
+    /// Start XML output with an `<?xml version="1.0" encoding="UTF-8"?>` declaration
+    pub fn xml_declaration(mut self, xml_declaration: bool) -> SerializeOptions {
+        self.xml_declaration = xml_declaration;
+        self
+    }
+
+    /// Wrap XML output in a root element called `name`
+    ///
+    /// A struct is written as the fields inside the root element, replacing the element named after the struct.
+    /// Other values, such as maps, are written inside the root element as they are.
+    pub fn xml_root_name<S: Into<String>>(mut self, name: S) -> SerializeOptions {
+        self.xml_root_name = Some(name.into());
+        self
+    }
+
+    /// Produce canonical output, which is byte-identical for equal values
+    ///
+    /// Map keys and struct fields are sorted, negative zero is written as zero, and the output is not
+    /// pretty-printed. All other options are ignored, so that the output only depends on the value and the format.
+    /// JSON is written in the JSON Canonicalization Scheme (RFC 8785), where all numbers are formatted as doubles,
+    /// so integers beyond 2<sup>53</sup> are rounded, and floats which JSON cannot represent, such as `NaN`,
+    /// are rejected with [`Error::Json`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::collections::HashMap;
+    /// # fn main() -> Result<(), serde_any::Error> {
+    /// use serde_any::{Format, SerializeOptions};
+    ///
+    /// let mut data = HashMap::new();
+    /// data.insert("b", vec![1.5, -0.0, 1e21]);
+    /// data.insert("a", vec![100.0]);
+    ///
+    /// let options = SerializeOptions::new().canonical(true);
+    /// let json = serde_any::to_string_with(&data, Format::Json, &options)?;
+    /// assert_eq!(json, r#"{"a":[100],"b":[1.5,0,1e+21]}"#);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Error::Json`]: ../error/enum.Error.html#variant.Json
+    pub fn canonical(mut self, canonical: bool) -> SerializeOptions {
+        self.canonical = canonical;
+        self
+    }
+
+    /// The indentation string, if any of the indentation options were set
+    #[allow(dead_code)]
+    fn indent_string(&self, default_width: usize) -> Option<String> {
+        if self.indent.is_none() && self.indent_char.is_none() {
+            return None;
+        }
+
+        let c = self.indent_char.unwrap_or(' ');
+        Some((0..self.indent.unwrap_or(default_width)).map(|_| c).collect())
+    }
+
+    /// Apply the options which do not depend on the format
+    fn finish(&self, mut output: String) -> String {
+        if self.line_ending == Some(LineEnding::CrLf) {
+            output = output.replace("\r\n", "\n").replace('\n', "\r\n");
+        }
+
+        match self.trailing_newline {
+            Some(true) if !output.ends_with('\n') => {
+                output.push_str(self.line_ending.unwrap_or(LineEnding::Lf).as_str())
+            }
+            Some(false) => {
+                while output.ends_with('\n') {
+                    output.pop();
+                    if output.ends_with('\r') {
+                        output.pop();
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        output
+    }
+}
+
+/// Serialize to a `String` with the specified options
+///
+/// # Errors
+///
+/// If serialization fails, the format-specific [`Error`] variant is returned,
+/// with the underlying error as its cause.
+///
+/// # Example
+///
+/// ```
+/// #[macro_use]
+/// extern crate serde;
+/// extern crate serde_any;
+/// extern crate failure;
+///
+/// use serde_any::{Format, SerializeOptions};
+/// use failure::Error;
+///
+/// #[derive(Serialize, Debug)]
+/// struct Person {
+///     name: String,
+///     knowledge: u32,
+/// }
+///
+/// fn main() -> Result<(), Error> {
+///     let bran = Person {
+///         name: "Brandon Stark".to_string(),
+///         knowledge: 100,
+///     };
+///     let options = SerializeOptions::new().indent(2).struct_names(true);
+///     let data = serde_any::to_string_with(&bran, Format::Ron, &options)?;
+///     assert_eq!(&data[..], "Person(\n  name: \"Brandon Stark\",\n  knowledge: 100,\n)");
+///     Ok(())
+/// }
+/// ```
+///
+/// [`Error`]: ../error/enum.Error.html
+///
+pub fn to_string_with<T>(value: &T, format: Format, options: &SerializeOptions) -> Result<String, Error>
+where
+    T: Serialize,
+{
+    if options.canonical {
+        return canonical_to_string(value, format);
+    }
+
+    let output = if options.sort_keys {
+        serialize_with(&Buffered::new(value).sort_keys(), format, options)?
+    } else {
+        serialize_with(value, format, options)?
+    };
+
+    Ok(options.finish(output))
+}
+
+/// Serialize to a byte vector with the specified options
+///
+/// # Errors
+///
+/// If serialization fails, the format-specific [`Error`] variant is returned,
+/// with the underlying error as its cause.
+///
+/// [`Error`]: ../error/enum.Error.html
+///
+pub fn to_vec_with<T>(value: &T, format: Format, options: &SerializeOptions) -> Result<Vec<u8>, Error>
+where
+    T: Serialize,
+{
+    Ok(to_string_with(value, format, options)?.into_bytes())
+}
+
+/// Serialize to a writer with the specified options
+///
+/// # Errors
+///
+/// If serialization fails, the format-specific [`Error`] variant is returned,
+/// with the underlying error as its cause.
+///
+/// [`Error`]: ../error/enum.Error.html
+///
+pub fn to_writer_with<W, T>(mut writer: W, value: &T, format: Format, options: &SerializeOptions) -> Result<(), Error>
 where
     W: Write,
     T: Serialize,
+{
+    let data = to_vec_with(value, format, options)?;
+    writer.write_all(&data)?;
+    Ok(())
+}
+
+/// Compute the SHA-256 hash of the canonical serialization of a value
+///
+/// Equal values have the same hash, regardless of the iteration order of maps such as `HashMap`.
+/// The hash depends on the format, see [`SerializeOptions::canonical`] for details of the output.
+/// This requires the `sha2` feature, which is enabled by default.
+///
+/// # Errors
+///
+/// If serialization fails, the format-specific [`Error`] variant is returned,
+/// with the underlying error as its cause.
+///
+/// # Example
+///
+/// ```
+/// # use std::collections::HashMap;
+/// # fn main() -> Result<(), serde_any::Error> {
+/// use serde_any::Format;
+///
+/// let mut a = HashMap::new();
+/// let mut b = HashMap::new();
+/// for i in 0..100 {
+///     a.insert(i, i * 2);
+///     b.insert(99 - i, (99 - i) * 2);
+/// }
+///
+/// let hash = serde_any::canonical_hash(&a, Format::Json)?;
+/// assert_eq!(hash, serde_any::canonical_hash(&b, Format::Json)?);
+/// let hex: String = hash.iter().map(|byte| format!("{:02x}", byte)).collect();
+/// assert_eq!(hex.len(), 64);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`SerializeOptions::canonical`]: struct.SerializeOptions.html#method.canonical
+/// [`Error`]: ../error/enum.Error.html
+#[cfg(feature = "sha2")]
+pub fn canonical_hash<T>(value: &T, format: Format) -> Result<[u8; 32], Error>
+where
+    T: Serialize,
+{
+    use sha2::{Digest, Sha256};
+
+    let data = canonical_to_string(value, format)?;
+    Ok(Sha256::digest(data.as_bytes()).into())
+}
+
+fn canonical_to_string<T>(value: &T, format: Format) -> Result<String, Error>
+where
+    T: Serialize,
+{
+    #[allow(unreachable_patterns)]
+    match format {
+        #[cfg(feature = "json")]
+        Format::Json => {
+            let mut output = String::with_capacity(128);
+            write_jcs(&to_value(value)?, &mut output).map_err(<serde_json::Error as ::serde::ser::Error>::custom)?;
+            Ok(output)
+        }
+        _ => {
+            let options = SerializeOptions::new().pretty(false).sort_keys(true);
+            serialize_with(&Buffered::new(value).normalize_floats().sort_keys(), format, &options)
+        }
+    }
+}
+
+#[allow(unused_variables)]
+fn serialize_with<T>(value: &T, format: Format, options: &SerializeOptions) -> Result<String, Error>
+where
+    T: Serialize,
 {
     #[allow(unreachable_patterns)]
     match format {
         #[cfg(feature = "yaml")]
-        Format::Yaml => Ok(serde_yaml::to_writer(writer, value)?),
+        Format::Yaml => Ok(serde_yaml::to_string(value)?),
         #[cfg(feature = "json")]
-        Format::Json => Ok(serde_json::to_writer_pretty(writer, value)?),
+        Format::Json => json_to_string(value, options),
         #[cfg(feature = "toml")]
-        Format::Toml => {
-            let s = toml::to_string_pretty(value)?;
-            write!(&mut writer, "{}", s)?;
-            Ok(())
-        }
+        Format::Toml => toml_to_string(value, options),
         #[cfg(feature = "ron")]
-        Format::Ron => {
-            let s = ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default())?;
-            write!(&mut writer, "{}", s)?;
-            Ok(())
-        }
+        Format::Ron => ron_to_string(value, options),
         #[cfg(feature = "xml")]
-        Format::Xml => Ok(xml::to_writer(writer, value)?),
+        Format::Xml => xml_to_string(value, options),
         #[cfg(feature = "url")]
-        Format::Url => {
-            let s = url::to_string(value)?;
-            write!(&mut writer, "{}", s)?;
-            Ok(())
-        }
+        Format::Url => Ok(url::to_string(value)?),
 
         _ => Err(Error::UnsupportedFormat(format)),
     }
 }
 
+#[cfg(feature = "json")]
+fn json_to_string<T>(value: &T, options: &SerializeOptions) -> Result<String, Error>
+where
+    T: Serialize,
+{
+    if !options.pretty {
+        return Ok(serde_json::to_string(value)?);
+    }
+
+    let indent = options.indent_string(2).unwrap_or_else(|| "  ".to_string());
+    let formatter = JsonFormatter {
+        pretty: serde_json::ser::PrettyFormatter::with_indent(indent.as_bytes()),
+        compact_arrays: options.compact_arrays,
+        inline: 0,
+    };
+
+    let mut output = Vec::with_capacity(128);
+    value.serialize(&mut serde_json::Serializer::with_formatter(&mut output, formatter))?;
+    Ok(String::from_utf8(output).expect("serde_json produces valid UTF-8"))
+}
+
+/// Pretty JSON formatter that can keep arrays, and everything inside them, on a single line
+#[cfg(feature = "json")]
+struct JsonFormatter<'a> {
+    pretty: serde_json::ser::PrettyFormatter<'a>,
+    compact_arrays: bool,
+    // Nesting depth inside arrays written on a single line
+    inline: usize,
+}
+
+#[cfg(feature = "json")]
+impl<'a> serde_json::ser::Formatter for JsonFormatter<'a> {
+    fn begin_array<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        if self.compact_arrays {
+            self.inline += 1;
+            writer.write_all(b"[")
+        } else {
+            self.pretty.begin_array(writer)
+        }
+    }
+
+    fn end_array<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        if self.compact_arrays {
+            self.inline -= 1;
+            writer.write_all(b"]")
+        } else {
+            self.pretty.end_array(writer)
+        }
+    }
+
+    fn begin_array_value<W: ?Sized + Write>(&mut self, writer: &mut W, first: bool) -> io::Result<()> {
+        match (self.inline, first) {
+            (0, _) => self.pretty.begin_array_value(writer, first),
+            (_, true) => Ok(()),
+            (_, false) => writer.write_all(b", "),
+        }
+    }
+
+    fn end_array_value<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.pretty.end_array_value(writer)
+    }
+
+    fn begin_object<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        if self.inline > 0 {
+            self.inline += 1;
+            writer.write_all(b"{")
+        } else {
+            self.pretty.begin_object(writer)
+        }
+    }
+
+    fn end_object<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        if self.inline > 0 {
+            self.inline -= 1;
+            writer.write_all(b"}")
+        } else {
+            self.pretty.end_object(writer)
+        }
+    }
+
+    fn begin_object_key<W: ?Sized + Write>(&mut self, writer: &mut W, first: bool) -> io::Result<()> {
+        match (self.inline, first) {
+            (0, _) => self.pretty.begin_object_key(writer, first),
+            (_, true) => Ok(()),
+            (_, false) => writer.write_all(b", "),
+        }
+    }
+
+    fn begin_object_value<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.pretty.begin_object_value(writer)
+    }
+
+    fn end_object_value<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.pretty.end_object_value(writer)
+    }
+}
+
+#[cfg(feature = "toml")]
+fn toml_to_string<T>(value: &T, options: &SerializeOptions) -> Result<String, Error>
+where
+    T: Serialize,
+{
+    // Sorting can move tables before plain values, which TOML cannot represent.
+    // Converting to a `toml::Value` first writes the tables last.
+    if options.sort_keys {
+        let value = toml::Value::try_from(value)?;
+        return toml_write(&value, options);
+    }
+
+    toml_write(value, options)
+}
+
+#[cfg(feature = "toml")]
+fn toml_write<T>(value: &T, options: &SerializeOptions) -> Result<String, Error>
+where
+    T: Serialize,
+{
+    let mut output = String::with_capacity(128);
+    {
+        let mut serializer = if options.pretty {
+            toml::Serializer::pretty(&mut output)
+        } else {
+            toml::Serializer::new(&mut output)
+        };
+
+        if options.pretty && options.compact_arrays {
+            serializer.pretty_array(false);
+        } else if let (true, Some(width)) = (options.pretty, options.indent) {
+            serializer.pretty_array_indent(width);
+        }
+
+        value.serialize(&mut serializer)?;
+    }
+    Ok(output)
+}
+
+#[cfg(feature = "ron")]
+fn ron_to_string<T>(value: &T, options: &SerializeOptions) -> Result<String, Error>
+where
+    T: Serialize,
+{
+    let config = if options.pretty {
+        let mut config = ron::ser::PrettyConfig::default();
+        if let Some(indent) = options.indent_string(4) {
+            config.indentor = indent;
+        }
+        if let Some(line_ending) = options.line_ending {
+            config.new_line = line_ending.as_str().to_string();
+        }
+        Some(config)
+    } else {
+        None
+    };
+
+    let mut serializer = ron::ser::Serializer::new(config, options.struct_names);
+    value.serialize(&mut serializer)?;
+    Ok(serializer.into_output_string())
+}
+
+#[cfg(feature = "xml")]
+fn xml_to_string<T>(value: &T, options: &SerializeOptions) -> Result<String, Error>
+where
+    T: Serialize,
+{
+    let mut output = match options.xml_root_name {
+        Some(ref root) => format!(
+            "<{0}>{1}</{0}>",
+            root,
+            xml::to_string(&Buffered::new(value).root_as_map())?
+        ),
+        None => xml::to_string(value)?,
+    };
+
+    let line_ending = options.line_ending.unwrap_or(LineEnding::Lf).as_str();
+    if options.pretty {
+        let indent = options.indent_string(2).unwrap_or_else(|| "  ".to_string());
+        output = indent_xml(&output, &indent, line_ending);
+    }
+
+    if options.xml_declaration {
+        let separator = if options.pretty { line_ending } else { "" };
+        output.insert_str(0, &format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>{}", separator));
+    }
+
+    Ok(output)
+}
+
+/// Put each XML element on its own line, indented by its depth
+///
+/// Elements which only contain text are kept on a single line.
+#[cfg(feature = "xml")]
+fn indent_xml(xml: &str, indent: &str, line_ending: &str) -> String {
+    let mut tokens = Vec::new();
+    let mut rest = xml;
+    while !rest.is_empty() {
+        let end = if rest.starts_with('<') {
+            rest.find('>').map_or(rest.len(), |i| i + 1)
+        } else {
+            rest.find('<').unwrap_or(rest.len())
+        };
+        tokens.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+
+    let is_open = |token: &str| token.starts_with('<') && !token.starts_with("</") && !token.ends_with("/>");
+    let is_close = |token: &str| token.starts_with("</");
+
+    let mut output = String::with_capacity(xml.len() * 2);
+    let mut depth = 0;
+    let mut i = 0;
+    while i < tokens.len() {
+        let token = tokens[i];
+        let next = tokens.get(i + 1).cloned().unwrap_or("");
+        let after_next = tokens.get(i + 2).cloned().unwrap_or("");
+        let line_tokens = if is_open(token) && is_close(next) {
+            2
+        } else if is_open(token) && !next.is_empty() && !next.starts_with('<') && is_close(after_next) {
+            3
+        } else {
+            1
+        };
+
+        if line_tokens == 1 && is_close(token) {
+            depth = usize::max(depth, 1) - 1;
+        }
+        for _ in 0..depth {
+            output.push_str(indent);
+        }
+        for token in &tokens[i..i + line_tokens] {
+            output.push_str(token);
+        }
+        output.push_str(line_ending);
+        if line_tokens == 1 && is_open(token) {
+            depth += 1;
+        }
+
+        i += line_tokens;
+    }
+
+    output
+}
+
+/// Serialize to a file with the specified options
+///
+/// The format is inferred from the file name, and the data is formatted according to [`SerializeOptions`].
+/// The file is written as with [`to_file`], with [`WriteOptions`] controlling how it is written,
+/// for example to keep a backup or to flush the data to disk.
+///
+/// # Errors
+///
+/// Errors are the same as for [`to_file`].
+/// If keeping a backup fails, [`Error::Io`] is returned and the file is not touched.
+///
+/// # Example
+///
+/// ```
+/// # use std::collections::BTreeMap;
+/// use serde_any::{SerializeOptions, WriteOptions};
+///
+/// let mut limits = BTreeMap::new();
+/// limits.insert("connections", 100);
+///
+/// let options = SerializeOptions::new().trailing_newline(true);
+/// serde_any::to_file_with("limits.json", &limits, &options, &WriteOptions::new().fsync(true)).unwrap();
+/// # std::fs::remove_file("limits.json").unwrap();
+/// ```
+///
+/// [`SerializeOptions`]: struct.SerializeOptions.html
+/// [`WriteOptions`]: struct.WriteOptions.html
+/// [`to_file`]: fn.to_file.html
+/// [`Error::Io`]: ../error/enum.Error.html#variant.Io
+///
+pub fn to_file_with<T, P>(
+    path: P,
+    value: &T,
+    options: &SerializeOptions,
+    write_options: &WriteOptions,
+) -> Result<(), Error>
+where
+    T: Serialize,
+    P: AsRef<Path>,
+{
+    serialize_to_file(path.as_ref(), write_options, |format| to_vec_with(value, format, options))
+}
+
 /// Serialize to a file
 ///
+/// The value is serialized in memory first, then written to a temporary file next to `path`,
+/// which is atomically renamed to `path`. If the file already exists, its permissions are kept.
+/// Use [`to_file_with`] to keep a backup or to flush the data to disk.
+///
+/// If the file name ends with a compression suffix, such as `data.json.gz`,
+/// the format is inferred from the inner extension and the data is compressed.
+///
 /// # Errors
 ///
 /// If the serialization format cannot be inferred from the file name,
 /// [`Error::UnsupportedFileExtension`] is returned.
-///
-/// If opening the file for writing fails, [`Error::Io`] is returned.
+/// If the compression suffix is recognized but its feature is not enabled,
+/// [`Error::UnsupportedCompression`] is returned.
 ///
 /// If serialization fails, the format-specific [`Error`] variant is returned,
-/// with the underlying error as its cause.
+/// with the underlying error as its cause. In this case, the file is not touched.
+///
+/// If writing the file fails, [`Error::Io`] is returned.
+///
+/// All of these errors are wrapped in [`Error::File`] with the path of the file.
 ///
 /// # Example
 ///
@@ -475,26 +1145,17 @@ where
 ///
 /// [`Error`]: ../error/enum.Error.html
 /// [`Error::UnsupportedFileExtension`]: ../error/enum.Error.html#variant.UnsupportedFileExtension
+/// [`Error::UnsupportedCompression`]: ../error/enum.Error.html#variant.UnsupportedCompression
 /// [`Error::Io`]: ../error/enum.Error.html#variant.Io
+/// [`Error::File`]: ../error/enum.Error.html#variant.File
+/// [`to_file_with`]: fn.to_file_with.html
 ///
 pub fn to_file<T, P>(path: P, value: &T) -> Result<(), Error>
 where
     T: Serialize,
     P: AsRef<Path>,
 {
-    let format = guess_format(&path);
-
-    match format {
-        Some(format) => to_writer(File::create(path)?, value, format),
-        None => {
-            let ext = path.as_ref()
-                .extension()
-                .and_then(OsStr::to_str)
-                .map(String::from)
-                .unwrap_or(String::new());
-            Err(Error::UnsupportedFileExtension(ext))
-        }
-    }
+    serialize_to_file(path.as_ref(), &WriteOptions::default(), |format| to_vec(value, format))
 }
 
 /// Serialize to a file with pretty printing
@@ -503,15 +1164,19 @@ where
 /// In such cases, the output from this function will be identical to the output
 /// of [`to_file`].
 ///
+/// The file is written in the same way as with [`to_file`].
+///
 /// # Errors
 ///
 /// If the serialization format cannot be inferred from the file name,
 /// [`Error::UnsupportedFileExtension`] is returned.
 ///
-/// If opening the file for writing fails, [`Error::Io`] is returned.
-///
 /// If serialization fails, the format-specific [`Error`] variant is returned,
-/// with the underlying error as its cause.
+/// with the underlying error as its cause. In this case, the file is not touched.
+///
+/// If writing the file fails, [`Error::Io`] is returned.
+///
+/// All of these errors are wrapped in [`Error::File`] with the path of the file.
 ///
 /// # Example
 ///
@@ -546,6 +1211,7 @@ where
 /// [`Error`]: ../error/enum.Error.html
 /// [`Error::UnsupportedFileExtension`]: ../error/enum.Error.html#variant.UnsupportedFileExtension
 /// [`Error::Io`]: ../error/enum.Error.html#variant.Io
+/// [`Error::File`]: ../error/enum.Error.html#variant.File
 /// [`to_file`]: fn.to_file.html
 ///
 pub fn to_file_pretty<T, P>(path: P, value: &T) -> Result<(), Error>
@@ -553,24 +1219,214 @@ where
     T: Serialize,
     P: AsRef<Path>,
 {
-    let format = guess_format(&path);
+    serialize_to_file(path.as_ref(), &WriteOptions::default(), |format| to_vec_pretty(value, format))
+}
 
-    match format {
-        Some(format) => to_writer_pretty(File::create(path)?, value, format),
-        None => {
-            let ext = path.as_ref()
-                .extension()
-                .and_then(OsStr::to_str)
-                .map(String::from)
-                .unwrap_or(String::new());
-            Err(Error::UnsupportedFileExtension(ext))
+/// Options for writing serialized data to a file
+///
+/// Files are always written by serializing to memory, writing the data to a temporary file
+/// in the same directory, and atomically renaming it over the target file.
+/// If the target is a symbolic link, the file it points to is replaced and the link is kept.
+/// These options control the additional steps of the process.
+///
+/// # Example
+///
+/// ```
+/// # use std::collections::HashMap;
+/// # fn main() -> Result<(), serde_any::Error> {
+/// # let mut config = HashMap::new();
+/// # config.insert("port", 8080);
+/// use serde_any::{SerializeOptions, WriteOptions};
+///
+/// let options = WriteOptions::new().backup(true).fsync(true);
+/// serde_any::to_file_with("server.toml", &config, &SerializeOptions::new(), &options)?;
+/// # std::fs::remove_file("server.toml").unwrap();
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WriteOptions {
+    backup: bool,
+    fsync: bool,
+    preserve_permissions: bool,
+}
+
+impl Default for WriteOptions {
+    fn default() -> WriteOptions {
+        WriteOptions {
+            backup: false,
+            fsync: false,
+            preserve_permissions: true,
+        }
+    }
+}
+
+impl WriteOptions {
+    /// Create the default options
+    ///
+    /// By default, no backup is kept, data is not explicitly flushed to disk,
+    /// and the permissions of an existing file are preserved.
+    pub fn new() -> WriteOptions {
+        WriteOptions::default()
+    }
+
+    /// Keep a copy of the previous contents of the file
+    ///
+    /// The copy is written next to the file, with `.bak` appended to its name,
+    /// and replaces any existing backup.
+    /// If the file does not exist yet, no backup is made.
+    pub fn backup(mut self, backup: bool) -> WriteOptions {
+        self.backup = backup;
+        self
+    }
+
+    /// Flush the written data and the directory entry to disk before returning
+    ///
+    /// This makes sure the new contents survive a system crash,
+    /// at the cost of making the write considerably slower.
+    pub fn fsync(mut self, fsync: bool) -> WriteOptions {
+        self.fsync = fsync;
+        self
+    }
+
+    /// Give the new file the same permissions as the file it replaces
+    pub fn preserve_permissions(mut self, preserve: bool) -> WriteOptions {
+        self.preserve_permissions = preserve;
+        self
+    }
+}
+
+/// Serialize in the format inferred from `path` and write the file, attaching the path to any error
+fn serialize_to_file<F>(path: &Path, options: &WriteOptions, serialize: F) -> Result<(), Error>
+where
+    F: FnOnce(Format) -> Result<Vec<u8>, Error>,
+{
+    format_from_path(path)
+        .and_then(serialize)
+        .and_then(|data| write_file(path, &data, options))
+        .map_err(|error| error.in_file(path))
+}
+
+pub(crate) fn format_from_path(path: &Path) -> Result<Format, Error> {
+    guess_format(path).ok_or_else(|| {
+        let ext = inner_path(path)
+            .extension()
+            .and_then(OsStr::to_str)
+            .map(String::from)
+            .unwrap_or_default();
+        Error::UnsupportedFileExtension(ext)
+    })
+}
+
+/// Atomically replace the contents of the file at `path` with `data`, compressed if the name has a compression suffix
+pub(crate) fn write_file(path: &Path, data: &[u8], options: &WriteOptions) -> Result<(), Error> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let compressed = compress_for_path(path, data)?;
+    let data = compressed.as_ref().map_or(data, |compressed| &compressed[..]);
+
+    // Renaming over a symbolic link would replace the link instead of writing to its target
+    let path = &resolve_symlinks(path)?;
+    let file_name = match path.file_name() {
+        Some(file_name) => file_name.to_string_lossy().into_owned(),
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "path does not name a file").into()),
+    };
+    let existing = fs::metadata(path).ok().filter(|m| m.is_file());
+
+    if options.backup && existing.is_some() {
+        fs::copy(path, path.with_file_name(format!("{}.bak", file_name)))?;
+    }
+
+    let temp_path = path.with_file_name(format!(
+        ".{}.{}.{}.tmp",
+        file_name,
+        process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let result = write_temp_file(&temp_path, data, options, existing.as_ref())
+        .and_then(|_| fs::rename(&temp_path, path));
+    if result.is_err() {
+        fs::remove_file(&temp_path).ok();
+    }
+    result?;
+
+    if options.fsync {
+        sync_parent_dir(path)?;
+    }
+
+    Ok(())
+}
+
+/// Follow symbolic links until a path that is not a link, which may not exist yet
+fn resolve_symlinks(path: &Path) -> io::Result<PathBuf> {
+    let mut path = path.to_path_buf();
+    // The same limit as Linux, to stop at loops of links
+    for _ in 0..40 {
+        match fs::symlink_metadata(&path) {
+            Ok(ref metadata) if metadata.file_type().is_symlink() => {
+                let target = fs::read_link(&path)?;
+                path = match path.parent() {
+                    Some(parent) => parent.join(target),
+                    None => target,
+                };
+            }
+            _ => return Ok(path),
         }
     }
+    Err(io::Error::new(io::ErrorKind::InvalidInput, "too many levels of symbolic links"))
+}
+
+fn write_temp_file(path: &Path, data: &[u8], options: &WriteOptions, existing: Option<&Metadata>) -> io::Result<()> {
+    let permissions = existing.filter(|_| options.preserve_permissions).map(Metadata::permissions);
+    let mut open_options = OpenOptions::new();
+    open_options.write(true).create_new(true);
+    if let Some(ref permissions) = permissions {
+        set_create_mode(&mut open_options, permissions);
+    }
+
+    let mut file = open_options.open(path)?;
+    // The creation mode is reduced by the umask, so set the exact permissions before any data is written
+    if let Some(permissions) = permissions {
+        file.set_permissions(permissions)?;
+    }
+    file.write_all(data)?;
+    if options.fsync {
+        file.sync_all()?;
+    }
+
+    Ok(())
+}
+
+/// Create the file with its final mode, so that the new contents are never readable by more users than the old ones
+#[cfg(unix)]
+fn set_create_mode(open_options: &mut OpenOptions, permissions: &fs::Permissions) {
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+    open_options.mode(permissions.mode());
+}
+
+#[cfg(not(unix))]
+fn set_create_mode(_open_options: &mut OpenOptions, _permissions: &fs::Permissions) {}
+
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(parent) if parent != Path::new("") => fs::File::open(parent)?.sync_all(),
+        _ => fs::File::open(".")?.sync_all(),
+    }
+}
+
+// Directories cannot be opened as files on other platforms, the rename is durable once it returns
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &Path) -> io::Result<()> {
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::{BTreeMap, HashMap};
     use std::fs::remove_file;
 
     #[derive(Serialize, PartialEq, Debug)]
@@ -579,6 +1435,12 @@ mod tests {
         bar: Vec<f32>,
     }
 
+    #[derive(Serialize)]
+    struct Unordered {
+        table: Foo,
+        value: u32,
+    }
+
     #[test]
     fn unknown_extension_write() {
         let foo = Foo {
@@ -587,14 +1449,330 @@ mod tests {
         };
 
         let file_name = "ser_foo.dat";
-        assert_matches!(
-            to_file(file_name, &foo),
-            Err(Error::UnsupportedFileExtension(_))
+        for result in [to_file(file_name, &foo), to_file_pretty(file_name, &foo)] {
+            match result {
+                Err(Error::File { path, error }) => {
+                    assert_eq!(path, Path::new(file_name));
+                    assert_matches!(*error, Error::UnsupportedFileExtension(_));
+                }
+                other => panic!("unexpected result {:?}", other),
+            }
+        }
+        remove_file(file_name).ok();
+    }
+
+    fn read(path: &str) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn failed_serialization_keeps_file() {
+        let file_name = "ser_keep.toml";
+        fs::write(file_name, "size = 10\n").unwrap();
+
+        // TOML requires values to be emitted before tables
+        let unordered = Unordered {
+            table: Foo { size: 1, bar: vec![] },
+            value: 2,
+        };
+        let error = to_file(file_name, &unordered).unwrap_err();
+        assert_eq!(error.file(), Some(Path::new(file_name)));
+        assert_eq!(error.format(), Some(Format::Toml));
+        assert_matches!(to_file_pretty(file_name, &unordered), Err(Error::File { .. }));
+        assert_eq!(read(file_name), "size = 10\n");
+
+        let leftovers: Vec<_> = fs::read_dir(".")
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_name().to_string_lossy().starts_with(".ser_keep.toml."))
+            .collect();
+        assert!(leftovers.is_empty());
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn write_with_backup() {
+        let file_name = "ser_backup.json";
+        let backup_name = "ser_backup.json.bak";
+        let options = WriteOptions::new().backup(true).fsync(true);
+        let compact = SerializeOptions::new().pretty(false);
+
+        to_file_with(file_name, &vec![1, 2], &compact, &options).unwrap();
+        assert!(fs::metadata(backup_name).is_err());
+
+        to_file_with(file_name, &vec![3, 4], &compact, &options).unwrap();
+        assert_eq!(read(file_name), "[3,4]");
+        assert_eq!(read(backup_name), "[1,2]");
+
+        remove_file(file_name).unwrap();
+        remove_file(backup_name).unwrap();
+    }
+
+    #[derive(Serialize)]
+    struct Server {
+        name: String,
+        ports: Vec<u16>,
+        limits: BTreeMap<String, u32>,
+    }
+
+    fn server() -> Server {
+        let mut limits = BTreeMap::new();
+        limits.insert("connections".to_string(), 100);
+        Server {
+            name: "web".to_string(),
+            ports: vec![80, 443],
+            limits,
+        }
+    }
+
+    #[test]
+    fn default_options_match_pretty() {
+        for &format in &[Format::Json, Format::Toml, Format::Yaml, Format::Ron, Format::Url] {
+            let value = Foo {
+                size: 10,
+                bar: vec![1.0, 2.0],
+            };
+            let options = SerializeOptions::default();
+            assert_eq!(
+                to_string_with(&value, format, &options).unwrap_or_default(),
+                to_string_pretty(&value, format).unwrap_or_default(),
+                "{}",
+                format
+            );
+        }
+    }
+
+    #[test]
+    fn json_options() {
+        let options = SerializeOptions::new().indent(1).indent_char('\t').compact_arrays(true);
+        let data = to_string_with(&server(), Format::Json, &options).unwrap();
+        assert_eq!(
+            data,
+            "{\n\t\"name\": \"web\",\n\t\"ports\": [80, 443],\n\t\"limits\": {\n\t\t\"connections\": 100\n\t}\n}"
         );
-        assert_matches!(
-            to_file_pretty(file_name, &foo),
-            Err(Error::UnsupportedFileExtension(_))
+
+        let nested = vec![vec![1, 2], vec![]];
+        let data = to_string_with(&nested, Format::Json, &options).unwrap();
+        assert_eq!(data, "[[1, 2], []]");
+
+        let options = SerializeOptions::new().pretty(false).sort_keys(true).trailing_newline(true);
+        let data = to_string_with(&server(), Format::Json, &options).unwrap();
+        assert_eq!(data, "{\"limits\":{\"connections\":100},\"name\":\"web\",\"ports\":[80,443]}\n");
+    }
+
+    #[test]
+    fn toml_options() {
+        let options = SerializeOptions::new().indent(2);
+        let data = to_string_with(&server(), Format::Toml, &options).unwrap();
+        assert_eq!(data, "name = 'web'\nports = [\n  80,\n  443,\n]\n\n[limits]\nconnections = 100\n");
+
+        let options = SerializeOptions::new().compact_arrays(true).line_ending(LineEnding::CrLf);
+        let data = to_string_with(&server(), Format::Toml, &options).unwrap();
+        assert_eq!(data, "name = 'web'\r\nports = [80, 443]\r\n\r\n[limits]\r\nconnections = 100\r\n");
+    }
+
+    #[test]
+    fn toml_sorted_keys_keep_tables_last() {
+        let value = Unordered {
+            table: Foo { size: 1, bar: vec![] },
+            value: 2,
+        };
+        let options = SerializeOptions::new().sort_keys(true);
+
+        let data = to_string_with(&value, Format::Toml, &options).unwrap();
+        assert_eq!(data, "value = 2\n\n[table]\nbar = []\nsize = 1\n");
+    }
+
+    /// Serializes its pairs as a map, which allows keys such as floats that a `HashMap` cannot hold
+    struct Pairs<K>(Vec<(K, u32)>);
+
+    impl<K: Serialize> Serialize for Pairs<K> {
+        fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_map(self.0.iter().map(|(key, value)| (key, value)))
+        }
+    }
+
+    #[derive(Serialize)]
+    enum Level {
+        Low,
+        High,
+    }
+
+    #[test]
+    fn sorted_keys_compare_values() {
+        let options = SerializeOptions::new().pretty(false).sort_keys(true);
+
+        let floats = Pairs(vec![(10.0, 1), (9.0, 2), (-1.5, 3), (2.0, 4)]);
+        let data = to_string_with(&floats, Format::Ron, &options).unwrap();
+        assert_eq!(data, "{-1.5:3,2:4,9:2,10:1,}");
+
+        let levels = Pairs(vec![(Level::High, 1), (Level::Low, 2)]);
+        let data = to_string_with(&levels, Format::Ron, &options).unwrap();
+        assert_eq!(data, "{Low:2,High:1,}");
+    }
+
+    #[test]
+    fn yaml_options() {
+        let mut map = HashMap::new();
+        for key in &["c", "a", "b"] {
+            map.insert(key.to_string(), 1);
+        }
+
+        let options = SerializeOptions::new().sort_keys(true).trailing_newline(false);
+        let data = to_string_with(&map, Format::Yaml, &options).unwrap();
+        assert_eq!(data, "---\na: 1\nb: 1\nc: 1");
+    }
+
+    #[test]
+    fn ron_options() {
+        let options = SerializeOptions::new()
+            .indent(2)
+            .struct_names(true)
+            .sort_keys(true)
+            .line_ending(LineEnding::CrLf);
+        let data = to_string_with(&server(), Format::Ron, &options).unwrap();
+        assert_eq!(
+            data,
+            "Server(\r\n  limits: {\r\n    \"connections\": 100,\r\n  },\r\n  name: \"web\",\r\n  ports: [\r\n    80,\r\n    443,\r\n  ],\r\n)"
         );
-        remove_file(file_name).ok();
+
+        let options = SerializeOptions::new().pretty(false).struct_names(true);
+        let data = to_string_with(&Foo { size: 1, bar: vec![] }, Format::Ron, &options).unwrap();
+        assert_eq!(data, "Foo(size:1,bar:[],)");
+    }
+
+    #[test]
+    fn canonical_output() {
+        let mut first = HashMap::new();
+        let mut second = HashMap::new();
+        for i in 0..20 {
+            first.insert(format!("key{}", i), vec![f64::from(i) / 4.0, -0.0]);
+            second.insert(format!("key{}", 19 - i), vec![f64::from(19 - i) / 4.0, 0.0]);
+        }
+
+        let options = SerializeOptions::new().canonical(true).indent(8).line_ending(LineEnding::CrLf);
+        for &format in &[Format::Json, Format::Toml, Format::Yaml, Format::Ron] {
+            let data = to_string_with(&first, format, &options).unwrap();
+            assert_eq!(data, to_string_with(&second, format, &options).unwrap(), "{}", format);
+            assert!(!data.contains("-0") && !data.contains('\r'), "{}", format);
+        }
+        #[cfg(feature = "sha2")]
+        assert_eq!(
+            canonical_hash(&first, Format::Json).unwrap(),
+            canonical_hash(&second, Format::Json).unwrap()
+        );
+
+        let data = to_string_with(&server(), Format::Json, &options).unwrap();
+        assert_eq!(data, r#"{"limits":{"connections":100},"name":"web","ports":[80,443]}"#);
+        let data = to_string_with(&server(), Format::Toml, &options).unwrap();
+        assert_eq!(data, "name = \"web\"\nports = [80, 443]\n\n[limits]\nconnections = 100\n");
+
+        let data = to_string_with(&f64::NAN, Format::Json, &options);
+        assert_matches!(data, Err(Error::Json(_)));
+    }
+
+    #[test]
+    fn xml_options() {
+        let data = to_string_with(&Foo { size: 1, bar: vec![] }, Format::Xml, &SerializeOptions::new().pretty(false));
+        assert_matches!(data, Err(Error::Xml(_)));
+
+        let value = server().limits;
+        let options = SerializeOptions::new().xml_declaration(true).xml_root_name("config");
+        let data = to_string_with(&value, Format::Xml, &options).unwrap();
+        assert_eq!(
+            data,
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<config>\n  <connections>100</connections>\n</config>\n"
+        );
+
+    }
+
+    #[test]
+    fn xml_indentation() {
+        #[derive(Serialize)]
+        struct Inner {
+            a: u32,
+            b: String,
+        }
+
+        #[derive(Serialize)]
+        struct Outer {
+            inner: Inner,
+            empty: String,
+        }
+
+        let value = Outer {
+            inner: Inner {
+                a: 1,
+                b: "x".to_string(),
+            },
+            empty: String::new(),
+        };
+        let data = to_string_with(&value, Format::Xml, &SerializeOptions::new().indent(1).indent_char('\t')).unwrap();
+        assert_eq!(
+            data,
+            "<Outer>\n\t<inner>\n\t\t<Inner>\n\t\t\t<a>1</a>\n\t\t\t<b>x</b>\n\t\t</Inner>\n\t</inner>\n\t<empty></empty>\n</Outer>\n"
+        );
+
+        let options = SerializeOptions::new().pretty(false).xml_root_name("config");
+        let data = to_string_with(&value, Format::Xml, &options).unwrap();
+        assert_eq!(data, "<config><inner><Inner><a>1</a><b>x</b></Inner></inner><empty></empty></config>");
+    }
+
+    #[test]
+    fn write_with_options() {
+        let file_name = "ser_options.json";
+        let options = SerializeOptions::new().compact_arrays(true).trailing_newline(true);
+        to_file_with(file_name, &vec![1, 2], &options, &WriteOptions::default()).unwrap();
+        assert_eq!(read(file_name), "[1, 2]\n");
+
+        let backup_name = "ser_options.json.bak";
+        to_file_with(file_name, &vec![3], &options, &WriteOptions::new().backup(true)).unwrap();
+        assert_eq!(read(file_name), "[3]\n");
+        assert_eq!(read(backup_name), "[1, 2]\n");
+        remove_file(backup_name).unwrap();
+
+        let mut output = Vec::new();
+        to_writer_with(&mut output, &vec![1, 2], Format::Json, &options).unwrap();
+        assert_eq!(output, to_vec_with(&vec![1, 2], Format::Json, &options).unwrap());
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_preserves_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let file_name = "ser_permissions.yaml";
+        fs::write(file_name, "").unwrap();
+        fs::set_permissions(file_name, fs::Permissions::from_mode(0o600)).unwrap();
+
+        to_file(file_name, &vec![1, 2]).unwrap();
+        let mode = fs::metadata(file_name).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_through_symlink() {
+        use std::os::unix::fs::symlink;
+
+        let (target, link, dangling) = ("ser_symlink_target.json", "ser_symlink.json", "ser_symlink_dangling.json");
+        fs::write(target, "[]").unwrap();
+        symlink(target, link).unwrap();
+        symlink("ser_symlink_created.json", dangling).unwrap();
+
+        to_file(link, &vec![1, 2]).unwrap();
+        to_file(dangling, &vec![3]).unwrap();
+        assert!(fs::symlink_metadata(link).unwrap().file_type().is_symlink());
+        assert_eq!(read(target), "[1,2]");
+        assert_eq!(read("ser_symlink_created.json"), "[3]");
+
+        for file_name in &[target, link, dangling, "ser_symlink_created.json"] {
+            remove_file(file_name).unwrap();
+        }
     }
 }
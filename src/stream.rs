@@ -0,0 +1,646 @@
+use std::io::{self, BufRead, BufReader, Read};
+use std::marker::PhantomData;
+
+use serde::de::DeserializeOwned;
+
+use de::from_slice;
+use error::Error;
+use format::Format;
+use path::Segment;
+use query::in_path;
+
+/// Deserialize the elements of a sequence one at a time
+///
+/// The input has to be a single sequence: a JSON array, a RON list, or a YAML document whose root is
+/// a block sequence (`- item`) or a flow sequence (`[item, ...]`). The elements are split without
+/// parsing them, and each one is deserialized into `T` as soon as it has been read, so memory use
+/// is bounded by the size of the largest element rather than the whole input.
+///
+/// Other formats do not have a top-level sequence, so the stream only yields [`Error::UnsupportedFormat`].
+///
+/// # Errors
+///
+/// If an element fails to deserialize, its error is yielded wrapped in [`Error::Field`] with the index
+/// of the element, such as `[3].port`, and the stream continues with the next element.
+/// Locations in such errors are relative to the start of the element.
+/// YAML aliases can only refer to anchors within the same element.
+///
+/// If the input is not a sequence, or is malformed in a way that makes the end of an element unclear,
+/// [`Error::InvalidSequence`] is yielded. Reading errors are yielded as [`Error::Io`].
+/// The stream ends after either of them.
+///
+/// # Example
+///
+/// ```
+/// #[macro_use]
+/// extern crate serde;
+/// extern crate serde_any;
+///
+/// use serde_any::Format;
+///
+/// #[derive(Deserialize, Debug)]
+/// struct Event {
+///     id: u32,
+/// }
+///
+/// fn main() {
+///     let input = r#"[{"id": 1}, {"id": "two"}, {"id": 3}]"#;
+///     let events: Vec<_> = serde_any::stream_seq::<Event, _>(input.as_bytes(), Format::Json).collect();
+///
+///     assert_eq!(events[0].as_ref().unwrap().id, 1);
+///     assert_eq!(events[1].as_ref().unwrap_err().path(), Some("[1].id"));
+///     assert_eq!(events[2].as_ref().unwrap().id, 3);
+/// }
+/// ```
+///
+/// [`Error::UnsupportedFormat`]: ../error/enum.Error.html#variant.UnsupportedFormat
+/// [`Error::Field`]: ../error/enum.Error.html#variant.Field
+/// [`Error::InvalidSequence`]: ../error/enum.Error.html#variant.InvalidSequence
+/// [`Error::Io`]: ../error/enum.Error.html#variant.Io
+pub fn stream_seq<T, R>(reader: R, format: Format) -> SeqStream<T, R>
+where
+    T: DeserializeOwned,
+    R: Read,
+{
+    SeqStream {
+        input: Input {
+            reader: BufReader::new(reader),
+            pending: Vec::new(),
+            offset: 0,
+        },
+        format,
+        state: State::Start,
+        index: 0,
+        marker: PhantomData,
+    }
+}
+
+/// Iterator over the elements of a sequence, returned by [`stream_seq`]
+///
+/// [`stream_seq`]: fn.stream_seq.html
+#[derive(Debug)]
+pub struct SeqStream<T, R> {
+    input: Input<R>,
+    format: Format,
+    state: State,
+    index: usize,
+    marker: PhantomData<fn() -> T>,
+}
+
+#[derive(Debug)]
+#[cfg_attr(not(feature = "yaml"), allow(dead_code))]
+enum State {
+    Start,
+    Delimited {
+        syntax: Syntax,
+        opened: bool,
+        after_comma: bool,
+    },
+    Block {
+        indent: usize,
+        next: Option<Vec<u8>>,
+    },
+    Done,
+}
+
+/// The syntax of a sequence delimited by brackets and commas
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Syntax {
+    Json,
+    Ron,
+    YamlFlow,
+}
+
+impl<T, R> Iterator for SeqStream<T, R>
+where
+    T: DeserializeOwned,
+    R: Read,
+{
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Result<T, Error>> {
+        match self.next_element() {
+            Ok(Some(element)) => {
+                let index = self.index;
+                self.index += 1;
+                Some(from_slice(&element, self.format).map_err(|error| in_path(error, &[Segment::Index(index)])))
+            }
+            Ok(None) => {
+                self.state = State::Done;
+                None
+            }
+            Err(error) => {
+                self.state = State::Done;
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+impl<T, R: Read> SeqStream<T, R> {
+    /// Read the source of the next element
+    fn next_element(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        loop {
+            self.state = match self.state {
+                State::Start => self.start()?,
+                State::Delimited {
+                    syntax,
+                    ref mut opened,
+                    ref mut after_comma,
+                } => return delimited_element(&mut self.input, syntax, opened, after_comma),
+                State::Block { indent, ref mut next } => return block_element(&mut self.input, indent, next),
+                State::Done => return Ok(None),
+            }
+        }
+    }
+
+    /// Find out how the elements are delimited
+    #[allow(unreachable_patterns)]
+    fn start(&mut self) -> Result<State, Error> {
+        self.input.skip_bom()?;
+        match self.format {
+            #[cfg(feature = "json")]
+            Format::Json => Ok(State::delimited(Syntax::Json)),
+            #[cfg(feature = "ron")]
+            Format::Ron => Ok(State::delimited(Syntax::Ron)),
+            #[cfg(feature = "yaml")]
+            Format::Yaml => yaml_start(&mut self.input),
+            format => Err(Error::UnsupportedFormat(format)),
+        }
+    }
+}
+
+impl State {
+    #[cfg_attr(not(any(feature = "json", feature = "ron", feature = "yaml")), allow(dead_code))]
+    fn delimited(syntax: Syntax) -> State {
+        State::Delimited {
+            syntax,
+            opened: false,
+            after_comma: false,
+        }
+    }
+}
+
+/// Buffered input which keeps track of the offset, and allows putting back a line
+#[derive(Debug)]
+struct Input<R> {
+    reader: BufReader<R>,
+    pending: Vec<u8>,
+    offset: usize,
+}
+
+impl<R: Read> Input<R> {
+    fn peek(&mut self) -> io::Result<Option<u8>> {
+        match self.pending.first() {
+            Some(&b) => Ok(Some(b)),
+            None => Ok(self.reader.fill_buf()?.first().cloned()),
+        }
+    }
+
+    fn bump(&mut self) {
+        if self.pending.is_empty() {
+            self.reader.consume(1);
+        } else {
+            self.pending.remove(0);
+        }
+        self.offset += 1;
+    }
+
+    fn next_byte(&mut self) -> io::Result<Option<u8>> {
+        let b = self.peek()?;
+        if b.is_some() {
+            self.bump();
+        }
+        Ok(b)
+    }
+
+    /// Read a line including the newline, or `None` at the end of the input
+    fn line(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut line = ::std::mem::take(&mut self.pending);
+        if !line.ends_with(b"\n") {
+            self.reader.read_until(b'\n', &mut line)?;
+        }
+        self.offset += line.len();
+        Ok(if line.is_empty() { None } else { Some(line) })
+    }
+
+    /// Put back the end of a line, so that it is read again
+    #[cfg_attr(not(feature = "yaml"), allow(dead_code))]
+    fn put_back(&mut self, rest: &[u8]) {
+        self.offset -= rest.len();
+        self.pending = rest.to_vec();
+    }
+
+    fn skip_bom(&mut self) -> io::Result<()> {
+        if self.peek()? == Some(0xef) {
+            let mut bom = [0; 3];
+            self.reader.read_exact(&mut bom)?;
+            self.offset += 3;
+            if bom != [0xef, 0xbb, 0xbf] {
+                self.pending = bom.to_vec();
+                self.offset -= 3;
+            }
+        }
+        Ok(())
+    }
+
+    fn invalid<S: Into<String>>(&self, reason: S) -> Error {
+        Error::InvalidSequence {
+            offset: self.offset,
+            reason: reason.into(),
+        }
+    }
+}
+
+/// Read the next element of a sequence delimited by brackets and commas
+fn delimited_element<R: Read>(
+    input: &mut Input<R>,
+    syntax: Syntax,
+    opened: &mut bool,
+    after_comma: &mut bool,
+) -> Result<Option<Vec<u8>>, Error> {
+    if !*opened {
+        skip_insignificant(input, syntax)?;
+        match input.peek()? {
+            Some(b'[') => input.bump(),
+            _ => return Err(input.invalid("expected '['")),
+        }
+        *opened = true;
+    }
+
+    skip_insignificant(input, syntax)?;
+    match input.peek()? {
+        None => return Err(input.invalid("unexpected end of input, expected ']'")),
+        Some(b']') if *after_comma && syntax == Syntax::Json => return Err(input.invalid("trailing comma")),
+        Some(b']') => {
+            input.bump();
+            skip_insignificant(input, syntax)?;
+            return match input.peek()? {
+                None => Ok(None),
+                Some(_) => Err(input.invalid("trailing characters after the sequence")),
+            };
+        }
+        Some(b',') => return Err(input.invalid("expected an element")),
+        Some(_) => {}
+    }
+
+    let mut element = Vec::new();
+    let mut depth = 0usize;
+    loop {
+        let b = match input.peek()? {
+            Some(b) => b,
+            None => return Err(input.invalid("unexpected end of input inside an element")),
+        };
+        if depth == 0 && (b == b',' || b == b']') {
+            *after_comma = b == b',';
+            if *after_comma {
+                input.bump();
+            }
+            return Ok(Some(element));
+        }
+
+        input.bump();
+        let previous = element.last().cloned();
+        element.push(b);
+        match b {
+            b'[' | b'{' | b'(' => depth += 1,
+            b'}' | b')' if depth == 0 => return Err(input.invalid(format!("unexpected '{}'", b as char))),
+            b']' | b'}' | b')' => depth -= 1,
+            b'"' => {
+                let hashes = if syntax == Syntax::Ron {
+                    raw_string_hashes(&element)
+                } else {
+                    None
+                };
+                match hashes {
+                    Some(hashes) => copy_raw_string(input, hashes, &mut element)?,
+                    None => copy_quoted(input, b'"', true, &mut element)?,
+                }
+            }
+            b'\'' if syntax == Syntax::Ron => copy_quoted(input, b'\'', true, &mut element)?,
+            b'\'' if syntax == Syntax::YamlFlow => copy_quoted(input, b'\'', false, &mut element)?,
+            b'/' if syntax == Syntax::Ron => copy_comment(input, &mut element)?,
+            b'#' if syntax == Syntax::YamlFlow && previous.is_none_or(|p| p.is_ascii_whitespace()) => {
+                copy_line(input, &mut element)?
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Skip whitespace and comments between elements
+fn skip_insignificant<R: Read>(input: &mut Input<R>, syntax: Syntax) -> Result<(), Error> {
+    loop {
+        match input.peek()? {
+            Some(b) if b.is_ascii_whitespace() => input.bump(),
+            Some(b'/') if syntax == Syntax::Ron => {
+                input.bump();
+                copy_comment(input, &mut Vec::new())?;
+            }
+            Some(b'#') if syntax == Syntax::YamlFlow => copy_line(input, &mut Vec::new())?,
+            _ => return Ok(()),
+        }
+    }
+}
+
+/// Copy a quoted string or character after its opening quote
+fn copy_quoted<R: Read>(input: &mut Input<R>, quote: u8, escapes: bool, element: &mut Vec<u8>) -> Result<(), Error> {
+    loop {
+        match input.next_byte()? {
+            Some(b) if b == quote => {
+                element.push(b);
+                return Ok(());
+            }
+            Some(b'\\') if escapes => {
+                element.push(b'\\');
+                match input.next_byte()? {
+                    Some(escaped) => element.push(escaped),
+                    None => break,
+                }
+            }
+            Some(b) => element.push(b),
+            None => break,
+        }
+    }
+    Err(input.invalid("unexpected end of input inside a string"))
+}
+
+/// Return the number of hashes if the quote just copied starts a RON raw string, such as `r#"`
+fn raw_string_hashes(element: &[u8]) -> Option<usize> {
+    let before_quote = &element[..element.len() - 1];
+    let hashes = before_quote.iter().rev().take_while(|&&b| b == b'#').count();
+    let prefix = &before_quote[..before_quote.len() - hashes];
+    match prefix.split_last() {
+        Some((b'r', rest)) if rest.last().is_none_or(|&b| !(b.is_ascii_alphanumeric() || b == b'_')) => Some(hashes),
+        _ => None,
+    }
+}
+
+/// Copy a RON raw string after its opening quote
+fn copy_raw_string<R: Read>(input: &mut Input<R>, hashes: usize, element: &mut Vec<u8>) -> Result<(), Error> {
+    let mut closing = None;
+    while let Some(b) = input.next_byte()? {
+        element.push(b);
+        closing = match (b, closing) {
+            (b'"', _) => Some(0),
+            (b'#', Some(n)) => Some(n + 1),
+            _ => None,
+        };
+        if closing == Some(hashes) {
+            return Ok(());
+        }
+    }
+    Err(input.invalid("unexpected end of input inside a string"))
+}
+
+/// Copy a RON comment after its first slash
+fn copy_comment<R: Read>(input: &mut Input<R>, element: &mut Vec<u8>) -> Result<(), Error> {
+    match input.next_byte()? {
+        Some(b'/') => {
+            element.push(b'/');
+            copy_line(input, element)
+        }
+        Some(b'*') => {
+            element.push(b'*');
+            let mut star = false;
+            while let Some(b) = input.next_byte()? {
+                element.push(b);
+                if star && b == b'/' {
+                    return Ok(());
+                }
+                star = b == b'*';
+            }
+            Err(input.invalid("unexpected end of input inside a comment"))
+        }
+        _ => Err(input.invalid("unexpected '/'")),
+    }
+}
+
+/// Copy the rest of the line, including the newline
+fn copy_line<R: Read>(input: &mut Input<R>, element: &mut Vec<u8>) -> Result<(), Error> {
+    while let Some(b) = input.next_byte()? {
+        element.push(b);
+        if b == b'\n' {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Skip the YAML directives, comments and document marker before the root sequence
+#[cfg(feature = "yaml")]
+fn yaml_start<R: Read>(input: &mut Input<R>) -> Result<State, Error> {
+    while let Some(line) = input.line()? {
+        let content = yaml_content(&line);
+        if content.is_empty() {
+            continue;
+        }
+
+        if content.starts_with(b"[") {
+            input.put_back(&line[line.len() - content.len()..]);
+            return Ok(State::delimited(Syntax::YamlFlow));
+        }
+        if is_yaml_item(content) {
+            let indent = line.iter().take_while(|&&b| b == b' ').count();
+            return Ok(State::Block {
+                indent,
+                next: Some(line),
+            });
+        }
+        return Err(input.invalid("expected a YAML sequence"));
+    }
+    Ok(State::Done)
+}
+
+/// Return the content of a line outside of a sequence, without directives, comments and document markers
+fn yaml_content(line: &[u8]) -> &[u8] {
+    let mut content = line;
+    if content.starts_with(b"%") {
+        return &[];
+    }
+    if content.starts_with(b"---") && content.get(3).is_none_or(|b| b.is_ascii_whitespace()) {
+        content = &content[3..];
+    }
+    let content = trim_start(content);
+    if content.starts_with(b"#") {
+        &[]
+    } else {
+        content
+    }
+}
+
+/// Read the next item of a YAML block sequence
+///
+/// The dash of the item is replaced with a space, which leaves the item as an indented document of its own
+/// with the same columns as in the input.
+fn block_element<R: Read>(
+    input: &mut Input<R>,
+    indent: usize,
+    next: &mut Option<Vec<u8>>,
+) -> Result<Option<Vec<u8>>, Error> {
+    let mut element = match next.take() {
+        Some(line) => line,
+        None => return Ok(None),
+    };
+    element[indent] = b' ';
+
+    while let Some(line) = input.line()? {
+        if is_document_end(&line) {
+            while let Some(line) = input.line()? {
+                if !yaml_content(&line).is_empty() && !is_document_end(&line) {
+                    return Err(input.invalid("only a single YAML document can be streamed"));
+                }
+            }
+            break;
+        }
+        let at_indent = line.len() > indent && line[..indent].iter().all(|&b| b == b' ');
+        if at_indent && is_yaml_item(&line[indent..]) {
+            *next = Some(line);
+            break;
+        }
+        element.extend(line);
+    }
+    Ok(Some(element))
+}
+
+fn is_yaml_item(content: &[u8]) -> bool {
+    content.starts_with(b"-") && content.get(1).is_none_or(|b| b.is_ascii_whitespace())
+}
+
+fn is_document_end(line: &[u8]) -> bool {
+    (line.starts_with(b"---") || line.starts_with(b"...")) && line.get(3).is_none_or(|b| b.is_ascii_whitespace())
+}
+
+fn trim_start(s: &[u8]) -> &[u8] {
+    let start = s.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(s.len());
+    &s[start..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Item {
+        name: String,
+        tags: Vec<String>,
+    }
+
+    fn item(name: &str, tags: &[&str]) -> Item {
+        Item {
+            name: name.to_string(),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+        }
+    }
+
+    fn collect(input: &str, format: Format) -> Vec<Result<Item, Error>> {
+        stream_seq(input.as_bytes(), format).collect()
+    }
+
+    #[test]
+    fn elements_in_all_formats() {
+        let expected = vec![item("a, [b]", &["x"]), item("c", &[]), item("d\"]", &["y", "z"])];
+        let inputs = [
+            (
+                Format::Json,
+                r#" [ {"name": "a, [b]", "tags": ["x"]}, {"name": "c", "tags": []},
+                     {"name": "d\"]", "tags": ["y", "z"]} ] "#,
+            ),
+            (
+                Format::Ron,
+                r##"// items
+                [
+                    (name: "a, [b]", tags: ["x"]),
+                    /* ] */ (name: r#"c"#, tags: []),
+                    (name: r#"d"]"#, tags: ["y", "z"]), // trailing comma
+                ]"##,
+            ),
+            (
+                Format::Yaml,
+                "%YAML 1.2\n---\n# items\n- name: a, [b]\n  tags: [x]\n- name: c\n  tags: []\n\n- name: d\"]\n  tags:\n  - y\n  - z\n...\n",
+            ),
+            (
+                Format::Yaml,
+                "[{name: 'a, [b]', tags: [x]}, {name: c, tags: []}, # comment\n {name: 'd\"]', tags: [y, z]}]\n",
+            ),
+        ];
+
+        for &(format, input) in &inputs {
+            let items: Vec<Item> = collect(input, format).into_iter().map(Result::unwrap).collect();
+            assert_eq!(items, expected, "{:?}", input);
+        }
+
+        let numbers: Vec<u32> = stream_seq(&b"- 1\n- 2\n-   3"[..], Format::Yaml)
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(numbers, vec![1, 2, 3]);
+        let nested: Vec<Vec<u32>> = stream_seq(&b"- - 1\n  - 2\n-\n  - 3\n"[..], Format::Yaml)
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(nested, vec![vec![1, 2], vec![3]]);
+        let chars: Vec<char> = stream_seq(&br"['a', ']', '\'']"[..], Format::Ron)
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(chars, vec!['a', ']', '\'']);
+        assert_eq!(stream_seq::<u32, _>(&b"[]"[..], Format::Json).count(), 0);
+        assert_eq!(stream_seq::<u32, _>(&b""[..], Format::Yaml).count(), 0);
+    }
+
+    #[test]
+    fn element_errors() {
+        let items = collect(
+            r#"[{"name": "a", "tags": []}, {"name": 1, "tags": []}, {"name": "c", "tags": []}]"#,
+            Format::Json,
+        );
+        assert_eq!(items.len(), 3);
+        assert!(items[0].is_ok() && items[2].is_ok());
+        assert_eq!(items[1].as_ref().unwrap_err().path(), Some("[1].name"));
+
+        let items = collect(
+            "- name: a\n  tags: []\n- name: b\n- name: c\n  tags: []\n",
+            Format::Yaml,
+        );
+        assert_eq!(items.len(), 3);
+        assert_matches!(items[1], Err(Error::Field { ref path, .. }) if path == "[1]");
+    }
+
+    #[test]
+    fn invalid_sequences() {
+        let last_error = |input: &str, format: Format| -> Error {
+            let mut items: Vec<Result<::value::Value, Error>> = stream_seq(input.as_bytes(), format).collect();
+            assert!(items.iter().rev().skip(1).all(Result::is_ok));
+            items.pop().unwrap().unwrap_err()
+        };
+
+        assert_matches!(
+            last_error(r#"{"a": 1}"#, Format::Json),
+            Error::InvalidSequence { offset: 0, .. }
+        );
+        assert_matches!(
+            last_error(r#"[{"name": "a", "tags": []}"#, Format::Json),
+            Error::InvalidSequence { offset: 26, .. }
+        );
+        assert_matches!(
+            last_error("[1,]", Format::Json),
+            Error::InvalidSequence { offset: 3, .. }
+        );
+        assert_matches!(last_error("[\"open]", Format::Json), Error::InvalidSequence { .. });
+        assert_matches!(
+            last_error("[] []", Format::Json),
+            Error::InvalidSequence { offset: 3, .. }
+        );
+        assert_matches!(last_error("a: 1\n", Format::Yaml), Error::InvalidSequence { .. });
+        assert_matches!(
+            last_error("- name: a\n  tags: []\n---\n- b\n", Format::Yaml),
+            Error::InvalidSequence { .. }
+        );
+        assert_matches!(
+            last_error("a = 1", Format::Toml),
+            Error::UnsupportedFormat(Format::Toml)
+        );
+
+        let map: BTreeMap<String, u32> = stream_seq(&b"[{\"a\": 1}]"[..], Format::Json).next().unwrap().unwrap();
+        assert_eq!(map["a"], 1);
+    }
+}
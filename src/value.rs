@@ -0,0 +1,1198 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryInto;
+use std::error;
+use std::fmt;
+use std::rc::Rc;
+
+use serde::de::value::{MapDeserializer, SeqDeserializer};
+use serde::de::{
+    self, Deserialize, DeserializeSeed, Deserializer, EnumAccess, IntoDeserializer, MapAccess, SeqAccess, Unexpected,
+    VariantAccess, Visitor,
+};
+use serde::forward_to_deserialize_any;
+use serde::ser::{
+    self as serde_ser, Serialize, SerializeMap, SerializeSeq, SerializeStruct, SerializeStructVariant,
+    SerializeTuple, SerializeTupleStruct, SerializeTupleVariant, Serializer,
+};
+
+use de::deserialize_tracked;
+use error::Error;
+
+/// A map with string keys, sorted by key
+pub type Map = BTreeMap<String, Value>;
+
+/// Data in any of the supported formats, independent of the format it came from
+///
+/// A `Value` can be deserialized from every format, and can itself be deserialized into any type
+/// with [`from_value`]. This allows combining and inspecting data from several sources before
+/// converting it into the target type.
+///
+/// Map keys are always strings. Keys of other types, such as integer keys in YAML,
+/// are converted to strings, and parsed back if the target type expects a number.
+///
+/// # Example
+///
+/// ```
+/// # use serde_any::{Format, Value};
+/// let value: Value = serde_any::from_str("port = 8080", Format::Toml).unwrap();
+/// let port: Value = value.get("port").cloned().unwrap();
+/// assert_eq!(port, Value::Int(8080));
+/// ```
+///
+/// [`from_value`]: fn.from_value.html
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// A missing value, such as `null` in JSON, `~` in YAML or `None` in RON
+    Null,
+    /// A boolean
+    Bool(bool),
+    /// A signed integer, or an unsigned integer which fits into `i64`
+    Int(i64),
+    /// An unsigned integer larger than `i64::MAX`
+    UInt(u64),
+    /// A floating point number
+    Float(f64),
+    /// A string
+    String(String),
+    /// A sequence of values
+    Seq(Vec<Value>),
+    /// A map of string keys to values
+    Map(Map),
+}
+
+impl Value {
+    /// Return the value with the given key, if this is a map containing it
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Map(map) => map.get(key),
+            _ => None,
+        }
+    }
+
+    /// Return a short description of the type of this value, for use in error messages
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Null => "null",
+            Value::Bool(_) => "boolean",
+            Value::Int(_) | Value::UInt(_) => "integer",
+            Value::Float(_) => "float",
+            Value::String(_) => "string",
+            Value::Seq(_) => "sequence",
+            Value::Map(_) => "map",
+        }
+    }
+
+    fn unexpected(&self) -> Unexpected<'_> {
+        match self {
+            Value::Null => Unexpected::Unit,
+            Value::Bool(b) => Unexpected::Bool(*b),
+            Value::Int(i) => Unexpected::Signed(*i),
+            Value::UInt(u) => Unexpected::Unsigned(*u),
+            Value::Float(f) => Unexpected::Float(*f),
+            Value::String(s) => Unexpected::Str(s),
+            Value::Seq(_) => Unexpected::Seq,
+            Value::Map(_) => Unexpected::Map,
+        }
+    }
+}
+
+/// Renders the value on a single line, in a syntax similar to JSON
+///
+/// Strings and map keys are quoted and escaped like Rust string literals.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Null => f.write_str("null"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Int(i) => write!(f, "{}", i),
+            Value::UInt(u) => write!(f, "{}", u),
+            Value::Float(x) => write!(f, "{:?}", x),
+            Value::String(s) => write!(f, "{:?}", s),
+            Value::Seq(elements) => {
+                f.write_str("[")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                f.write_str("]")
+            }
+            Value::Map(map) => {
+                f.write_str("{")?;
+                for (i, (key, value)) in map.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{:?}: {}", key, value)?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+/// Error deserializing from or serializing into a [`Value`]
+///
+/// [`Value`]: enum.Value.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ValueError {
+    message: String,
+}
+
+impl fmt::Display for ValueError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl error::Error for ValueError {}
+
+impl de::Error for ValueError {
+    fn custom<T: fmt::Display>(msg: T) -> ValueError {
+        ValueError {
+            message: msg.to_string(),
+        }
+    }
+}
+
+impl serde_ser::Error for ValueError {
+    fn custom<T: fmt::Display>(msg: T) -> ValueError {
+        ValueError {
+            message: msg.to_string(),
+        }
+    }
+}
+
+/// Deserialize a value of type `T` from a [`Value`]
+///
+/// # Errors
+///
+/// If the value does not match `T`, [`Error::Value`] is returned.
+/// If the mismatch is inside a nested value, that error is wrapped in
+/// [`Error::Field`] together with the path to the value.
+///
+/// [`Value`]: enum.Value.html
+/// [`Error::Value`]: ../error/enum.Error.html#variant.Value
+/// [`Error::Field`]: ../error/enum.Error.html#variant.Field
+pub fn from_value<T>(value: Value) -> Result<T, Error>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    deserialize_tracked(value)
+}
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            // Unlike unit, `None` is accepted by TOML inside of tables
+            Value::Null => serializer.serialize_none(),
+            Value::Bool(b) => serializer.serialize_bool(*b),
+            Value::Int(i) => serializer.serialize_i64(*i),
+            Value::UInt(u) => serializer.serialize_u64(*u),
+            Value::Float(f) => serializer.serialize_f64(*f),
+            Value::String(s) => serializer.serialize_str(s),
+            Value::Seq(elements) => {
+                let mut seq = serializer.serialize_seq(Some(elements.len()))?;
+                for element in elements {
+                    seq.serialize_element(element)?;
+                }
+                seq.end()
+            }
+            Value::Map(entries) => {
+                let mut map = serializer.serialize_map(Some(entries.len()))?;
+                for (key, value) in entries {
+                    map.serialize_entry(key, value)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+/// Serialize any value into a [`Value`]
+///
+/// Unit values and `None` become [`Value::Null`], and enum variants are represented
+/// in the same way as in JSON: unit variants as strings, and other variants as maps with a single key.
+///
+/// # Errors
+///
+/// If the value contains a map with keys that are not scalars, or an integer that does not fit into 64 bits,
+/// [`Error::Value`] is returned.
+///
+/// # Example
+///
+/// ```
+/// # use std::collections::HashMap;
+/// # use serde_any::{to_value, Value};
+/// let mut m = HashMap::new();
+/// m.insert("port", 8080);
+///
+/// let value = to_value(&m).unwrap();
+/// assert_eq!(value.get("port"), Some(&Value::Int(8080)));
+/// ```
+///
+/// [`Value`]: enum.Value.html
+/// [`Value::Null`]: enum.Value.html#variant.Null
+/// [`Error::Value`]: ../error/enum.Error.html#variant.Value
+pub fn to_value<T>(value: &T) -> Result<Value, Error>
+where
+    T: ?Sized + Serialize,
+{
+    value.serialize(ValueSerializer).map_err(Error::from)
+}
+
+/// The serializer producing a [`Value`]
+///
+/// [`Value`]: enum.Value.html
+struct ValueSerializer;
+
+fn unsigned(u: u64) -> Value {
+    if u <= i64::MAX as u64 {
+        Value::Int(u as i64)
+    } else {
+        Value::UInt(u)
+    }
+}
+
+/// Wrap `value` in a map with the variant name as its only key
+fn variant(name: &str, value: Value) -> Value {
+    let mut map = Map::new();
+    map.insert(name.to_string(), value);
+    Value::Map(map)
+}
+
+impl Serializer for ValueSerializer {
+    type Ok = Value;
+    type Error = ValueError;
+
+    type SerializeSeq = SeqBuilder;
+    type SerializeTuple = SeqBuilder;
+    type SerializeTupleStruct = SeqBuilder;
+    type SerializeTupleVariant = SeqBuilder;
+    type SerializeMap = MapBuilder;
+    type SerializeStruct = MapBuilder;
+    type SerializeStructVariant = MapBuilder;
+
+    fn serialize_bool(self, v: bool) -> Result<Value, ValueError> {
+        Ok(Value::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value, ValueError> {
+        Ok(Value::Int(v.into()))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value, ValueError> {
+        Ok(Value::Int(v.into()))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value, ValueError> {
+        Ok(Value::Int(v.into()))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value, ValueError> {
+        Ok(Value::Int(v))
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Value, ValueError> {
+        if let Ok(i) = v.try_into() {
+            Ok(Value::Int(i))
+        } else if let Ok(u) = v.try_into() {
+            Ok(Value::UInt(u))
+        } else {
+            Err(serde_ser::Error::custom(format_args!("integer {} does not fit into 64 bits", v)))
+        }
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value, ValueError> {
+        Ok(unsigned(v.into()))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value, ValueError> {
+        Ok(unsigned(v.into()))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value, ValueError> {
+        Ok(unsigned(v.into()))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value, ValueError> {
+        Ok(unsigned(v))
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Value, ValueError> {
+        match v.try_into() {
+            Ok(u) => Ok(unsigned(u)),
+            Err(_) => Err(serde_ser::Error::custom(format_args!("integer {} does not fit into 64 bits", v))),
+        }
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value, ValueError> {
+        Ok(Value::Float(v.into()))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value, ValueError> {
+        Ok(Value::Float(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value, ValueError> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value, ValueError> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value, ValueError> {
+        Ok(Value::Seq(v.iter().map(|&b| Value::Int(b.into())).collect()))
+    }
+
+    fn serialize_none(self) -> Result<Value, ValueError> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<Value, ValueError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value, ValueError> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, ValueError> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Value, ValueError> {
+        Ok(Value::String(variant.to_string()))
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Value, ValueError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        name: &'static str,
+        value: &T,
+    ) -> Result<Value, ValueError> {
+        Ok(variant(name, value.serialize(self)?))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqBuilder, ValueError> {
+        Ok(SeqBuilder::new(None, len.unwrap_or(0)))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqBuilder, ValueError> {
+        Ok(SeqBuilder::new(None, len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<SeqBuilder, ValueError> {
+        Ok(SeqBuilder::new(None, len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SeqBuilder, ValueError> {
+        Ok(SeqBuilder::new(Some(variant), len))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<MapBuilder, ValueError> {
+        Ok(MapBuilder::new(None))
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<MapBuilder, ValueError> {
+        Ok(MapBuilder::new(None))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<MapBuilder, ValueError> {
+        Ok(MapBuilder::new(Some(variant)))
+    }
+}
+
+/// Collects the elements of a sequence, tuple or tuple variant
+struct SeqBuilder {
+    variant: Option<&'static str>,
+    elements: Vec<Value>,
+}
+
+impl SeqBuilder {
+    fn new(variant: Option<&'static str>, len: usize) -> SeqBuilder {
+        SeqBuilder {
+            variant,
+            elements: Vec::with_capacity(len),
+        }
+    }
+
+    fn push<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), ValueError> {
+        self.elements.push(value.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Value, ValueError> {
+        let seq = Value::Seq(self.elements);
+        Ok(match self.variant {
+            Some(name) => variant(name, seq),
+            None => seq,
+        })
+    }
+}
+
+impl SerializeSeq for SeqBuilder {
+    type Ok = Value;
+    type Error = ValueError;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), ValueError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, ValueError> {
+        self.finish()
+    }
+}
+
+impl SerializeTuple for SeqBuilder {
+    type Ok = Value;
+    type Error = ValueError;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), ValueError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, ValueError> {
+        self.finish()
+    }
+}
+
+impl SerializeTupleStruct for SeqBuilder {
+    type Ok = Value;
+    type Error = ValueError;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), ValueError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, ValueError> {
+        self.finish()
+    }
+}
+
+impl SerializeTupleVariant for SeqBuilder {
+    type Ok = Value;
+    type Error = ValueError;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), ValueError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, ValueError> {
+        self.finish()
+    }
+}
+
+/// Collects the entries of a map, struct or struct variant
+struct MapBuilder {
+    variant: Option<&'static str>,
+    entries: Map,
+    key: Option<String>,
+}
+
+impl MapBuilder {
+    fn new(variant: Option<&'static str>) -> MapBuilder {
+        MapBuilder {
+            variant,
+            entries: Map::new(),
+            key: None,
+        }
+    }
+
+    fn finish(self) -> Result<Value, ValueError> {
+        let map = Value::Map(self.entries);
+        Ok(match self.variant {
+            Some(name) => variant(name, map),
+            None => map,
+        })
+    }
+}
+
+/// Convert a serialized map key to a string, in the same way as keys are deserialized
+fn key_string(key: Value) -> Result<String, ValueError> {
+    match key {
+        Value::String(s) => Ok(s),
+        Value::Bool(b) => Ok(b.to_string()),
+        Value::Int(i) => Ok(i.to_string()),
+        Value::UInt(u) => Ok(u.to_string()),
+        Value::Float(f) => Ok(f.to_string()),
+        other => Err(serde_ser::Error::custom(format_args!(
+            "map keys must be scalars, found a {}",
+            other.type_name()
+        ))),
+    }
+}
+
+impl SerializeMap for MapBuilder {
+    type Ok = Value;
+    type Error = ValueError;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), ValueError> {
+        self.key = Some(key_string(key.serialize(ValueSerializer)?)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), ValueError> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| serde_ser::Error::custom("map value serialized before its key"))?;
+        self.entries.insert(key, value.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, ValueError> {
+        self.finish()
+    }
+}
+
+impl SerializeStruct for MapBuilder {
+    type Ok = Value;
+    type Error = ValueError;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<(), ValueError> {
+        self.entries.insert(key.to_string(), value.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, ValueError> {
+        self.finish()
+    }
+}
+
+impl SerializeStructVariant for MapBuilder {
+    type Ok = Value;
+    type Error = ValueError;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<(), ValueError> {
+        self.entries.insert(key.to_string(), value.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, ValueError> {
+        self.finish()
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Value, D::Error> {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("any value")
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<Value, E> {
+        Ok(Value::Bool(v))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Value, E> {
+        Ok(Value::Int(v))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Value, E> {
+        if v <= i64::MAX as u64 {
+            Ok(Value::Int(v as i64))
+        } else {
+            Ok(Value::UInt(v))
+        }
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Value, E> {
+        Ok(Value::Float(v))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Value, E> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<Value, E> {
+        Ok(Value::String(v))
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Value, E> {
+        Ok(Value::Seq(v.iter().map(|&b| Value::Int(i64::from(b))).collect()))
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        Value::deserialize(deserializer)
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        Value::deserialize(deserializer)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut elements = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+        while let Some(element) = seq.next_element()? {
+            elements.push(element);
+        }
+        Ok(Value::Seq(elements))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut entries = Map::new();
+        while let Some(key) = map.next_key_seed(KeySeed)? {
+            let value = map.next_value()?;
+            entries.insert(key, value);
+        }
+
+        // TOML datetimes are passed as a map with a single private key, and are kept as strings instead
+        if entries.len() == 1 {
+            if let Some(Value::String(_)) = entries.get(TOML_DATETIME_KEY) {
+                return Ok(entries.remove(TOML_DATETIME_KEY).unwrap());
+            }
+        }
+        Ok(Value::Map(entries))
+    }
+}
+
+/// The key of the map the TOML backends deserialize datetimes as
+const TOML_DATETIME_KEY: &str = "$__toml_private_datetime";
+
+/// Deserializes a map key of any scalar type as a string
+pub(crate) struct KeySeed;
+
+impl<'de> DeserializeSeed<'de> for KeySeed {
+    type Value = String;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<String, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for KeySeed {
+    type Value = String;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a string, number or boolean key")
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<String, E> {
+        Ok(v.to_string())
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<String, E> {
+        Ok(v.to_string())
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<String, E> {
+        Ok(v.to_string())
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<String, E> {
+        Ok(v.to_string())
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<String, E> {
+        Ok(v.to_string())
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<String, E> {
+        Ok(v)
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<String, E> {
+        Ok("null".to_string())
+    }
+}
+
+impl<'de> IntoDeserializer<'de, ValueError> for Value {
+    type Deserializer = Value;
+
+    fn into_deserializer(self) -> Value {
+        self
+    }
+}
+
+macro_rules! forward_to_value_deserializer {
+    ($($method:ident($($arg:ident: $ty:ty),*);)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value, ValueError> {
+                ValueDeserializer::new(self, Coerce::Never).$method($($arg,)* visitor)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for Value {
+    type Error = ValueError;
+
+    forward_to_value_deserializer! {
+        deserialize_any();
+        deserialize_bool();
+        deserialize_i8();
+        deserialize_i16();
+        deserialize_i32();
+        deserialize_i64();
+        deserialize_i128();
+        deserialize_u8();
+        deserialize_u16();
+        deserialize_u32();
+        deserialize_u64();
+        deserialize_u128();
+        deserialize_f32();
+        deserialize_f64();
+        deserialize_char();
+        deserialize_str();
+        deserialize_string();
+        deserialize_bytes();
+        deserialize_byte_buf();
+        deserialize_option();
+        deserialize_unit();
+        deserialize_unit_struct(name: &'static str);
+        deserialize_newtype_struct(name: &'static str);
+        deserialize_seq();
+        deserialize_tuple(len: usize);
+        deserialize_tuple_struct(name: &'static str, len: usize);
+        deserialize_map();
+        deserialize_struct(name: &'static str, fields: &'static [&'static str]);
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]);
+        deserialize_identifier();
+        deserialize_ignored_any();
+    }
+}
+
+/// Deserialize a value of type `T` from a [`Value`], converting strings where `T` expects other scalars
+///
+/// This is used for formats such as XML, where all scalars are strings.
+/// Strings are parsed if the target expects a boolean or a number,
+/// and split at commas if the target expects a sequence.
+///
+/// [`Value`]: enum.Value.html
+pub(crate) fn from_value_coerced<T>(value: Value) -> Result<T, Error>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    deserialize_tracked(ValueDeserializer::new(value, Coerce::Always))
+}
+
+/// Deserialize a value of type `T` from a [`Value`], converting only the strings at the given paths
+///
+/// This is used for configuration, where some sources such as environment variables only contain strings,
+/// while strings from other sources have to match the target type as they would when deserialized directly.
+/// Paths are written as in [`Error::Field`], and strings nested inside them are converted as well.
+///
+/// [`Value`]: enum.Value.html
+/// [`Error::Field`]: ../error/enum.Error.html#variant.Field
+pub(crate) fn from_value_coerced_at<T>(value: Value, paths: BTreeSet<String>) -> Result<T, Error>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    let coerce = if paths.contains("") {
+        Coerce::Always
+    } else {
+        Coerce::At(Rc::new(paths), String::new())
+    };
+    deserialize_tracked(ValueDeserializer::new(value, coerce))
+}
+
+/// Which strings are converted to the type expected by the visitor
+#[derive(Clone, Debug)]
+enum Coerce {
+    Never,
+    Always,
+    /// Only strings at or inside one of the paths, with the path of the current value
+    At(Rc<BTreeSet<String>>, String),
+}
+
+impl Coerce {
+    fn key(&self, key: &str) -> Coerce {
+        match self {
+            Coerce::At(paths, path) if path.is_empty() => Coerce::at(paths, key.to_string()),
+            Coerce::At(paths, path) => Coerce::at(paths, format!("{}.{}", path, key)),
+            coerce => coerce.clone(),
+        }
+    }
+
+    fn index(&self, index: usize) -> Coerce {
+        match self {
+            Coerce::At(paths, path) => Coerce::at(paths, format!("{}[{}]", path, index)),
+            coerce => coerce.clone(),
+        }
+    }
+
+    fn at(paths: &Rc<BTreeSet<String>>, path: String) -> Coerce {
+        if paths.contains(&path) {
+            Coerce::Always
+        } else {
+            Coerce::At(paths.clone(), path)
+        }
+    }
+}
+
+/// The deserializer for values, optionally converting strings to the type expected by the visitor
+struct ValueDeserializer {
+    value: Value,
+    coerce: Coerce,
+}
+
+impl ValueDeserializer {
+    fn new(value: Value, coerce: Coerce) -> ValueDeserializer {
+        ValueDeserializer { value, coerce }
+    }
+
+    /// Return the string that should be converted, if coercion is enabled
+    fn coercible(&self) -> Option<&str> {
+        match (&self.value, &self.coerce) {
+            (Value::String(s), Coerce::Always) => Some(s.trim()),
+            _ => None,
+        }
+    }
+}
+
+impl<'de> IntoDeserializer<'de, ValueError> for ValueDeserializer {
+    type Deserializer = ValueDeserializer;
+
+    fn into_deserializer(self) -> ValueDeserializer {
+        self
+    }
+}
+
+macro_rules! deserialize_coerced {
+    ($($method:ident => $visit:ident($ty:ty);)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+                if let Some(Ok(v)) = self.coercible().map(str::parse::<$ty>) {
+                    return visitor.$visit(v);
+                }
+                self.deserialize_any(visitor)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for ValueDeserializer {
+    type Error = ValueError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        let coerce = self.coerce;
+        match self.value {
+            Value::Null => visitor.visit_unit(),
+            Value::Bool(b) => visitor.visit_bool(b),
+            Value::Int(i) => visitor.visit_i64(i),
+            Value::UInt(u) => visitor.visit_u64(u),
+            Value::Float(f) => visitor.visit_f64(f),
+            Value::String(s) => visitor.visit_string(s),
+            Value::Seq(elements) => {
+                let elements = elements
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, value)| ValueDeserializer::new(value, coerce.index(index)));
+                let mut seq = SeqDeserializer::new(elements);
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            }
+            Value::Map(entries) => {
+                let entries = entries
+                    .into_iter()
+                    .map(|(key, value)| {
+                        let coerce = coerce.key(&key);
+                        (Key(key), ValueDeserializer::new(value, coerce))
+                    });
+                let mut map = MapDeserializer::new(entries);
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            }
+        }
+    }
+
+    deserialize_coerced! {
+        deserialize_bool => visit_bool(bool);
+        deserialize_i8 => visit_i64(i64);
+        deserialize_i16 => visit_i64(i64);
+        deserialize_i32 => visit_i64(i64);
+        deserialize_i64 => visit_i64(i64);
+        deserialize_u8 => visit_u64(u64);
+        deserialize_u16 => visit_u64(u64);
+        deserialize_u32 => visit_u64(u64);
+        deserialize_u64 => visit_u64(u64);
+        deserialize_f32 => visit_f64(f64);
+        deserialize_f64 => visit_f64(f64);
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        let elements: Option<Vec<Value>> = self.coercible().map(|s| {
+            s.split(',')
+                .map(str::trim)
+                .filter(|element| !element.is_empty())
+                .map(|element| Value::String(element.to_string()))
+                .collect()
+        });
+        match elements {
+            Some(elements) => ValueDeserializer::new(Value::Seq(elements), Coerce::Always).deserialize_any(visitor),
+            None => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, ValueError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        match self.value {
+            Value::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, ValueError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, ValueError> {
+        let coerce = self.coerce;
+        match self.value {
+            Value::String(variant) => visitor.visit_enum(variant.into_deserializer()),
+            Value::Map(entries) => {
+                if entries.len() != 1 {
+                    return Err(de::Error::invalid_length(entries.len(), &"a map with a single key"));
+                }
+                let (variant, value) = entries.into_iter().next().unwrap();
+                let coerce = coerce.key(&variant);
+                visitor.visit_enum(Variant {
+                    variant,
+                    value: ValueDeserializer::new(value, coerce),
+                })
+            }
+            other => Err(de::Error::invalid_type(other.unexpected(), &"a string or a map with a single key")),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        i128 u128 char str string bytes byte_buf unit unit_struct tuple_struct map struct identifier ignored_any
+    }
+}
+
+/// A map key, which is parsed if the target type expects a scalar other than a string
+struct Key(String);
+
+impl<'de> IntoDeserializer<'de, ValueError> for Key {
+    type Deserializer = Key;
+
+    fn into_deserializer(self) -> Key {
+        self
+    }
+}
+
+macro_rules! deserialize_parsed_key {
+    ($($method:ident => $visit:ident($ty:ty);)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+                match self.0.parse::<$ty>() {
+                    Ok(v) => visitor.$visit(v),
+                    Err(_) => visitor.visit_string(self.0),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for Key {
+    type Error = ValueError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        visitor.visit_string(self.0)
+    }
+
+    deserialize_parsed_key! {
+        deserialize_bool => visit_bool(bool);
+        deserialize_i8 => visit_i8(i8);
+        deserialize_i16 => visit_i16(i16);
+        deserialize_i32 => visit_i32(i32);
+        deserialize_i64 => visit_i64(i64);
+        deserialize_u8 => visit_u8(u8);
+        deserialize_u16 => visit_u16(u16);
+        deserialize_u32 => visit_u32(u32);
+        deserialize_u64 => visit_u64(u64);
+        deserialize_f32 => visit_f32(f32);
+        deserialize_f64 => visit_f64(f64);
+        deserialize_char => visit_char(char);
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, ValueError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, ValueError> {
+        visitor.visit_enum(self.0.into_deserializer())
+    }
+
+    forward_to_deserialize_any! {
+        i128 u128 str string bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+/// An enum variant represented as a map with a single key
+struct Variant {
+    variant: String,
+    value: ValueDeserializer,
+}
+
+impl<'de> EnumAccess<'de> for Variant {
+    type Error = ValueError;
+    type Variant = ValueDeserializer;
+
+    fn variant_seed<S: DeserializeSeed<'de>>(self, seed: S) -> Result<(S::Value, ValueDeserializer), ValueError> {
+        let variant = seed.deserialize(self.variant.into_deserializer())?;
+        Ok((variant, self.value))
+    }
+}
+
+impl<'de> VariantAccess<'de> for ValueDeserializer {
+    type Error = ValueError;
+
+    fn unit_variant(self) -> Result<(), ValueError> {
+        match self.value {
+            Value::Null => Ok(()),
+            other => Err(de::Error::invalid_type(other.unexpected(), &"a unit variant")),
+        }
+    }
+
+    fn newtype_variant_seed<S: DeserializeSeed<'de>>(self, seed: S) -> Result<S::Value, ValueError> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, ValueError> {
+        match self.value {
+            Value::Seq(_) => self.deserialize_any(visitor),
+            ref other => Err(de::Error::invalid_type(other.unexpected(), &"a tuple variant")),
+        }
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, ValueError> {
+        match self.value {
+            Value::Map(_) => self.deserialize_any(visitor),
+            ref other => Err(de::Error::invalid_type(other.unexpected(), &"a struct variant")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use de::from_str;
+    use format::Format;
+    use ser::to_string;
+    use std::collections::HashMap;
+
+    #[derive(Deserialize, Serialize, PartialEq, Debug)]
+    enum Shape {
+        Point,
+        Circle(f64),
+        Rect { w: u32, h: u32 },
+    }
+
+    #[derive(Deserialize, Serialize, PartialEq, Debug)]
+    struct Drawing {
+        name: Option<String>,
+        shapes: Vec<Shape>,
+        layers: HashMap<u32, String>,
+    }
+
+    #[test]
+    fn round_trip() {
+        let s = r#"{"name": null, "shapes": ["Point", {"Circle": 1.5}, {"Rect": {"w": 2, "h": 3}}],
+                    "layers": {"1": "background"}}"#;
+        let value: Value = from_str(s, Format::Json).unwrap();
+        assert_eq!(value.get("name"), Some(&Value::Null));
+
+        let drawing: Drawing = from_value(value.clone()).unwrap();
+        assert_eq!(drawing.shapes[1], Shape::Circle(1.5));
+        assert_eq!(drawing.layers[&1], "background");
+
+        let json = to_string(&value, Format::Json).unwrap();
+        assert_eq!(from_str::<Drawing>(&json, Format::Json).unwrap(), drawing);
+    }
+
+    #[test]
+    fn scalars() {
+        let value: Value = from_str("a: 1\nb: -2\nc: 18446744073709551615\nd: 1.5\ne: true\n1: x\n", Format::Yaml).unwrap();
+        assert_eq!(value.get("a"), Some(&Value::Int(1)));
+        assert_eq!(value.get("b"), Some(&Value::Int(-2)));
+        assert_eq!(value.get("c"), Some(&Value::UInt(u64::MAX)));
+        assert_eq!(value.get("d"), Some(&Value::Float(1.5)));
+        assert_eq!(value.get("e"), Some(&Value::Bool(true)));
+        assert_eq!(value.get("1"), Some(&Value::String("x".to_string())));
+    }
+
+    #[test]
+    fn type_errors_have_paths() {
+        let value: Value = from_str(r#"{"name": "a", "shapes": [{"Rect": {"w": "wide", "h": 3}}], "layers": {}}"#, Format::Json).unwrap();
+        let error = from_value::<Drawing>(value).unwrap_err();
+        assert_eq!(error.path(), Some("shapes[0].Rect.w"));
+        assert_matches!(error, Error::Field { .. });
+    }
+}